@@ -1,41 +1,91 @@
 #![allow(unused)]
 #![allow(dead_code)]
 
-use chrono::{DateTime, Local, NaiveDate};
-use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::rc::Rc;
+use std::sync::Arc;
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize, Serializer};
+use crate::error::{ConversionError, Error, Result};
 use crate::timestamp::Timestamp;
 use crate::value::Value;
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
-pub struct Args(Vec<Value>);
+/// Serialized as the list of values, which fails when an argument didn't convert.
+#[derive(Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(transparent)]
+pub struct Args {
+    values: Vec<Value>,
+    /// First argument that didn't convert, reported when the arguments are bound.
+    #[serde(skip)]
+    error: Option<ConversionError>
+}
+
+impl Serialize for Args {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match &self.error {
+            Some(e) => Err(serde::ser::Error::custom(e)),
+            None => self.values.serialize(serializer)
+        }
+    }
+}
 
 impl Args {
     pub fn new() -> Self {
-        Self(Vec::new())
+        Self::default()
     }
     pub fn with_capacity(capacity: usize) -> Self {
-        Self(Vec::with_capacity(capacity))
+        Self { values: Vec::with_capacity(capacity), error: None }
     }
+    /// Add an argument. One that doesn't convert (e.g. a `u64` past `i64::MAX`) is
+    /// bound as NULL and its error returned by the statement, see `error`.
     pub fn arg<T:ValueConvertible>(mut self, data: T) -> Self {
-        self.0.push(data.to_value());
+        match convert(&data) {
+            Ok(value) => self.values.push(value),
+            Err(mut e) => {
+                e.context = Some(format!("argument {}", self.values.len() + 1).into());
+                self.error.get_or_insert(e);
+                self.values.push(Value::Null);
+            }
+        }
         self
     }
+    /// First argument that didn't convert.
+    pub fn error(&self) -> Option<&ConversionError> {
+        self.error.as_ref()
+    }
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.values.is_empty()
     }
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.values.len()
     }
     pub fn get(&self, index: usize) -> Option<&Value> {
-        self.0.get(index)
+        self.values.get(index)
     }
     pub fn iter(&self) -> std::slice::Iter<'_, Value> {
-        self.0.iter()
+        self.values.iter()
     }
 }
 
+impl From<Vec<Value>> for Args {
+    fn from(values: Vec<Value>) -> Self {
+        Self { values, error: None }
+    }
+}
+
+/// `try_to_value` with its error as a `ConversionError`.
+pub(crate) fn convert<T: ValueConvertible + ?Sized>(data: &T) -> std::result::Result<Value, ConversionError> {
+    data.try_to_value().map_err(|e| match e {
+        Error::Conversion(e) => *e,
+        e => ConversionError::new("Value", "argument", "").reason(&e.to_string())
+    })
+}
+
+/// Types usable as query arguments. Implemented for references, `Option`, `Box`, `Rc`,
+/// `Arc` and `Cow` of any implementing type, so a newtype needs a single impl.
 pub trait ValueConvertible {
-    fn to_value(&self) -> Value;
+    /// Value to bind, an error for values SQLite can't hold (e.g. a `u64` past `i64::MAX`).
+    fn try_to_value(&self) -> Result<Value>;
 }
 
 /********************************************************************
@@ -47,217 +97,374 @@ pub trait ValueConvertible {
 //------- Numbers -----------------------------------------
 
 impl ValueConvertible for i8 {
-    fn to_value(&self) -> Value {
-        (*self as i64).into()
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self as i64).into())
     }
 }
 impl ValueConvertible for u8 {
-    fn to_value(&self) -> Value {
-        (*self as i64).into()
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self as i64).into())
     }
 }
 impl ValueConvertible for i16 {
-    fn to_value(&self) -> Value {
-        (*self as i64).into()
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self as i64).into())
     }
 }
 impl ValueConvertible for u16 {
-    fn to_value(&self) -> Value {
-        (*self as i64).into()
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self as i64).into())
     }
 }
 impl ValueConvertible for i32 {
-    fn to_value(&self) -> Value {
-        (*self as i64).into()
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self as i64).into())
     }
 }
 impl ValueConvertible for u32 {
-    fn to_value(&self) -> Value {
-        (*self as i64).into()
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self as i64).into())
     }
 }
 impl ValueConvertible for i64 {
-    fn to_value(&self) -> Value {
-        (*self).into()
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self).into())
     }
 }
 impl ValueConvertible for f32 {
-    fn to_value(&self) -> Value {
-        (*self as f64).into()
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self as f64).into())
     }
 }
 impl ValueConvertible for f64 {
-    fn to_value(&self) -> Value {
-        (*self).into()
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self).into())
     }
 }
 
-//------- Containers  -------------------------------------
+//------- Booleans, characters and wide integers ---------
 
-impl ValueConvertible for &str {
-    fn to_value(&self) -> Value {
-        (*self).into()
+/// Stored as 0/1.
+impl ValueConvertible for bool {
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self as i64).into())
     }
 }
-impl ValueConvertible for &String {
-    fn to_value(&self) -> Value {
-        (*self).clone().into()
+/// Stored as single-character text.
+impl ValueConvertible for char {
+    fn try_to_value(&self) -> Result<Value> {
+        Ok(Value::Text(self.to_string()))
     }
 }
-impl ValueConvertible for &[u8] {
-    fn to_value(&self) -> Value {
-        (*self).into()
+
+/// Integers wider than SQLite's, for `AsText` and `AsBlob`.
+pub trait WideInteger: Copy + TryFrom<i64> + std::str::FromStr {
+    /// Type name for conversion errors.
+    const NAME: &'static str;
+    /// Length of `to_blob`.
+    const BLOB_LEN: usize;
+    /// Decimal text.
+    fn to_text(&self) -> String;
+    /// Big-endian bytes, signed types with the sign bit flipped so blobs sort like the numbers.
+    fn to_blob(&self) -> Vec<u8>;
+    /// Read `to_blob`, None for another length or a value out of range.
+    fn from_blob(bytes: &[u8]) -> Option<Self>;
+}
+
+impl WideInteger for u64 {
+    const NAME: &'static str = "u64";
+    const BLOB_LEN: usize = 8;
+    fn to_text(&self) -> String {
+        self.to_string()
+    }
+    fn to_blob(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+    fn from_blob(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok().map(u64::from_be_bytes)
+    }
+}
+impl WideInteger for usize {
+    const NAME: &'static str = "usize";
+    const BLOB_LEN: usize = 8;
+    fn to_text(&self) -> String {
+        self.to_string()
+    }
+    fn to_blob(&self) -> Vec<u8> {
+        (*self as u64).to_blob()
+    }
+    fn from_blob(bytes: &[u8]) -> Option<Self> {
+        u64::from_blob(bytes).and_then(|n| usize::try_from(n).ok())
     }
 }
-impl ValueConvertible for &Vec<u8> {
-    fn to_value(&self) -> Value {
-        (*self).clone().into()
+impl WideInteger for i128 {
+    const NAME: &'static str = "i128";
+    const BLOB_LEN: usize = 16;
+    fn to_text(&self) -> String {
+        self.to_string()
+    }
+    fn to_blob(&self) -> Vec<u8> {
+        ((*self as u128) ^ (1 << 127)).to_be_bytes().to_vec()
+    }
+    fn from_blob(bytes: &[u8]) -> Option<Self> {
+        u128::from_blob(bytes).map(|n| (n ^ (1 << 127)) as i128)
+    }
+}
+impl WideInteger for u128 {
+    const NAME: &'static str = "u128";
+    const BLOB_LEN: usize = 16;
+    fn to_text(&self) -> String {
+        self.to_string()
+    }
+    fn to_blob(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+    fn from_blob(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok().map(u128::from_be_bytes)
     }
 }
 
-//------- Optional numbers --------------------------------
+fn out_of_range<T: WideInteger>(expected: &str, v: T) -> Error {
+    ConversionError::new(expected, "integer", &v.to_text()).reason("out of range for SQLite integers").into()
+}
 
-impl ValueConvertible for &Option<i8> {
-    fn to_value(&self) -> Value {
-        match self {
-            Some(v) => Value::from(*v as i64),
-            None => Value::from(()),
-        }
+fn integer_try_value<T: WideInteger>(v: T) -> Result<Value>
+    where i64: TryFrom<T>
+{
+    i64::try_from(v).map(Value::I64).map_err(|_| out_of_range("i64", v))
+}
+
+/// Stored as an integer, fails outside the `i64` range.
+/// Wrap in `AsText` or `AsBlob` to store any value.
+impl ValueConvertible for u64 {
+    fn try_to_value(&self) -> Result<Value> {
+        integer_try_value(*self)
     }
 }
-impl ValueConvertible for &Option<u8> {
-    fn to_value(&self) -> Value {
-        match self {
-            Some(v) => Value::from(*v as i64),
-            None => Value::from(()),
-        }
+/// Like `u64`.
+impl ValueConvertible for usize {
+    fn try_to_value(&self) -> Result<Value> {
+        integer_try_value(*self)
     }
 }
-impl ValueConvertible for &Option<i16> {
-    fn to_value(&self) -> Value {
-        match self {
-            Some(v) => Value::from(*v as i64),
-            None => Value::from(()),
-        }
+/// Like `u64`.
+impl ValueConvertible for i128 {
+    fn try_to_value(&self) -> Result<Value> {
+        integer_try_value(*self)
     }
 }
-impl ValueConvertible for &Option<u16> {
-    fn to_value(&self) -> Value {
-        match self {
-            Some(v) => Value::from(*v as i64),
-            None => Value::from(()),
-        }   
+/// Like `u64`.
+impl ValueConvertible for u128 {
+    fn try_to_value(&self) -> Result<Value> {
+        integer_try_value(*self)
     }
 }
-impl ValueConvertible for &Option<i32> {
-    fn to_value(&self) -> Value {
-        match self {
-            Some(v) => Value::from(*v as i64),
-            None => Value::from(()),
-        }
+
+/// Wide integer stored as decimal text. Use a TEXT or BLOB column: numeric columns turn
+/// text past `i64` into a lossy real.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AsText<T>(pub T);
+
+/// Wide integer stored as a blob, see `WideInteger::to_blob`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AsBlob<T>(pub T);
+
+impl<T: WideInteger> ValueConvertible for AsText<T> {
+    fn try_to_value(&self) -> Result<Value> {
+        Ok(Value::Text(self.0.to_text()))
     }
 }
-impl ValueConvertible for &Option<u32> {
-    fn to_value(&self) -> Value {
-        match self {
-            Some(v) => Value::from(*v as i64),
-            None => Value::from(()),       
-        }
+impl<T: WideInteger> ValueConvertible for AsBlob<T> {
+    fn try_to_value(&self) -> Result<Value> {
+        Ok(Value::Blob(self.0.to_blob()))
     }
 }
-impl ValueConvertible for &Option<i64> {
-    fn to_value(&self) -> Value {
-        match self {
-            Some(v) => Value::from(*v),
-            None => Value::from(()),
-        }
+
+//------- Text and blobs ---------------------------------
+
+impl ValueConvertible for str {
+    fn try_to_value(&self) -> Result<Value> {
+        Ok(self.into())
     }
 }
-impl ValueConvertible for &Option<f32> {
-    fn to_value(&self) -> Value {
-        match self {
-            Some(v) => Value::from(*v as f64),
-            None => Value::from(()),       
-        }
+impl ValueConvertible for String {
+    fn try_to_value(&self) -> Result<Value> {
+        Ok(self.as_str().into())
     }
 }
-impl ValueConvertible for &Option<f64> {
-    fn to_value(&self) -> Value {
-        match self {
-            Some(v) => Value::from(*v),
-            None => Value::from(()),
-        }
+impl ValueConvertible for [u8] {
+    fn try_to_value(&self) -> Result<Value> {
+        Ok(self.into())
+    }
+}
+impl ValueConvertible for Vec<u8> {
+    fn try_to_value(&self) -> Result<Value> {
+        Ok(self.as_slice().into())
     }
 }
 
-//------- Optional containers  ----------------------------
+//------- Options, references and smart pointers ----------
 
-impl ValueConvertible for &Option<String> {
-    fn to_value(&self) -> Value {
+/// None is NULL.
+impl<T: ValueConvertible> ValueConvertible for Option<T> {
+    fn try_to_value(&self) -> Result<Value> {
         match self {
-            Some(v) => Value::from(v.clone()),
-            None => Value::from(()),
+            Some(v) => v.try_to_value(),
+            None => Ok(Value::Null),
         }
     }
 }
-impl ValueConvertible for &Option<Vec<u8>> {
-    fn to_value(&self) -> Value {
-        match self {
-            Some(v) => Value::from(v.clone()),
-            None => Value::from(()),
-        }
+impl<T: ValueConvertible + ?Sized> ValueConvertible for &T {
+    fn try_to_value(&self) -> Result<Value> {
+        (**self).try_to_value()
+    }
+}
+impl<T: ValueConvertible + ?Sized> ValueConvertible for &mut T {
+    fn try_to_value(&self) -> Result<Value> {
+        (**self).try_to_value()
+    }
+}
+impl<T: ValueConvertible + ?Sized> ValueConvertible for Box<T> {
+    fn try_to_value(&self) -> Result<Value> {
+        (**self).try_to_value()
+    }
+}
+impl<T: ValueConvertible + ?Sized> ValueConvertible for Rc<T> {
+    fn try_to_value(&self) -> Result<Value> {
+        (**self).try_to_value()
+    }
+}
+impl<T: ValueConvertible + ?Sized> ValueConvertible for Arc<T> {
+    fn try_to_value(&self) -> Result<Value> {
+        (**self).try_to_value()
+    }
+}
+/// `Cow<str>` and `Cow<[u8]>`.
+impl<T: ValueConvertible + ToOwned + ?Sized> ValueConvertible for Cow<'_, T> {
+    fn try_to_value(&self) -> Result<Value> {
+        (**self).try_to_value()
     }
 }
 
-//------- NaiveDate ---------------------------------------
+//------- Dates and times --------------------------------
 
 impl ValueConvertible for NaiveDate {
-    fn to_value(&self) -> Value {
-        (*self).into()
-    } 
-}
-impl ValueConvertible for &Option<NaiveDate> {
-    fn to_value(&self) -> Value {
-        match self {
-            Some(v) => Value::from(*v),
-            None => Value::from(()),
-        }
-    }  
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self).into())
+    }
 }
 
 impl ValueConvertible for DateTime<Local> {
-    fn to_value(&self) -> Value {
-        (*self).into()
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self).into())
     }
 }
-impl ValueConvertible for &Option<DateTime<Local>> {
-    fn to_value(&self) -> Value {
-        match self {
-            Some(v) => Value::from(*v),
-            None => Value::from(()),
-        }
+
+impl ValueConvertible for NaiveDateTime {
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self).into())
+    }
+}
+impl ValueConvertible for NaiveTime {
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self).into())
+    }
+}
+impl ValueConvertible for DateTime<Utc> {
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self).into())
+    }
+}
+impl ValueConvertible for DateTime<FixedOffset> {
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self).into())
+    }
+}
+impl ValueConvertible for Duration {
+    fn try_to_value(&self) -> Result<Value> {
+        Ok((*self).into())
     }
 }
 
 //------- Timestamps --------------------------------------
 
 impl ValueConvertible for Timestamp {
-    fn to_value(&self) -> Value {
-        self.value().into()
+    fn try_to_value(&self) -> Result<Value> {
+        Ok(self.value().into())
     }
 }
-impl ValueConvertible for &Option<Timestamp> {
-    fn to_value(&self) -> Value {
-        match self {
-            Some(v) => Value::from(v.value()),
-            None => Value::from(()),
-        }
+
+//------- Value -------------------------------------------
+
+impl ValueConvertible for Value {
+    fn try_to_value(&self) -> Result<Value> {
+        Ok(self.clone())
     }
 }
 
 //------- Null --------------------------------------------
 impl ValueConvertible for () {
-    fn to_value(&self) -> Value {
-        Value::Null
+    fn try_to_value(&self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A downstream newtype, one impl makes every wrapper work.
+    struct UserId(i64);
+
+    impl ValueConvertible for UserId {
+        fn try_to_value(&self) -> Result<Value> {
+            Ok(Value::I64(self.0))
+        }
+    }
+
+    #[test]
+    fn blanket_impls_test() {
+        let id = UserId(7);
+        let args = Args::new()
+            .arg(UserId(1))
+            .arg(&id)
+            .arg(Some(UserId(2)))
+            .arg(&None::<UserId>)
+            .arg(Box::new(UserId(3)))
+            .arg(Rc::new(UserId(4)))
+            .arg(Arc::new(String::from("arc")))
+            .arg(String::from("owned"))
+            .arg(Cow::Borrowed("cow"))
+            .arg(Cow::<[u8]>::Owned(vec![1, 2]))
+            .arg(vec![3u8])
+            .arg(Box::<str>::from("boxed"));
+        let expected = [
+            Value::I64(1), Value::I64(7), Value::I64(2), Value::Null, Value::I64(3), Value::I64(4),
+            Value::from("arc"), Value::from("owned"), Value::from("cow"), Value::Blob(vec![1, 2]),
+            Value::Blob(vec![3]), Value::from("boxed"),
+        ];
+        assert_eq!(args.iter().cloned().collect::<Vec<_>>(), expected);
+        assert!(args.error().is_none());
+
+        // fallible conversions go through the wrappers
+        assert!(Args::new().arg(Some(u64::MAX)).error().is_some());
+        assert!(Args::new().arg(Box::new(Some(u64::MAX))).error().is_some());
+        assert!(Args::new().arg(None::<u64>).error().is_none());
+    }
+
+    #[test]
+    fn borrowed_test() {
+        let data = vec![1u8, 2];
+        let borrowed = &data;
+        let nested = &borrowed;
+        let id = &UserId(5);
+        let mut text = String::from("text");
+        let args = Args::new().arg(borrowed).arg(nested).arg(id).arg(&mut text);
+        assert_eq!(args.iter().cloned().collect::<Vec<_>>(), [
+            Value::Blob(vec![1, 2]), Value::Blob(vec![1, 2]), Value::I64(5), Value::from(text.as_str()),
+        ]);
+
+        let wide = &u64::MAX;
+        assert!(Args::new().arg(wide).error().is_some());
     }
 }
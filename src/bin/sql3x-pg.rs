@@ -0,0 +1,61 @@
+use std::fs;
+use std::process::ExitCode;
+use sql3x::pgwire::PgServer;
+use sql3x::policy::Policy;
+
+const HELP: &str = "\
+Usage: sql3x-pg [OPTIONS] DATABASE [ADDRESS]
+
+Serves DATABASE to Postgres clients (psql, BI tools) on a loopback
+ADDRESS, 127.0.0.1:5432 by default. Every client gets its own connection.
+
+Options:
+  -r            reject statements that change the database
+  -a FILE       accept only the statements in FILE, one per line
+  -h            print this help
+";
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let mut policy = Policy::default();
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-r" => policy.read_only = true,
+            "-a" => match fs::read_to_string(args.next().unwrap_or_default()) {
+                Ok(text) => {
+                    let lines = text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with("--"));
+                    policy.allow = Some(lines.map(String::from).collect());
+                },
+                Err(e) => {
+                    eprintln!("Error: cannot read allowlist: {e}");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-h" | "--help" => {
+                print!("{HELP}");
+                return ExitCode::SUCCESS;
+            },
+            _ => positional.push(arg)
+        }
+    }
+    let (path, address) = match positional.as_slice() {
+        [path] => (path.as_str(), "127.0.0.1:5432"),
+        [path, address] => (path.as_str(), address.as_str()),
+        _ => {
+            eprint!("{HELP}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if !fs::exists(path).unwrap_or(false) {
+        eprintln!("Error: database {path} doesn't exist");
+        return ExitCode::FAILURE;
+    }
+
+    eprintln!("serving {path} on {address}");
+    if let Err(e) = PgServer::new(path, policy).serve(address) {
+        eprintln!("Error: {}", e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
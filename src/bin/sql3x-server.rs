@@ -0,0 +1,59 @@
+use std::fs;
+use std::process::ExitCode;
+use sql3x::prelude::*;
+use sql3x::policy::Policy;
+use sql3x::remote::Server;
+
+const HELP: &str = "\
+Usage: sql3x-server [OPTIONS] DATABASE ADDRESS
+
+Serves DATABASE to sql3x clients with newline-delimited JSON requests.
+ADDRESS is a Unix socket path (unix:/path) or a loopback host:port.
+
+Options:
+  -r            reject statements that change the database
+  -a FILE       accept only the statements in FILE, one per line
+  -h            print this help
+";
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let mut policy = Policy::default();
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-r" => policy.read_only = true,
+            "-a" => match fs::read_to_string(args.next().unwrap_or_default()) {
+                Ok(text) => {
+                    let lines = text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with("--"));
+                    policy.allow = Some(lines.map(String::from).collect());
+                },
+                Err(e) => {
+                    eprintln!("Error: cannot read allowlist: {e}");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-h" | "--help" => {
+                print!("{HELP}");
+                return ExitCode::SUCCESS;
+            },
+            _ => positional.push(arg)
+        }
+    }
+    let [path, address] = positional.as_slice() else {
+        eprint!("{HELP}");
+        return ExitCode::FAILURE;
+    };
+
+    let mut sq = SQLite::new().dbf(path);
+    if let Err(e) = sq.open(policy.read_only) {
+        eprintln!("Error: cannot open {path}: {}", e);
+        return ExitCode::FAILURE;
+    }
+    eprintln!("serving {path} on {address}");
+    if let Err(e) = Server::new(sq, policy).serve(address) {
+        eprintln!("Error: {}", e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
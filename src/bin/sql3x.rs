@@ -0,0 +1,481 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Instant;
+use sql3x::csv::CsvOptions;
+use sql3x::dump::DumpOptions;
+use sql3x::pretty::{TableOptions, TableStyle};
+use sql3x::prelude::*;
+
+const HELP: &str = "\
+Usage: sql3x [OPTIONS] [DATABASE] [SCRIPT]
+
+Opens DATABASE (in-memory when omitted) and runs SCRIPT, the -c command
+or an interactive shell.
+
+Options:
+  -c SQL        run SQL and exit
+  -m MODE       output mode: table, markdown, html, csv, json or line
+  -a VALUE      bind VALUE to the next ? placeholder (repeatable)
+  -t            print run time of every statement
+  -r            open the database read-only
+  -h            print this help
+";
+
+const DOT_HELP: &str = "\
+.dump [TABLE]             print the database as SQL
+.exit | .quit             leave the shell
+.export FILE QUERY        write the result of QUERY as CSV to FILE
+.help                     print this help
+.history                  print statements entered, earlier sessions included
+.import FILE TABLE        read CSV FILE into TABLE (created when missing)
+.mode [MODE]              show or set output mode: table, markdown, html, csv, json or line
+.param                    list bound parameters
+.param add VALUE          bind VALUE to the next ? placeholder
+.param clear              remove all parameters
+.read FILE                run statements from FILE
+.schema [TABLE]           print CREATE statements
+.tables                   list tables and views
+.timer on|off             print run time of every statement
+";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    Table,
+    Markdown,
+    Html,
+    Csv,
+    Json,
+    Line,
+}
+
+impl Mode {
+    fn parse(text: &str) -> Option<Mode> {
+        match text {
+            "table" => Some(Mode::Table),
+            "markdown" => Some(Mode::Markdown),
+            "html" => Some(Mode::Html),
+            "csv" => Some(Mode::Csv),
+            "json" => Some(Mode::Json),
+            "line" => Some(Mode::Line),
+            _ => None
+        }
+    }
+}
+
+/// Parameter text as a value: integers, reals and NULL are recognized, the rest is text.
+fn parse_value(text: &str) -> Value {
+    if text.eq_ignore_ascii_case("null") {
+        return Value::Null;
+    }
+    if let Ok(v) = text.parse::<i64>() {
+        return Value::I64(v);
+    }
+    if let Ok(v) = text.parse::<f64>() {
+        return Value::F64(v);
+    }
+    Value::from(text)
+}
+
+/// Split a script into complete statements, semicolons in strings and triggers are kept.
+fn statements(script: &str) -> (Vec<String>, String) {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    for piece in script.split_inclusive(';') {
+        current.push_str(piece);
+        if SQLite::is_complete(&current) {
+            result.push(current.trim().to_string());
+            current.clear();
+        }
+    }
+    (result, current)
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::I64(v) => v.to_string(),
+        Value::F64(v) => v.to_string(),
+        Value::Text(v) => v.clone(),
+        Value::Blob(_) => value.to_sql_literal(),
+    }
+}
+
+/// Shell state, results and dot-command output go to `out`.
+struct Shell<W: Write> {
+    sq: SQLite,
+    out: W,
+    mode: Mode,
+    timer: bool,
+    params: Vec<Value>,
+    history: Vec<String>,
+    history_file: Option<PathBuf>,
+}
+
+impl<W: Write> Shell<W> {
+    fn run_script(&mut self, script: &str) -> Result<()> {
+        let (statements, rest) = statements(script);
+        for statement in statements {
+            self.run_statement(&statement)?;
+        }
+        if !rest.trim().is_empty() {
+            self.run_statement(rest.trim())?;
+        }
+        Ok(())
+    }
+
+    fn run_statement(&mut self, sql: &str) -> Result<()> {
+        // parameters go to the placeholders only, a '?' in a string literal isn't one
+        let query = match self.sq.prepare(sql)?.parameter_count() {
+            0 => Query::new(sql),
+            count => Query::with_args(sql, Args::from(self.params.iter().take(count).cloned().collect::<Vec<_>>()))
+        };
+        let started = Instant::now();
+        let style = match self.mode {
+            Mode::Table => Some(TableStyle::Text),
+            Mode::Markdown => Some(TableStyle::Markdown),
+            Mode::Html => Some(TableStyle::Html),
+            _ => None
+        };
+        if let Some(style) = style {
+            self.sq.render(query, &mut self.out, &TableOptions { style, ..Default::default() })?;
+            return self.print_timer(started);
+        }
+        if self.mode == Mode::Csv {
+            self.sq.export_csv(query, &mut self.out, &CsvOptions::default())?;
+            return self.print_timer(started);
+        }
+        let mut started_json = false;
+        let out = &mut self.out;
+        let mode = self.mode;
+        let mut count = 0;
+        self.sq.select_each(query, |names, values| {
+            match mode {
+                Mode::Json => {
+                    if !started_json {
+                        started_json = true;
+                        write!(out, "[")?;
+                    }
+                    let fields = names
+                        .iter()
+                        .zip(&values)
+                        .map(|(name, value)| format!("{}:{}", serde_json::Value::from(name.as_str()), value.to_json_value()))
+                        .collect::<Vec<_>>();
+                    write!(out, "{}\n{{{}}}", if count == 0 { "" } else { "," }, fields.join(","))?;
+                },
+                // line mode, the other modes returned above
+                _ => {
+                    let width = names.iter().map(|n| n.chars().count()).max().unwrap_or(0);
+                    if count > 0 {
+                        writeln!(out)?;
+                    }
+                    for (name, value) in names.iter().zip(&values) {
+                        writeln!(out, "{name:>width$} = {}", cell(value))?;
+                    }
+                }
+            }
+            count += 1;
+            Ok(())
+        })?;
+        if started_json {
+            writeln!(out, "\n]")?;
+        }
+        self.print_timer(started)
+    }
+
+    fn print_timer(&mut self, started: Instant) -> Result<()> {
+        if self.timer {
+            writeln!(self.out, "Run Time: {:.6}s", started.elapsed().as_secs_f64())?;
+        }
+        Ok(())
+    }
+
+    fn run_dot_command(&mut self, line: &str) -> Result<bool> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let out = &mut self.out;
+        match words.as_slice() {
+            [".exit"] | [".quit"] => return Ok(false),
+            [".help"] => write!(out, "{DOT_HELP}")?,
+            [".history"] => {
+                for (idx, entry) in self.history.iter().enumerate() {
+                    writeln!(out, "{:5}  {entry}", idx + 1)?;
+                }
+            },
+            [".tables"] => {
+                let schema = self.sq.schema()?;
+                let names = schema.tables.iter().map(|t| t.name.as_str()).chain(schema.views.iter().map(|v| v.name.as_str()));
+                for name in names {
+                    writeln!(out, "{name}")?;
+                }
+            },
+            [".schema", rest @ ..] => {
+                let schema = self.sq.schema()?;
+                let wanted = |name: &str| rest.is_empty() || rest.iter().any(|r| r.eq_ignore_ascii_case(name));
+                for table in schema.tables.iter().filter(|t| wanted(&t.name)) {
+                    let indexes = table.indexes.iter().filter_map(|i| i.sql.as_ref());
+                    for sql in table.sql.iter().chain(indexes) {
+                        writeln!(out, "{sql};")?;
+                    }
+                }
+                for sql in schema.views.iter().filter(|v| wanted(&v.name)).filter_map(|v| v.sql.as_ref()) {
+                    writeln!(out, "{sql};")?;
+                }
+                for sql in schema.triggers.iter().filter(|t| wanted(&t.table)).filter_map(|t| t.sql.as_ref()) {
+                    writeln!(out, "{sql};")?;
+                }
+            },
+            [".dump", tables @ ..] => {
+                let options = DumpOptions {
+                    tables: (!tables.is_empty()).then(|| tables.iter().map(|t| t.to_string()).collect()),
+                    ..Default::default()
+                };
+                self.sq.dump(&mut *out, &options)?;
+            },
+            [".import", file, table] => {
+                let options = CsvOptions { infer_types: true, create_table: true, ..Default::default() };
+                let rows = self.sq.import_csv(File::open(file)?, table, &options)?;
+                writeln!(out, "{rows} row(s) imported")?;
+            },
+            [".export", file, _, ..] => {
+                let sql = line.splitn(3, char::is_whitespace).nth(2).unwrap_or_default().trim();
+                let rows = self.sq.export_csv(Query::new(sql), File::create(file)?, &CsvOptions::default())?;
+                writeln!(out, "{rows} row(s) exported")?;
+            },
+            [".mode"] => writeln!(out, "{:?}", self.mode)?,
+            [".mode", mode] => match Mode::parse(mode) {
+                Some(mode) => self.mode = mode,
+                None => return Err("unknown mode, use: table, markdown, html, csv, json or line".into())
+            },
+            [".param"] | [".param", "list"] => {
+                for (idx, value) in self.params.iter().enumerate() {
+                    writeln!(out, "?{} = {}", idx + 1, value.to_sql_literal())?;
+                }
+            },
+            [".param", "add", ..] => {
+                let value = line.splitn(3, char::is_whitespace).nth(2).unwrap_or_default().trim();
+                self.params.push(parse_value(value));
+            },
+            [".param", "clear"] => self.params.clear(),
+            [".read", file] => {
+                let script = fs::read_to_string(file)?;
+                self.run_script(&script)?;
+            },
+            [".timer", "on"] => self.timer = true,
+            [".timer", "off"] => self.timer = false,
+            _ => return Err("unknown or invalid command, enter .help for help".into())
+        }
+        Ok(true)
+    }
+
+    /// Start the history with the last entries of the history file and append to it from now on.
+    fn load_history(&mut self, path: PathBuf) {
+        const HISTORY_SIZE: usize = 1000;
+        if let Ok(text) = fs::read_to_string(&path) {
+            let entries = text.lines().filter(|l| !l.trim().is_empty()).collect::<Vec<_>>();
+            let start = entries.len().saturating_sub(HISTORY_SIZE);
+            self.history = entries[start..].iter().map(|l| l.to_string()).collect();
+        }
+        self.history_file = Some(path);
+    }
+
+    fn remember(&mut self, entry: &str) {
+        self.history.push(entry.to_string());
+        if let Some(path) = &self.history_file
+            && let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+            let _ = writeln!(file, "{}", entry.replace('\n', " "));
+        }
+    }
+
+    fn repl(&mut self) {
+        let interactive = io::stdin().is_terminal();
+        if interactive {
+            println!("sql3x (SQLite {}), enter .help for help", SQLite::version());
+        }
+        let mut buffer = String::new();
+        let mut lines = io::stdin().lock().lines();
+        loop {
+            if interactive {
+                print!("{}", if buffer.is_empty() { "sql3x> " } else { "   ...> " });
+                let _ = io::stdout().flush();
+            }
+            let Some(Ok(line)) = lines.next() else {
+                break;
+            };
+            if buffer.is_empty() && line.trim_start().starts_with('.') {
+                self.remember(line.trim());
+                match self.run_dot_command(line.trim()) {
+                    Ok(true) => continue,
+                    Ok(false) => break,
+                    Err(e) => eprintln!("Error: {}", e)
+                }
+                continue;
+            }
+            buffer.push_str(&line);
+            buffer.push('\n');
+            if SQLite::is_complete(&buffer) {
+                let script = std::mem::take(&mut buffer);
+                self.remember(script.trim());
+                if let Err(e) = self.run_script(&script) {
+                    eprintln!("Error: {}", e);
+                }
+            }
+        }
+    }
+}
+
+fn open(path: &str, read_only: bool) -> Result<SQLite> {
+    let mut sq = SQLite::new().dbf(path);
+    if path == ":memory:" || (!read_only && !fs::exists(path).unwrap_or(false)) {
+        return sq.create(false, |_| Ok(()));
+    }
+    sq.open(read_only)?;
+    Ok(sq)
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let mut command = None;
+    let mut mode = Mode::Table;
+    let mut params = Vec::new();
+    let mut timer = false;
+    let mut read_only = false;
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" => command = args.next(),
+            "-m" => match args.next().as_deref().and_then(Mode::parse) {
+                Some(m) => mode = m,
+                None => {
+                    eprintln!("Error: unknown mode, use: table, markdown, html, csv, json or line");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-a" => params.push(parse_value(&args.next().unwrap_or_default())),
+            "-t" => timer = true,
+            "-r" => read_only = true,
+            "-h" | "--help" => {
+                print!("{HELP}");
+                return ExitCode::SUCCESS;
+            },
+            _ => positional.push(arg)
+        }
+    }
+
+    let path = positional.first().cloned().unwrap_or(":memory:".to_string());
+    let sq = match open(&path, read_only) {
+        Ok(sq) => sq,
+        Err(e) => {
+            eprintln!("Error: cannot open {path}: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let history_file = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".sql3x_history"));
+    let mut shell = Shell { sq, out: io::stdout(), mode, timer, params, history: Vec::new(), history_file: None };
+
+    let script = match (command, positional.get(1)) {
+        (Some(command), _) => Some(Ok(command)),
+        (None, Some(file)) => Some(fs::read_to_string(file)),
+        (None, None) => None
+    };
+    match script {
+        Some(Ok(script)) => {
+            let result = match script.trim_start().starts_with('.') {
+                true => shell.run_dot_command(script.trim()).map(|_| ()),
+                false => shell.run_script(&script)
+            };
+            if let Err(e) = result {
+                eprintln!("Error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        Some(Err(e)) => {
+            eprintln!("Error: {e}");
+            return ExitCode::FAILURE;
+        },
+        None => {
+            if let Some(path) = history_file {
+                shell.load_history(path);
+            }
+            shell.repl();
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell() -> Shell<Vec<u8>> {
+        let sq = SQLite::new().create(true, |sq| sq.exec_command("CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT)")).unwrap();
+        Shell { sq, out: Vec::new(), mode: Mode::Csv, timer: false, params: Vec::new(), history: Vec::new(), history_file: None }
+    }
+
+    fn output(shell: &mut Shell<Vec<u8>>) -> String {
+        String::from_utf8(std::mem::take(&mut shell.out)).unwrap()
+    }
+
+    #[test]
+    fn statements_test() {
+        let script = "SELECT 1;\nSELECT\n  'a;b';\nCREATE TRIGGER t AFTER INSERT ON item BEGIN\n  SELECT 1;\n  SELECT 2;\nEND;\nSELECT";
+        let (statements, rest) = statements(script);
+        assert_eq!(statements, vec![
+            "SELECT 1;",
+            "SELECT\n  'a;b';",
+            "CREATE TRIGGER t AFTER INSERT ON item BEGIN\n  SELECT 1;\n  SELECT 2;\nEND;",
+        ]);
+        assert_eq!(rest, "\nSELECT");
+
+        assert_eq!(parse_value("42"), Value::I64(42));
+        assert_eq!(parse_value("1.5"), Value::F64(1.5));
+        assert_eq!(parse_value("NULL"), Value::Null);
+        assert_eq!(parse_value("007x"), Value::from("007x"));
+    }
+
+    #[test]
+    fn parameters_test() {
+        let mut shell = shell();
+        shell.run_dot_command(".param add 7").unwrap();
+        shell.run_dot_command(".param add hello world").unwrap();
+        // a '?' in a literal isn't a placeholder, the parameters go to the first ones
+        shell.run_script("INSERT INTO item VALUES (?, ?)").unwrap();
+        output(&mut shell);
+        shell.run_script("SELECT name, '?' AS q FROM item; SELECT ? AS id;").unwrap();
+        assert_eq!(output(&mut shell), "name,q\r\nhello world,?\r\nid\r\n7\r\n");
+
+        shell.run_dot_command(".param").unwrap();
+        assert_eq!(output(&mut shell), "?1 = 7\n?2 = 'hello world'\n");
+        shell.run_dot_command(".param clear").unwrap();
+        // unbound placeholders are NULL
+        shell.run_script("SELECT ? AS id").unwrap();
+        assert_eq!(output(&mut shell), "id\r\n\r\n");
+    }
+
+    #[test]
+    fn dot_commands_test() {
+        let mut shell = shell();
+        shell.run_dot_command(".mode json").unwrap();
+        shell.run_script("INSERT INTO item (name) VALUES ('a'); SELECT * FROM item;").unwrap();
+        assert_eq!(output(&mut shell), "[\n{\"id\":1,\"name\":\"a\"}\n]\n");
+        shell.run_dot_command(".mode line").unwrap();
+        shell.run_dot_command(".mode").unwrap();
+        assert_eq!(output(&mut shell), "Line\n");
+        assert!(shell.run_dot_command(".mode nope").is_err());
+
+        let path = std::env::temp_dir().join(format!("sql3x-shell-{}.csv", std::process::id()));
+        let file = path.display().to_string();
+        shell.run_dot_command(&format!(".export {file} SELECT id, name FROM item ORDER BY id")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "id,name\r\n1,a\r\n");
+        shell.run_dot_command(&format!(".import {file} copy")).unwrap();
+        assert_eq!(output(&mut shell), "1 row(s) exported\n1 row(s) imported\n");
+        let _ = fs::remove_file(&path);
+
+        shell.run_dot_command(".tables").unwrap();
+        assert_eq!(output(&mut shell), "item\ncopy\n");
+        shell.run_script("SELECT name FROM copy").unwrap();
+        assert_eq!(output(&mut shell), "name = a\n");
+        assert!(shell.run_dot_command(".nope").is_err());
+        assert!(!shell.run_dot_command(".quit").unwrap());
+    }
+}
@@ -0,0 +1,981 @@
+use crate::args::{convert, Args, ValueConvertible};
+use crate::error::{ConversionError, Error, Result};
+use crate::query::{quote_identifier, Query};
+use crate::value::Value;
+
+/// Quote a possibly qualified name (`table.column`), `*` is left as it is.
+fn quote_path(name: &str) -> String {
+    name.split('.')
+        .map(|part| match part {
+            "*" => "*".to_string(),
+            _ => quote_identifier(part)
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// SQL text with values collected for placeholders and the first error found.
+#[derive(Default)]
+struct Sql {
+    text: String,
+    values: Vec<Value>,
+    error: Option<Error>,
+}
+
+impl Sql {
+    fn push(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+    fn value(&mut self, value: Value) {
+        self.text.push('?');
+        self.values.push(value);
+    }
+    fn list<T>(&mut self, items: &[T], write: impl Fn(&mut Sql, &T)) {
+        for (idx, item) in items.iter().enumerate() {
+            if idx > 0 {
+                self.push(", ");
+            }
+            write(self, item);
+        }
+    }
+    fn fail(&mut self, message: String) {
+        self.error.get_or_insert(Error::Validation(message));
+    }
+    /// Placeholder for a value that didn't convert, the query fails with its error.
+    fn invalid(&mut self, e: &ConversionError) {
+        let mut e = e.clone();
+        e.context.get_or_insert_with(|| format!("argument {}", self.values.len() + 1).into());
+        self.error.get_or_insert(e.into());
+        self.value(Value::Null);
+    }
+    fn into_query(self) -> Result<Query> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(Query::with_args(self.text.as_str(), Args::from(self.values)))
+        }
+    }
+}
+
+/********************************************************************
+*                                                                   *
+*                       E X P R E S S I O N S                       *
+*                                                                   *
+********************************************************************/
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Column(String),
+    Value(Value),
+    /// Value that didn't convert, reported by `build`.
+    Invalid(ConversionError),
+    Raw(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(Box<Expr>, &'static str, Box<Expr>),
+    Postfix(Box<Expr>, &'static str),
+    In(Box<Expr>, Vec<Expr>, bool),
+    InSelect(Box<Expr>, Box<Select>, bool),
+    Between(Box<Expr>, Box<Expr>, Box<Expr>),
+    Exists(Box<Select>),
+    Subquery(Box<Select>),
+    Function(String, Vec<Expr>),
+}
+
+/// SQL expression used in column lists, conditions and assignments.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr(Node);
+
+/// Column reference, `table.column` is quoted part by part.
+pub fn col(name: &str) -> Expr {
+    Expr(Node::Column(name.to_string()))
+}
+
+/// Value bound as a query argument, `build` fails when it doesn't convert.
+pub fn val<T: ValueConvertible>(value: T) -> Expr {
+    match convert(&value) {
+        Ok(value) => Expr(Node::Value(value)),
+        Err(e) => Expr(Node::Invalid(e))
+    }
+}
+
+/// SQL text inserted as it is, e.g. `raw("count(*)")`.
+pub fn raw(sql: &str) -> Expr {
+    Expr(Node::Raw(sql.to_string()))
+}
+
+/// Function call, e.g. `func("lower", [col("name")])`.
+pub fn func<I: IntoIterator<Item = Expr>>(name: &str, args: I) -> Expr {
+    Expr(Node::Function(name.to_string(), args.into_iter().collect()))
+}
+
+/// `EXISTS (subquery)`.
+pub fn exists(select: Select) -> Expr {
+    Expr(Node::Exists(Box::new(select)))
+}
+
+/// `NOT expr`.
+pub fn not(expr: Expr) -> Expr {
+    Expr(Node::Unary("NOT", Box::new(expr)))
+}
+
+impl<T: ValueConvertible> From<T> for Expr {
+    fn from(value: T) -> Self {
+        val(value)
+    }
+}
+
+impl From<Select> for Expr {
+    fn from(select: Select) -> Self {
+        Expr(Node::Subquery(Box::new(select)))
+    }
+}
+
+impl Expr {
+    fn binary(self, op: &'static str, other: impl Into<Expr>) -> Expr {
+        Expr(Node::Binary(Box::new(self), op, Box::new(other.into())))
+    }
+
+    pub fn eq(self, other: impl Into<Expr>) -> Expr {
+        self.binary("=", other)
+    }
+    pub fn ne(self, other: impl Into<Expr>) -> Expr {
+        self.binary("<>", other)
+    }
+    pub fn lt(self, other: impl Into<Expr>) -> Expr {
+        self.binary("<", other)
+    }
+    pub fn le(self, other: impl Into<Expr>) -> Expr {
+        self.binary("<=", other)
+    }
+    pub fn gt(self, other: impl Into<Expr>) -> Expr {
+        self.binary(">", other)
+    }
+    pub fn ge(self, other: impl Into<Expr>) -> Expr {
+        self.binary(">=", other)
+    }
+    pub fn like(self, pattern: impl Into<Expr>) -> Expr {
+        self.binary("LIKE", pattern)
+    }
+    pub fn glob(self, pattern: impl Into<Expr>) -> Expr {
+        self.binary("GLOB", pattern)
+    }
+    pub fn is(self, other: impl Into<Expr>) -> Expr {
+        self.binary("IS", other)
+    }
+    pub fn concat(self, other: impl Into<Expr>) -> Expr {
+        self.binary("||", other)
+    }
+    pub fn plus(self, other: impl Into<Expr>) -> Expr {
+        self.binary("+", other)
+    }
+    pub fn minus(self, other: impl Into<Expr>) -> Expr {
+        self.binary("-", other)
+    }
+    pub fn and(self, other: impl Into<Expr>) -> Expr {
+        self.binary("AND", other)
+    }
+    pub fn or(self, other: impl Into<Expr>) -> Expr {
+        self.binary("OR", other)
+    }
+    pub fn is_null(self) -> Expr {
+        Expr(Node::Postfix(Box::new(self), "IS NULL"))
+    }
+    pub fn is_not_null(self) -> Expr {
+        Expr(Node::Postfix(Box::new(self), "IS NOT NULL"))
+    }
+    pub fn asc(self) -> Expr {
+        Expr(Node::Postfix(Box::new(self), "ASC"))
+    }
+    pub fn desc(self) -> Expr {
+        Expr(Node::Postfix(Box::new(self), "DESC"))
+    }
+    pub fn between(self, low: impl Into<Expr>, high: impl Into<Expr>) -> Expr {
+        Expr(Node::Between(Box::new(self), Box::new(low.into()), Box::new(high.into())))
+    }
+    pub fn in_list<I, T>(self, items: I) -> Expr
+        where I: IntoIterator<Item = T>, T: Into<Expr>
+    {
+        Expr(Node::In(Box::new(self), items.into_iter().map(Into::into).collect(), false))
+    }
+    pub fn not_in_list<I, T>(self, items: I) -> Expr
+        where I: IntoIterator<Item = T>, T: Into<Expr>
+    {
+        Expr(Node::In(Box::new(self), items.into_iter().map(Into::into).collect(), true))
+    }
+    pub fn in_select(self, select: Select) -> Expr {
+        Expr(Node::InSelect(Box::new(self), Box::new(select), false))
+    }
+    pub fn not_in_select(self, select: Select) -> Expr {
+        Expr(Node::InSelect(Box::new(self), Box::new(select), true))
+    }
+
+    /// Compound expressions are put in parentheses when nested.
+    fn write_operand(&self, sql: &mut Sql) {
+        match self.0 {
+            Node::Unary(..) | Node::Binary(..) | Node::Postfix(..) | Node::In(..) | Node::InSelect(..) | Node::Between(..) => {
+                sql.push("(");
+                self.write(sql);
+                sql.push(")");
+            },
+            _ => self.write(sql)
+        }
+    }
+
+    fn write(&self, sql: &mut Sql) {
+        match &self.0 {
+            Node::Column(name) => sql.push(&quote_path(name)),
+            Node::Value(value) => sql.value(value.clone()),
+            Node::Invalid(e) => sql.invalid(e),
+            Node::Raw(text) => sql.push(text),
+            Node::Unary(op, expr) => {
+                sql.push(op);
+                sql.push(" ");
+                expr.write_operand(sql);
+            },
+            Node::Binary(left, op, right) => {
+                left.write_operand(sql);
+                sql.push(" ");
+                sql.push(op);
+                sql.push(" ");
+                right.write_operand(sql);
+            },
+            Node::Postfix(expr, op) => {
+                expr.write_operand(sql);
+                sql.push(" ");
+                sql.push(op);
+            },
+            Node::In(expr, items, negated) => {
+                expr.write_operand(sql);
+                sql.push(if *negated { " NOT IN (" } else { " IN (" });
+                sql.list(items, |sql, item| item.write(sql));
+                sql.push(")");
+            },
+            Node::InSelect(expr, select, negated) => {
+                expr.write_operand(sql);
+                sql.push(if *negated { " NOT IN (" } else { " IN (" });
+                select.write(sql);
+                sql.push(")");
+            },
+            Node::Between(expr, low, high) => {
+                expr.write_operand(sql);
+                sql.push(" BETWEEN ");
+                low.write_operand(sql);
+                sql.push(" AND ");
+                high.write_operand(sql);
+            },
+            Node::Exists(select) => {
+                sql.push("EXISTS (");
+                select.write(sql);
+                sql.push(")");
+            },
+            Node::Subquery(select) => {
+                sql.push("(");
+                select.write(sql);
+                sql.push(")");
+            },
+            Node::Function(name, args) => {
+                sql.push(name);
+                sql.push("(");
+                sql.list(args, |sql, arg| arg.write(sql));
+                sql.push(")");
+            },
+        }
+    }
+}
+
+/// Parse a column list entry: `*`, `t.*`, `t.col` or `col`.
+fn column_expr(name: &str) -> Expr {
+    match name {
+        "*" => raw("*"),
+        _ => col(name)
+    }
+}
+
+/// Join two optional conditions with AND.
+fn and_filter(filter: Option<Expr>, expr: Expr) -> Option<Expr> {
+    match filter {
+        Some(current) => Some(current.and(expr)),
+        None => Some(expr)
+    }
+}
+
+fn write_filter(sql: &mut Sql, filter: &Option<Expr>) {
+    if let Some(filter) = filter {
+        sql.push(" WHERE ");
+        filter.write(sql);
+    }
+}
+
+fn write_returning(sql: &mut Sql, returning: &[String]) {
+    if !returning.is_empty() {
+        sql.push(" RETURNING ");
+        sql.list(returning, |sql, name| sql.push(&match name.as_str() {
+            "*" => "*".to_string(),
+            _ => quote_path(name)
+        }));
+    }
+}
+
+/********************************************************************
+*                                                                   *
+*                           S E L E C T                             *
+*                                                                   *
+********************************************************************/
+
+#[derive(Clone, Debug, PartialEq)]
+enum Source {
+    Table(String, Option<String>),
+    Select(Box<Select>, String),
+}
+
+impl Source {
+    fn write(&self, sql: &mut Sql) {
+        match self {
+            Source::Table(name, alias) => {
+                sql.push(&quote_path(name));
+                if let Some(alias) = alias {
+                    sql.push(" AS ");
+                    sql.push(&quote_identifier(alias));
+                }
+            },
+            Source::Select(select, alias) => {
+                sql.push("(");
+                select.write(sql);
+                sql.push(") AS ");
+                sql.push(&quote_identifier(alias));
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Join {
+    kind: &'static str,
+    source: Source,
+    on: Option<Expr>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Cte {
+    name: String,
+    select: Select,
+}
+
+/// SELECT statement builder.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Select {
+    recursive: bool,
+    ctes: Vec<Cte>,
+    distinct: bool,
+    columns: Vec<(Expr, Option<String>)>,
+    from: Option<Source>,
+    joins: Vec<Join>,
+    filter: Option<Expr>,
+    group_by: Vec<Expr>,
+    having: Option<Expr>,
+    compounds: Vec<(&'static str, Select)>,
+    order_by: Vec<Expr>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+/// Start a SELECT with a list of column names (`*` and `table.column` allowed).
+pub fn select<I, S>(columns: I) -> Select
+    where I: IntoIterator<Item = S>, S: AsRef<str>
+{
+    Select {
+        columns: columns.into_iter().map(|c| (column_expr(c.as_ref()), None)).collect(),
+        ..Default::default()
+    }
+}
+
+impl Select {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a column expression.
+    pub fn column(mut self, expr: impl Into<Expr>) -> Self {
+        self.columns.push((expr.into(), None));
+        self
+    }
+    /// Add a column expression with an alias.
+    pub fn column_as(mut self, expr: impl Into<Expr>, alias: &str) -> Self {
+        self.columns.push((expr.into(), Some(alias.to_string())));
+        self
+    }
+    pub fn distinct(mut self) -> Self {
+        self.distinct = true;
+        self
+    }
+    /// Add a common table expression (`WITH name AS (select)`).
+    pub fn with(mut self, name: &str, select: Select) -> Self {
+        self.ctes.push(Cte { name: name.to_string(), select });
+        self
+    }
+    /// Add a recursive common table expression (`WITH RECURSIVE`).
+    pub fn with_recursive(mut self, name: &str, select: Select) -> Self {
+        self.recursive = true;
+        self.with(name, select)
+    }
+    pub fn from(mut self, table: &str) -> Self {
+        self.from = Some(Source::Table(table.to_string(), None));
+        self
+    }
+    pub fn from_as(mut self, table: &str, alias: &str) -> Self {
+        self.from = Some(Source::Table(table.to_string(), Some(alias.to_string())));
+        self
+    }
+    /// Select from a subquery, which in SQLite needs an alias.
+    pub fn from_select(mut self, select: Select, alias: &str) -> Self {
+        self.from = Some(Source::Select(Box::new(select), alias.to_string()));
+        self
+    }
+    fn add_join(mut self, kind: &'static str, table: &str, alias: Option<&str>, on: Option<Expr>) -> Self {
+        let source = Source::Table(table.to_string(), alias.map(str::to_string));
+        self.joins.push(Join { kind, source, on });
+        self
+    }
+    pub fn join(self, table: &str, on: Expr) -> Self {
+        self.add_join("JOIN", table, None, Some(on))
+    }
+    pub fn join_as(self, table: &str, alias: &str, on: Expr) -> Self {
+        self.add_join("JOIN", table, Some(alias), Some(on))
+    }
+    pub fn left_join(self, table: &str, on: Expr) -> Self {
+        self.add_join("LEFT JOIN", table, None, Some(on))
+    }
+    pub fn left_join_as(self, table: &str, alias: &str, on: Expr) -> Self {
+        self.add_join("LEFT JOIN", table, Some(alias), Some(on))
+    }
+    pub fn cross_join(self, table: &str) -> Self {
+        self.add_join("CROSS JOIN", table, None, None)
+    }
+    /// Join a subquery under an alias.
+    pub fn join_select(mut self, select: Select, alias: &str, on: Expr) -> Self {
+        let source = Source::Select(Box::new(select), alias.to_string());
+        self.joins.push(Join { kind: "JOIN", source, on: Some(on) });
+        self
+    }
+    /// Add a WHERE condition, repeated calls are joined with AND.
+    pub fn where_(mut self, expr: Expr) -> Self {
+        self.filter = and_filter(self.filter, expr);
+        self
+    }
+    pub fn group_by<I, S>(mut self, columns: I) -> Self
+        where I: IntoIterator<Item = S>, S: AsRef<str>
+    {
+        self.group_by.extend(columns.into_iter().map(|c| col(c.as_ref())));
+        self
+    }
+    pub fn having(mut self, expr: Expr) -> Self {
+        self.having = and_filter(self.having, expr);
+        self
+    }
+    pub fn union(mut self, select: Select) -> Self {
+        self.compounds.push(("UNION", select));
+        self
+    }
+    pub fn union_all(mut self, select: Select) -> Self {
+        self.compounds.push(("UNION ALL", select));
+        self
+    }
+    /// Order by a column or expression, use `col("x").desc()` for descending order.
+    pub fn order_by(mut self, expr: impl Into<OrderBy>) -> Self {
+        self.order_by.push(expr.into().0);
+        self
+    }
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Build the query.
+    pub fn build(&self) -> Result<Query> {
+        let mut sql = Sql::default();
+        self.write(&mut sql);
+        sql.into_query()
+    }
+
+    /// Only the core of a SELECT can be a compound member as it is.
+    fn is_simple(&self) -> bool {
+        self.ctes.is_empty() && self.compounds.is_empty() && self.order_by.is_empty()
+            && self.limit.is_none() && self.offset.is_none()
+    }
+
+    fn write(&self, sql: &mut Sql) {
+        if !self.ctes.is_empty() {
+            sql.push(if self.recursive { "WITH RECURSIVE " } else { "WITH " });
+            sql.list(&self.ctes, |sql, cte| {
+                sql.push(&quote_identifier(&cte.name));
+                sql.push(" AS (");
+                cte.select.write(sql);
+                sql.push(")");
+            });
+            sql.push(" ");
+        }
+        self.write_core(sql);
+        for (op, select) in &self.compounds {
+            sql.push(" ");
+            sql.push(op);
+            sql.push(" ");
+            // other members keep their compounds, ORDER BY and LIMIT in a subquery
+            match select.is_simple() {
+                true => select.write_core(sql),
+                false => {
+                    sql.push("SELECT * FROM (");
+                    select.write(sql);
+                    sql.push(")");
+                }
+            }
+        }
+        if !self.order_by.is_empty() {
+            sql.push(" ORDER BY ");
+            sql.list(&self.order_by, |sql, expr| expr.write(sql));
+        }
+        if let Some(limit) = self.limit {
+            sql.push(" LIMIT ");
+            sql.value(Value::I64(limit));
+        }
+        if let Some(offset) = self.offset {
+            if self.limit.is_none() {
+                sql.push(" LIMIT -1");
+            }
+            sql.push(" OFFSET ");
+            sql.value(Value::I64(offset));
+        }
+    }
+
+    fn write_core(&self, sql: &mut Sql) {
+        sql.push(if self.distinct { "SELECT DISTINCT " } else { "SELECT " });
+        if self.columns.is_empty() {
+            sql.push("*");
+        }
+        sql.list(&self.columns, |sql, (expr, alias)| {
+            expr.write(sql);
+            if let Some(alias) = alias {
+                sql.push(" AS ");
+                sql.push(&quote_identifier(alias));
+            }
+        });
+        if let Some(from) = &self.from {
+            sql.push(" FROM ");
+            from.write(sql);
+        }
+        for join in &self.joins {
+            sql.push(" ");
+            sql.push(join.kind);
+            sql.push(" ");
+            join.source.write(sql);
+            if let Some(on) = &join.on {
+                sql.push(" ON ");
+                on.write(sql);
+            }
+        }
+        write_filter(sql, &self.filter);
+        if !self.group_by.is_empty() {
+            sql.push(" GROUP BY ");
+            sql.list(&self.group_by, |sql, expr| expr.write(sql));
+        }
+        if let Some(having) = &self.having {
+            sql.push(" HAVING ");
+            having.write(sql);
+        }
+    }
+}
+
+impl TryFrom<Select> for Query {
+    type Error = Error;
+    fn try_from(select: Select) -> Result<Self> {
+        select.build()
+    }
+}
+
+/// ORDER BY term, a column name or an expression.
+pub struct OrderBy(Expr);
+
+impl From<&str> for OrderBy {
+    fn from(name: &str) -> Self {
+        OrderBy(col(name))
+    }
+}
+
+impl From<Expr> for OrderBy {
+    fn from(expr: Expr) -> Self {
+        OrderBy(expr)
+    }
+}
+
+/********************************************************************
+*                                                                   *
+*                 I N S E R T / U P D A T E / D E L E T E           *
+*                                                                   *
+********************************************************************/
+
+/// INSERT statement builder.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Insert {
+    table: String,
+    conflict: Option<&'static str>,
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+    /// First value that didn't convert.
+    error: Option<ConversionError>,
+    select: Option<Select>,
+    returning: Vec<String>,
+}
+
+/// Start an INSERT into a table.
+pub fn insert_into(table: &str) -> Insert {
+    Insert { table: table.to_string(), ..Default::default() }
+}
+
+impl Insert {
+    pub fn columns<I, S>(mut self, columns: I) -> Self
+        where I: IntoIterator<Item = S>, S: AsRef<str>
+    {
+        self.columns = columns.into_iter().map(|c| c.as_ref().to_string()).collect();
+        self
+    }
+    /// Add one row of values, in the order of `columns`.
+    /// `build` fails with the error of an argument that didn't convert.
+    pub fn values(mut self, args: Args) -> Self {
+        if let Some(e) = args.error() && self.error.is_none() {
+            let mut e = e.clone();
+            e.context = Some(format!("row {}, {}", self.rows.len() + 1, e.context.as_deref().unwrap_or("argument")).into());
+            self.error = Some(e);
+        }
+        self.rows.push(args.iter().cloned().collect());
+        self
+    }
+    /// Set one column of a single-row insert.
+    pub fn value<T: ValueConvertible>(mut self, column: &str, value: T) -> Self {
+        let value = convert(&value).unwrap_or_else(|e| {
+            self.error.get_or_insert(e.column(column));
+            Value::Null
+        });
+        self.columns.push(column.to_string());
+        match self.rows.first_mut() {
+            Some(row) => row.push(value),
+            None => self.rows.push(vec![value])
+        }
+        self
+    }
+    /// Insert rows produced by a SELECT.
+    pub fn select(mut self, select: Select) -> Self {
+        self.select = Some(select);
+        self
+    }
+    pub fn or_replace(mut self) -> Self {
+        self.conflict = Some("REPLACE");
+        self
+    }
+    pub fn or_ignore(mut self) -> Self {
+        self.conflict = Some("IGNORE");
+        self
+    }
+    pub fn returning<I, S>(mut self, columns: I) -> Self
+        where I: IntoIterator<Item = S>, S: AsRef<str>
+    {
+        self.returning = columns.into_iter().map(|c| c.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Build the query, every row must have a value for each column.
+    pub fn build(&self) -> Result<Query> {
+        let mut sql = Sql::default();
+        let width = match self.columns.is_empty() {
+            true => self.rows.first().map(Vec::len).unwrap_or_default(),
+            false => self.columns.len()
+        };
+        if let Some(e) = &self.error {
+            sql.error = Some(e.clone().into());
+        }
+        if let Some((idx, row)) = self.rows.iter().enumerate().find(|(_, row)| row.len() != width) {
+            sql.fail(format!("row {} has {} values, expected {width}", idx + 1, row.len()));
+        }
+        sql.push("INSERT ");
+        if let Some(conflict) = self.conflict {
+            sql.push("OR ");
+            sql.push(conflict);
+            sql.push(" ");
+        }
+        sql.push("INTO ");
+        sql.push(&quote_path(&self.table));
+        if !self.columns.is_empty() {
+            sql.push(" (");
+            sql.list(&self.columns, |sql, name| sql.push(&quote_identifier(name)));
+            sql.push(")");
+        }
+        match &self.select {
+            Some(select) => {
+                sql.push(" ");
+                select.write(&mut sql);
+            },
+            None if self.rows.is_empty() => sql.push(" DEFAULT VALUES"),
+            None => {
+                sql.push(" VALUES ");
+                sql.list(&self.rows, |sql, row| {
+                    sql.push("(");
+                    sql.list(row, |sql, value| sql.value(value.clone()));
+                    sql.push(")");
+                });
+            }
+        }
+        write_returning(&mut sql, &self.returning);
+        sql.into_query()
+    }
+}
+
+impl TryFrom<Insert> for Query {
+    type Error = Error;
+    fn try_from(insert: Insert) -> Result<Self> {
+        insert.build()
+    }
+}
+
+/// UPDATE statement builder.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Update {
+    table: String,
+    assignments: Vec<(String, Expr)>,
+    filter: Option<Expr>,
+    returning: Vec<String>,
+}
+
+/// Start an UPDATE of a table.
+pub fn update(table: &str) -> Update {
+    Update { table: table.to_string(), ..Default::default() }
+}
+
+impl Update {
+    /// Assign a value or an expression to a column.
+    pub fn set(mut self, column: &str, value: impl Into<Expr>) -> Self {
+        self.assignments.push((column.to_string(), value.into()));
+        self
+    }
+    /// Add a WHERE condition, repeated calls are joined with AND.
+    pub fn where_(mut self, expr: Expr) -> Self {
+        self.filter = and_filter(self.filter, expr);
+        self
+    }
+    pub fn returning<I, S>(mut self, columns: I) -> Self
+        where I: IntoIterator<Item = S>, S: AsRef<str>
+    {
+        self.returning = columns.into_iter().map(|c| c.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Build the query, at least one `set` is required.
+    pub fn build(&self) -> Result<Query> {
+        let mut sql = Sql::default();
+        if self.assignments.is_empty() {
+            sql.fail(format!("UPDATE of {} has no SET", self.table));
+        }
+        sql.push("UPDATE ");
+        sql.push(&quote_path(&self.table));
+        sql.push(" SET ");
+        sql.list(&self.assignments, |sql, (name, expr)| {
+            sql.push(&quote_identifier(name));
+            sql.push("=");
+            expr.write(sql);
+        });
+        write_filter(&mut sql, &self.filter);
+        write_returning(&mut sql, &self.returning);
+        sql.into_query()
+    }
+}
+
+impl TryFrom<Update> for Query {
+    type Error = Error;
+    fn try_from(update: Update) -> Result<Self> {
+        update.build()
+    }
+}
+
+/// DELETE statement builder.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Delete {
+    table: String,
+    filter: Option<Expr>,
+    returning: Vec<String>,
+}
+
+/// Start a DELETE from a table.
+pub fn delete_from(table: &str) -> Delete {
+    Delete { table: table.to_string(), ..Default::default() }
+}
+
+impl Delete {
+    /// Add a WHERE condition, repeated calls are joined with AND.
+    pub fn where_(mut self, expr: Expr) -> Self {
+        self.filter = and_filter(self.filter, expr);
+        self
+    }
+    pub fn returning<I, S>(mut self, columns: I) -> Self
+        where I: IntoIterator<Item = S>, S: AsRef<str>
+    {
+        self.returning = columns.into_iter().map(|c| c.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Build the query.
+    pub fn build(&self) -> Result<Query> {
+        let mut sql = Sql::default();
+        sql.push("DELETE FROM ");
+        sql.push(&quote_path(&self.table));
+        write_filter(&mut sql, &self.filter);
+        write_returning(&mut sql, &self.returning);
+        sql.into_query()
+    }
+}
+
+impl TryFrom<Delete> for Query {
+    type Error = Error;
+    fn try_from(delete: Delete) -> Result<Self> {
+        delete.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+
+    static CREATE_SCHEMA: &str = r#"
+        CREATE TABLE person (id INTEGER PRIMARY KEY, first_name TEXT, surname TEXT, "odd ""name""" INT);
+        CREATE TABLE pet (id INTEGER PRIMARY KEY, owner INT, name TEXT);
+    "#;
+
+    #[test]
+    fn select_test() {
+        let query = select(["id", "surname"])
+            .from("person")
+            .where_(col("id").eq(5))
+            .where_(col("surname").like("P%").or(col("surname").is_null()))
+            .order_by(col("surname").desc())
+            .order_by("id")
+            .limit(10)
+            .build().unwrap();
+        assert_eq!(query.cmd, r#"SELECT "id", "surname" FROM "person" WHERE ("id" = ?) AND (("surname" LIKE ?) OR ("surname" IS NULL)) ORDER BY "surname" DESC, "id" LIMIT ?"#);
+        assert_eq!(query.args, Args::new().arg(5).arg("P%").arg(10i64));
+    }
+
+    #[test]
+    fn join_subquery_and_cte_test() {
+        let query = select(["p.surname", "x.name"])
+            .with("pets", select(["owner", "name"]).from("pet"))
+            .from_as("person", "p")
+            .join_as("pets", "x", col("x.owner").eq(col("p.id")))
+            .where_(col("p.id").in_select(select(["owner"]).from("pet").where_(col("name").ne("Rex"))))
+            .build().unwrap();
+        assert_eq!(query.cmd, r#"WITH "pets" AS (SELECT "owner", "name" FROM "pet") SELECT "p"."surname", "x"."name" FROM "person" AS "p" JOIN "pets" AS "x" ON "x"."owner" = "p"."id" WHERE "p"."id" IN (SELECT "owner" FROM "pet" WHERE "name" <> ?)"#);
+        assert_eq!(query.args.len(), 1);
+    }
+
+    #[test]
+    fn run_against_database() {
+        let mut sq = test_database(CREATE_SCHEMA);
+        let id = insert_into("person")
+            .value("first_name", "Piotr")
+            .value("surname", "Pszczółkowski")
+            .value("odd \"name\"", 3)
+            .build().unwrap()
+            .insert(&mut sq)
+            .unwrap();
+        insert_into("person")
+            .columns(["first_name", "surname"])
+            .values(Args::new().arg("Robert").arg("C"))
+            .values(Args::new().arg("Anna").arg("B"))
+            .build().unwrap()
+            .insert(&mut sq)
+            .unwrap();
+        insert_into("pet").columns(["owner", "name"]).values(Args::new().arg(id).arg("Rex")).build().unwrap().insert(&mut sq).unwrap();
+
+        let result = select(["surname"]).from("person").where_(col("id").between(2, 3)).order_by("surname").build().unwrap().select(&mut sq).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0]["surname"], Value::from("B"));
+
+        let result = Select::new()
+            .column_as(col("p.first_name"), "who")
+            .column_as(func("count", [col("t.id")]), "pets")
+            .from_as("person", "p")
+            .left_join_as("pet", "t", col("t.owner").eq(col("p.id")))
+            .group_by(["p.id"])
+            .having(func("count", [col("t.id")]).gt(0))
+            .build().unwrap()
+            .select(&mut sq)
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0]["who"], Value::from("Piotr"));
+        assert_eq!(result[0]["pets"], Value::I64(1));
+
+        let result = select(["n"])
+            .with_recursive("cnt", Select::new().column_as(val(1), "n")
+                .union_all(Select::new().column(col("n").plus(1)).from("cnt").where_(col("n").lt(5))))
+            .from("cnt")
+            .build().unwrap()
+            .select(&mut sq)
+            .unwrap();
+        assert_eq!(result.len(), 5);
+
+        let changes = update("person").set("surname", "X").set("odd \"name\"", col("odd \"name\"").plus(1))
+            .where_(col("id").eq(id))
+            .build().unwrap()
+            .update(&mut sq)
+            .unwrap();
+        assert_eq!(changes.changes, 1);
+
+        let result = delete_from("person")
+            .where_(col("id").in_list([2, 3]))
+            .returning(["id"])
+            .build().unwrap()
+            .returning_rows(&mut sq)
+            .unwrap();
+        assert_eq!(result.len(), 2);
+
+        let result = select(["*"]).from("person").where_(exists(select(["id"]).from("pet").where_(col("pet.owner").eq(col("person.id"))))).build().unwrap();
+        let json = result.to_json().unwrap();
+        let result = Query::from_json(&json).unwrap().select(&mut sq).unwrap();
+        assert_eq!(result[0]["odd \"name\""], Value::I64(4));
+    }
+
+    #[test]
+    fn compound_and_errors_test() {
+        let mut sq = test_database(CREATE_SCHEMA);
+        sq.exec_command("INSERT INTO pet (name) VALUES ('a'), ('b'), ('c'), ('d')").unwrap();
+
+        let nested = select(["name"]).from("pet").where_(col("name").eq("b"))
+            .union(select(["name"]).from("pet").where_(col("name").eq("c")));
+        let query = select(["name"]).from("pet").where_(col("name").eq("a")).union(nested).build().unwrap();
+        assert_eq!(query.cmd, r#"SELECT "name" FROM "pet" WHERE "name" = ? UNION SELECT * FROM (SELECT "name" FROM "pet" WHERE "name" = ? UNION SELECT "name" FROM "pet" WHERE "name" = ?)"#);
+        assert_eq!(query.select(&mut sq).unwrap().len(), 3);
+
+        let last = select(["name"]).from("pet").order_by(col("name").desc()).limit(1);
+        let result = select(["name"]).from("pet").where_(col("name").eq("a")).union_all(last).order_by("name").build().unwrap().select(&mut sq).unwrap();
+        let names: Vec<_> = result.iter().map(|row| row["name"].clone()).collect();
+        assert_eq!(names, vec![Value::from("a"), Value::from("d")]);
+
+        assert!(update("pet").where_(col("id").eq(1)).build().is_err());
+        let e = insert_into("pet").columns(["owner", "name"]).values(Args::new().arg(1)).build().unwrap_err();
+        assert_eq!(e.to_string(), "row 1 has 1 values, expected 2");
+        assert!(insert_into("pet").values(Args::new().arg(1).arg(1).arg("x")).values(Args::new().arg(2)).build().is_err());
+        assert!(Query::try_from(delete_from("pet")).is_ok());
+    }
+
+    #[test]
+    fn conversion_error_test() {
+        let e = select(["name"]).from("pet").where_(col("id").eq(1).and(col("id").ne(u64::MAX))).build().unwrap_err();
+        assert_eq!(e.conversion().unwrap().context.as_deref(), Some("argument 2"));
+        let e = insert_into("pet").value("name", "Rex").value("owner", u64::MAX).build().unwrap_err();
+        assert_eq!(e.conversion().unwrap().column.as_deref(), Some("owner"));
+        let e = insert_into("pet").columns(["owner", "name"])
+            .values(Args::new().arg(1).arg("a"))
+            .values(Args::new().arg(u64::MAX).arg("b"))
+            .build().unwrap_err();
+        assert_eq!(e.conversion().unwrap().context.as_deref(), Some("row 2, argument 1"));
+        assert!(select(["name"]).from("pet").where_(col("id").eq(7_u64)).build().is_ok());
+    }
+}
@@ -0,0 +1,376 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::sync::{Arc, Mutex, RwLock};
+use chrono::{DateTime, Duration, Utc};
+use sqlite3_sys::{
+    sqlite3_context,
+    sqlite3_create_function_v2,
+    sqlite3_result_blob,
+    sqlite3_result_double,
+    sqlite3_result_error,
+    sqlite3_result_int64,
+    sqlite3_result_null,
+    sqlite3_result_text,
+    sqlite3_user_data,
+    sqlite3_value,
+    sqlite3_value_blob,
+    sqlite3_value_bytes,
+    sqlite3_value_double,
+    sqlite3_value_int64,
+    sqlite3_value_text,
+    sqlite3_value_type,
+    SQLITE_BLOB,
+    SQLITE_DETERMINISTIC,
+    SQLITE_DONE,
+    SQLITE_FLOAT,
+    SQLITE_INNOCUOUS,
+    SQLITE_INTEGER,
+    SQLITE_NULL,
+    SQLITE_OK,
+    SQLITE_ROW,
+    SQLITE_UTF8,
+};
+use crate::args::Args;
+use crate::db::SQLite;
+use crate::error::{Result, SqliteError};
+use crate::stmt::Stmt;
+use crate::value::Value;
+
+/// Source of the current time.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The operating system clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Clock for tests: stands still until set or advanced.
+#[derive(Debug)]
+pub struct ManualClock(Mutex<DateTime<Utc>>);
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        ManualClock(Mutex::new(now))
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.0.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, d: Duration) {
+        *self.0.lock().unwrap() += d;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+static GLOBAL_CLOCK: RwLock<Option<Arc<dyn Clock>>> = RwLock::new(None);
+
+/// Held by tests that set or read the global clock, they run in parallel otherwise.
+#[cfg(test)]
+pub(crate) static GLOBAL_CLOCK_TEST: Mutex<()> = Mutex::new(());
+
+/// Clock for `Timestamp::now` and connections without their own.
+pub fn set_global_clock(clock: Arc<dyn Clock>) {
+    *GLOBAL_CLOCK.write().unwrap() = Some(clock);
+}
+
+/// Back to the system clock.
+pub fn reset_global_clock() {
+    *GLOBAL_CLOCK.write().unwrap() = None;
+}
+
+pub fn global_clock() -> Arc<dyn Clock> {
+    GLOBAL_CLOCK.read().unwrap().clone().unwrap_or_else(|| Arc::new(SystemClock))
+}
+
+/// Current time of the global clock.
+pub fn now() -> DateTime<Utc> {
+    global_clock().now()
+}
+
+/// Clock of a connection, shared with its SQL functions. None is the global clock.
+pub(crate) type ClockSlot = Arc<RwLock<Option<Arc<dyn Clock>>>>;
+
+/// Date functions and where their time values are. Called with fewer arguments
+/// than the first position the time value is 'now'.
+const DATE_FUNCTIONS: [(&str, &[usize]); 7] = [
+    ("date", &[0]),
+    ("time", &[0]),
+    ("datetime", &[0]),
+    ("julianday", &[0]),
+    ("unixepoch", &[0]),
+    ("strftime", &[1]),
+    ("timediff", &[0, 1]),
+];
+
+/// `CURRENT_*` keywords call these.
+const CURRENT_FUNCTIONS: [(&str, &str); 3] = [
+    ("current_date", "%Y-%m-%d"),
+    ("current_time", "%H:%M:%S"),
+    ("current_timestamp", "%Y-%m-%d %H:%M:%S"),
+];
+
+struct ClockFunction {
+    clock: ClockSlot,
+    name: &'static str,
+    /// Format of a `CURRENT_*` function.
+    format: Option<&'static str>,
+    time_args: &'static [usize],
+    /// Connection running the built-in function once 'now' is replaced.
+    builtin: RefCell<Option<Builtin>>,
+}
+
+/// Private connection with a prepared `SELECT fn(?, ...)` for each argument count.
+struct Builtin {
+    // dropped before the connection, which can't close with statements left
+    statements: HashMap<usize, Stmt>,
+    sq: SQLite,
+}
+
+impl Builtin {
+    fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value> {
+        let stmt = match self.statements.entry(args.len()) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                let placeholders = vec!["?"; args.len()].join(", ");
+                entry.insert(Stmt::for_command(self.sq.db, &format!("SELECT {name}({placeholders})"))?)
+            }
+        };
+        stmt.bind(Args::from(args))?;
+        let result = match stmt.step() {
+            SQLITE_ROW => Ok(stmt.value_ref(0).to_value()),
+            SQLITE_DONE => Ok(Value::Null),
+            _ => Err(stmt.error())
+        };
+        stmt.reset().and(result)
+    }
+}
+
+impl ClockFunction {
+    fn now(&self) -> DateTime<Utc> {
+        match &*self.clock.read().unwrap() {
+            Some(clock) => clock.now(),
+            None => now()
+        }
+    }
+
+    fn call(&self, mut args: Vec<Value>) -> Result<Value> {
+        if let Some(format) = self.format {
+            return Ok(Value::Text(self.now().format(format).to_string()));
+        }
+        let now = Value::Text(self.now().format("%Y-%m-%d %H:%M:%S%.3f").to_string());
+        // date() is date('now'), strftime(format) is strftime(format, 'now')
+        if self.time_args.len() == 1 && args.len() == self.time_args[0] {
+            args.push(now.clone());
+        }
+        for &i in self.time_args {
+            if let Some(Value::Text(text)) = args.get(i)
+                && text.trim().eq_ignore_ascii_case("now")
+            {
+                args[i] = now.clone();
+            }
+        }
+        let mut builtin = self.builtin.borrow_mut();
+        if builtin.is_none() {
+            *builtin = Some(Builtin { statements: HashMap::new(), sq: SQLite::new().create(true, |_| Ok(()))? });
+        }
+        builtin.as_mut().unwrap().call(self.name, args)
+    }
+}
+
+unsafe fn read_value(v: *mut sqlite3_value) -> Value {
+    unsafe {
+        match sqlite3_value_type(v) {
+            SQLITE_NULL => Value::Null,
+            SQLITE_INTEGER => Value::I64(sqlite3_value_int64(v)),
+            SQLITE_FLOAT => Value::F64(sqlite3_value_double(v)),
+            SQLITE_BLOB => {
+                let len = sqlite3_value_bytes(v) as usize;
+                let ptr = sqlite3_value_blob(v) as *const u8;
+                Value::Blob(if len == 0 { Vec::new() } else { std::slice::from_raw_parts(ptr, len).to_vec() })
+            },
+            _ => Value::Text(CStr::from_ptr(sqlite3_value_text(v) as *const c_char).to_string_lossy().into_owned())
+        }
+    }
+}
+
+/// SQLITE_TRANSIENT: SQLite copies the result.
+fn transient() -> Option<unsafe extern "C" fn(*mut c_void)> {
+    unsafe { std::mem::transmute::<*const c_void, Option<unsafe extern "C" fn(*mut c_void)>>(!0 as *const c_void) }
+}
+
+unsafe extern "C" fn call_clock_function(ctx: *mut sqlite3_context, argc: c_int, argv: *mut *mut sqlite3_value) {
+    unsafe {
+        let function = &*(sqlite3_user_data(ctx) as *const ClockFunction);
+        let args = (0..argc as usize).map(|i| read_value(*argv.add(i))).collect();
+        match function.call(args) {
+            Ok(Value::Null) => sqlite3_result_null(ctx),
+            Ok(Value::I64(v)) => sqlite3_result_int64(ctx, v),
+            Ok(Value::F64(v)) => sqlite3_result_double(ctx, v),
+            Ok(Value::Text(v)) => sqlite3_result_text(ctx, v.as_ptr() as *const c_char, v.len() as c_int, transient()),
+            Ok(Value::Blob(v)) => sqlite3_result_blob(ctx, v.as_ptr() as *const c_void, v.len() as c_int, transient()),
+            Err(e) => {
+                let message = e.to_string();
+                sqlite3_result_error(ctx, message.as_ptr() as *const c_char, message.len() as c_int);
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn drop_clock_function(data: *mut c_void) {
+    unsafe { drop(Box::from_raw(data as *mut ClockFunction)) }
+}
+
+impl SQLite {
+    /// Clock of this connection, the global clock when none is set.
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.read().unwrap().clone().unwrap_or_else(global_clock)
+    }
+
+    /// Use `clock` for this connection instead of the global clock.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        *self.clock.write().unwrap() = Some(clock);
+    }
+
+    /// Make 'now' in SQL date and time functions and `CURRENT_TIMESTAMP`, `CURRENT_DATE`
+    /// and `CURRENT_TIME` read `clock()`, with millisecond precision. Works on an open
+    /// connection and lasts until it is closed.
+    ///
+    /// The overrides hide SQLite's own functions, so every call, with 'now' or not,
+    /// runs the built-in through a statement prepared on a private in-memory connection.
+    /// That is several times slower than the built-in, avoid it for date functions over
+    /// large tables.
+    ///
+    /// Calls with explicit arguments stay deterministic, so generated columns, index
+    /// expressions and CHECK constraints can still use them. SQLite rejects 'now' in those
+    /// places, the overrides can't tell and read the clock there too.
+    pub fn use_clock_in_sql(&mut self) -> Result<()> {
+        // (name, format of CURRENT_*, time value positions, argument count, deterministic)
+        let functions = DATE_FUNCTIONS.iter()
+            .flat_map(|&(name, time_args)| {
+                let implicit_now = (time_args.len() == 1).then(|| (name, None, time_args, time_args[0] as c_int, false));
+                [Some((name, None, time_args, -1, true)), implicit_now]
+            })
+            .flatten()
+            .chain(CURRENT_FUNCTIONS.iter().map(|&(name, format)| (name, Some(format), &[][..], 0, false)));
+        for (name, format, time_args, n_arg, deterministic) in functions {
+            let function = Box::new(ClockFunction {
+                clock: self.clock.clone(),
+                name,
+                format,
+                time_args,
+                builtin: RefCell::new(None),
+            });
+            let c_name = CString::new(name).unwrap();
+            let flags = match deterministic {
+                true => SQLITE_UTF8 | SQLITE_DETERMINISTIC | SQLITE_INNOCUOUS,
+                false => SQLITE_UTF8
+            };
+            let stat = unsafe {
+                sqlite3_create_function_v2(
+                    self.db,
+                    c_name.as_ptr(),
+                    n_arg,
+                    flags,
+                    Box::into_raw(function) as *mut c_void,
+                    Some(call_clock_function),
+                    None,
+                    None,
+                    Some(drop_clock_function))
+            };
+            if stat != SQLITE_OK {
+                return Err(SqliteError::from_db(self.db, None).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+    use crate::query::Query;
+    use crate::timestamp::Timestamp;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    fn one(sq: &mut SQLite, sql: &str) -> Value {
+        sq.select(Query::new(sql)).unwrap().remove(0).into_values().next().unwrap()
+    }
+
+    #[test]
+    fn connection_clock_test() {
+        let clock = Arc::new(ManualClock::new(at("2024-05-01T10:00:00.250Z")));
+        let mut sq = test_database("CREATE TABLE t (id INTEGER PRIMARY KEY, at TEXT DEFAULT CURRENT_TIMESTAMP)");
+        sq.set_clock(clock.clone());
+        assert_eq!(sq.clock().now(), at("2024-05-01T10:00:00.250Z"));
+
+        sq.use_clock_in_sql().unwrap();
+        assert_eq!(one(&mut sq, "SELECT datetime('now')"), Value::from("2024-05-01 10:00:00"));
+        assert_eq!(one(&mut sq, "SELECT date('NOW', '+1 day')"), Value::from("2024-05-02"));
+        assert_eq!(one(&mut sq, "SELECT strftime('%H:%M:%f', 'now')"), Value::from("10:00:00.250"));
+        assert_eq!(one(&mut sq, "SELECT unixepoch()"), Value::I64(1714557600));
+        assert_eq!(one(&mut sq, "SELECT CURRENT_TIMESTAMP"), Value::from("2024-05-01 10:00:00"));
+        // other time values are left alone
+        assert_eq!(one(&mut sq, "SELECT datetime('2000-01-01', '+1 hour')"), Value::from("2000-01-01 01:00:00"));
+
+        clock.advance(Duration::days(1));
+        sq.exec(Query::new("INSERT INTO t (id) VALUES (1)")).unwrap();
+        assert_eq!(one(&mut sq, "SELECT at FROM t"), Value::from("2024-05-02 10:00:00"));
+        assert_eq!(one(&mut sq, "SELECT CURRENT_DATE"), Value::from("2024-05-02"));
+        assert!(sq.select(Query::new("SELECT datetime('now', 'bogus')")).unwrap().remove(0).into_values().all(|v| v == Value::Null));
+        assert_eq!(one(&mut sq, "SELECT strftime('%Y')"), Value::from("2024"));
+    }
+
+    #[test]
+    fn deterministic_test() {
+        let clock = Arc::new(ManualClock::new(at("2024-05-01T10:00:00Z")));
+        let mut sq = test_database("CREATE TABLE t (at TEXT, day TEXT AS (date(at)))");
+        sq.set_clock(clock);
+        sq.use_clock_in_sql().unwrap();
+        sq.exec_command("CREATE INDEX t_month ON t (strftime('%Y-%m', at)) WHERE unixepoch(at) > 0").unwrap();
+        sq.exec(Query::new("INSERT INTO t (at) VALUES ('2023-12-31 23:00:00'), (datetime('now'))")).unwrap();
+        let rows = sq.select(Query::new("SELECT day FROM t WHERE strftime('%Y-%m', at) = '2024-05'")).unwrap();
+        assert_eq!(rows[0]["day"], Value::from("2024-05-01"));
+        // 'now' is still read per call outside the schema
+        assert_eq!(one(&mut sq, "SELECT count(*) FROM t WHERE day < date('now')"), Value::I64(1));
+    }
+
+    #[test]
+    fn system_clock_test() {
+        let mut sq = SQLite::new().create(true, |_| Ok(())).unwrap();
+        // not the global clock, global_clock_test changes it
+        sq.set_clock(Arc::new(SystemClock));
+        sq.use_clock_in_sql().unwrap();
+        let Value::I64(now) = one(&mut sq, "SELECT unixepoch('now')") else { panic!() };
+        assert!((now - Utc::now().timestamp()).abs() <= 1);
+    }
+
+    #[test]
+    fn global_clock_test() {
+        let _lock = GLOBAL_CLOCK_TEST.lock().unwrap_or_else(|e| e.into_inner());
+        let mut sq = SQLite::new().create(true, |sq| sq.use_clock_in_sql()).unwrap();
+        set_global_clock(Arc::new(ManualClock::new(at("2030-01-01T00:00:00Z"))));
+        let timestamp = Timestamp::now();
+        let today = one(&mut sq, "SELECT CURRENT_DATE");
+        reset_global_clock();
+        assert_eq!(timestamp, Timestamp::from_value(1893456000));
+        assert_eq!(today, Value::from("2030-01-01"));
+    }
+}
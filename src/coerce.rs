@@ -0,0 +1,328 @@
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use crate::args::AsText;
+use crate::error::{Error, Result};
+use crate::timestamp::Timestamp;
+use crate::value::Value;
+use crate::value_try_from::mismatch;
+
+/// Lenient conversion from `Value` following SQLite's type affinity rules:
+/// integers widen to reals, numeric text becomes a number, reals become integers
+/// when exact and 0/1, 'true'/'false' become booleans. Conversions that would lose
+/// information fail instead. NULL only converts to `Option`.
+pub trait Coerce: Sized {
+    fn coerce(v: Value) -> Result<Self>;
+}
+
+impl Value {
+    /// Value converted to `T` with SQLite's affinity rules, see `Coerce`.
+    /// `get` and `try_get` stay strict.
+    pub fn coerce<T: Coerce>(self) -> Result<T> {
+        T::coerce(self)
+    }
+}
+
+/// Number in a text the way SQLite reads it, surrounding spaces allowed.
+fn numeric(text: &str) -> Option<Value> {
+    let text = text.trim();
+    if let Ok(v) = text.parse::<i64>() {
+        return Some(Value::I64(v));
+    }
+    // f64 parsing also accepts inf and NaN, SQLite doesn't
+    if text.chars().any(|c| c.is_ascii_alphabetic() && c != 'e' && c != 'E') {
+        return None;
+    }
+    text.parse::<f64>().ok().map(Value::F64)
+}
+
+/// Real that holds an integer exactly.
+fn exact_integer(v: f64) -> Option<i64> {
+    const LIMIT: f64 = 9223372036854775808.0; // 2^63
+    (v.fract() == 0.0 && (-LIMIT..LIMIT).contains(&v)).then_some(v as i64)
+}
+
+fn integer(expected: &str, v: Value) -> Result<i64> {
+    match &v {
+        Value::I64(iv) => Ok(*iv),
+        Value::F64(fv) => exact_integer(*fv).ok_or_else(|| mismatch(expected, &v).reason("not an exact integer").into()),
+        Value::Text(tv) => match numeric(tv) {
+            Some(Value::I64(iv)) => Ok(iv),
+            Some(Value::F64(fv)) => exact_integer(fv).ok_or_else(|| mismatch(expected, &v).reason("not an exact integer").into()),
+            _ => Err(mismatch(expected, &v).reason("not a number").into())
+        },
+        _ => Err(mismatch(expected, &v).into())
+    }
+}
+
+fn real(expected: &str, v: Value) -> Result<f64> {
+    let widen = |iv: i64| match iv as f64 {
+        fv if fv as i128 == iv as i128 => Ok(fv),
+        _ => Err(mismatch(expected, &v).reason("integer not exactly representable").into())
+    };
+    match &v {
+        Value::F64(fv) => Ok(*fv),
+        Value::I64(iv) => widen(*iv),
+        Value::Text(tv) => match numeric(tv) {
+            Some(Value::F64(fv)) => Ok(fv),
+            Some(Value::I64(iv)) => widen(iv),
+            _ => Err(mismatch(expected, &v).reason("not a number").into())
+        },
+        _ => Err(mismatch(expected, &v).into())
+    }
+}
+
+impl Coerce for i8 {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(Value::I64(integer("i8", v)?).try_into()?)
+    }
+}
+
+impl Coerce for u8 {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(Value::I64(integer("u8", v)?).try_into()?)
+    }
+}
+
+impl Coerce for i16 {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(Value::I64(integer("i16", v)?).try_into()?)
+    }
+}
+
+impl Coerce for u16 {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(Value::I64(integer("u16", v)?).try_into()?)
+    }
+}
+
+impl Coerce for i32 {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(Value::I64(integer("i32", v)?).try_into()?)
+    }
+}
+
+impl Coerce for u32 {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(Value::I64(integer("u32", v)?).try_into()?)
+    }
+}
+
+impl Coerce for i64 {
+    fn coerce(v: Value) -> Result<Self> {
+        integer("i64", v)
+    }
+}
+
+impl Coerce for u64 {
+    fn coerce(v: Value) -> Result<Self> {
+        match AsText::<u64>::try_from(v.clone()) {
+            Ok(AsText(n)) => Ok(n),
+            Err(_) => Ok(Value::I64(integer("u64", v)?).try_into()?)
+        }
+    }
+}
+
+impl Coerce for usize {
+    fn coerce(v: Value) -> Result<Self> {
+        match AsText::<usize>::try_from(v.clone()) {
+            Ok(AsText(n)) => Ok(n),
+            Err(_) => Ok(Value::I64(integer("usize", v)?).try_into()?)
+        }
+    }
+}
+
+impl Coerce for i128 {
+    fn coerce(v: Value) -> Result<Self> {
+        match AsText::<i128>::try_from(v.clone()) {
+            Ok(AsText(n)) => Ok(n),
+            Err(_) => Ok(Value::I64(integer("i128", v)?).try_into()?)
+        }
+    }
+}
+
+impl Coerce for u128 {
+    fn coerce(v: Value) -> Result<Self> {
+        match AsText::<u128>::try_from(v.clone()) {
+            Ok(AsText(n)) => Ok(n),
+            Err(_) => Ok(Value::I64(integer("u128", v)?).try_into()?)
+        }
+    }
+}
+
+impl Coerce for char {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(v.try_into()?)
+    }
+}
+
+impl Coerce for f32 {
+    fn coerce(v: Value) -> Result<Self> {
+        let fv = real("f32", v.clone())?;
+        match fv as f32 {
+            narrow if narrow as f64 == fv || fv.is_nan() => Ok(narrow),
+            _ => Err(mismatch("f32", &v).reason("not exactly representable").into())
+        }
+    }
+}
+
+impl Coerce for f64 {
+    fn coerce(v: Value) -> Result<Self> {
+        real("f64", v)
+    }
+}
+
+impl Coerce for bool {
+    fn coerce(v: Value) -> Result<Self> {
+        let flag = match &v {
+            Value::Text(tv) if tv.trim().eq_ignore_ascii_case("true") => return Ok(true),
+            Value::Text(tv) if tv.trim().eq_ignore_ascii_case("false") => return Ok(false),
+            Value::Null | Value::Blob(_) => return Err(mismatch("bool", &v).into()),
+            _ => integer("bool", v.clone())
+        };
+        match flag {
+            Ok(0) => Ok(false),
+            Ok(1) => Ok(true),
+            _ => Err(mismatch("bool", &v).reason("not 0, 1, 'true' or 'false'").into())
+        }
+    }
+}
+
+/// Numbers take their SQL text form, blobs must be UTF-8.
+impl Coerce for String {
+    fn coerce(v: Value) -> Result<Self> {
+        match v {
+            Value::Text(tv) => Ok(tv),
+            Value::I64(iv) => Ok(iv.to_string()),
+            Value::F64(_) => Ok(v.to_sql_literal()),
+            Value::Blob(bv) => String::from_utf8(bv)
+                .map_err(|e| mismatch("String", &Value::Blob(e.into_bytes())).reason("invalid UTF-8").into()),
+            Value::Null => Err(mismatch("String", &v).into())
+        }
+    }
+}
+
+/// Text becomes its UTF-8 bytes.
+impl Coerce for Vec<u8> {
+    fn coerce(v: Value) -> Result<Self> {
+        match v {
+            Value::Text(tv) => Ok(tv.into_bytes()),
+            v => Ok(v.try_into()?)
+        }
+    }
+}
+
+impl Coerce for NaiveDate {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(v.try_into()?)
+    }
+}
+
+impl Coerce for DateTime<Local> {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(v.try_into()?)
+    }
+}
+
+impl Coerce for NaiveDateTime {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(v.try_into()?)
+    }
+}
+
+impl Coerce for NaiveTime {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(v.try_into()?)
+    }
+}
+
+impl Coerce for DateTime<Utc> {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(v.try_into()?)
+    }
+}
+
+impl Coerce for DateTime<FixedOffset> {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(v.try_into()?)
+    }
+}
+
+impl Coerce for Duration {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(v.try_into()?)
+    }
+}
+
+impl Coerce for Timestamp {
+    fn coerce(v: Value) -> Result<Self> {
+        Ok(v.try_into()?)
+    }
+}
+
+/// NULL as `None`.
+impl<T: Coerce> Coerce for Option<T> {
+    fn coerce(v: Value) -> Result<Self> {
+        match v {
+            Value::Null => Ok(None),
+            v => T::coerce(v).map(Some)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+    use crate::field::RowExt;
+    use crate::query::Query;
+
+    #[test]
+    fn coerce_test() {
+        assert_eq!(Value::I64(2).coerce::<f64>().unwrap(), 2.0);
+        assert_eq!(Value::from(" 12 ").coerce::<i64>().unwrap(), 12);
+        assert_eq!(Value::from("1e3").coerce::<u16>().unwrap(), 1000);
+        assert_eq!(Value::from("2.5").coerce::<f32>().unwrap(), 2.5);
+        assert_eq!(Value::F64(3.0).coerce::<i32>().unwrap(), 3);
+        assert_eq!(Value::F64(1.0).coerce::<String>().unwrap(), "1.0");
+        assert_eq!(Value::Null.coerce::<Option<i64>>().unwrap(), None);
+
+        assert!(Value::from("TRUE").coerce::<bool>().unwrap());
+        assert!(!Value::I64(0).coerce::<bool>().unwrap());
+        assert!(Value::I64(2).coerce::<bool>().is_err());
+        assert_eq!(Value::from("18446744073709551615").coerce::<u64>().unwrap(), u64::MAX);
+        assert_eq!(Value::F64(4.0).coerce::<u128>().unwrap(), 4);
+
+        // strict stays the default
+        assert_eq!(Value::I64(2).get::<f64>(), None);
+        assert_eq!(Value::from("12").get::<i64>(), None);
+    }
+
+    #[test]
+    fn lossy_test() {
+        let e = Value::F64(2.5).coerce::<i64>().unwrap_err();
+        assert_eq!(e.to_string(), "cannot convert F64 2.5 to i64: not an exact integer");
+        let e = Value::I64(i64::MAX).coerce::<f64>().unwrap_err();
+        assert_eq!(e.conversion().unwrap().reason.as_deref(), Some("integer not exactly representable"));
+        assert!(Value::from("12abc").coerce::<i64>().is_err());
+        assert!(Value::from("inf").coerce::<f64>().is_err());
+        let e = Value::I64(16_777_217).coerce::<f32>().unwrap_err();
+        assert_eq!(e.conversion().unwrap().reason.as_deref(), Some("not exactly representable"));
+        assert!(Value::from("0.1").coerce::<f32>().is_err());
+        assert!(Value::F64(1e39).coerce::<f32>().is_err());
+        assert_eq!(Value::I64(16_777_216).coerce::<f32>().unwrap(), 16_777_216.0);
+        assert!(Value::from("300").coerce::<u8>().is_err());
+        assert!(Value::Null.coerce::<i64>().is_err());
+    }
+
+    #[test]
+    fn row_coerce_test() {
+        let mut sq = test_database("CREATE TABLE t (price REAL, qty INTEGER)");
+        // '12' is stored as an integer, 'n/a' stays text
+        sq.exec(Query::new("INSERT INTO t VALUES (10, '12'), (NULL, 'n/a')")).unwrap();
+        let rows = sq.select(Query::new("SELECT price, qty FROM t ORDER BY rowid")).unwrap();
+        assert_eq!(rows[0].coerce::<f64>("price").unwrap(), 10.0);
+        assert_eq!(rows[0].coerce::<f32>("qty").unwrap(), 12.0);
+        assert_eq!(rows[1].coerce::<Option<f64>>("price").unwrap(), None);
+        let e = rows[1].coerce::<i64>("qty").unwrap_err();
+        assert_eq!(e.to_string(), "cannot convert Text 'n/a' to i64: not a number (column qty)");
+    }
+}
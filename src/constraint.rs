@@ -0,0 +1,268 @@
+use serde::{Deserialize, Serialize};
+use sqlite3_sys::{
+    SQLITE_CONSTRAINT_CHECK,
+    SQLITE_CONSTRAINT_DATATYPE,
+    SQLITE_CONSTRAINT_FOREIGNKEY,
+    SQLITE_CONSTRAINT_NOTNULL,
+    SQLITE_CONSTRAINT_PRIMARYKEY,
+    SQLITE_CONSTRAINT_ROWID,
+    SQLITE_CONSTRAINT_TRIGGER,
+    SQLITE_CONSTRAINT_UNIQUE,
+};
+use crate::db::SQLite;
+use crate::error::{Error, Result};
+use crate::query::{quote_identifier, Query};
+use crate::value::Value;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConstraintKind {
+    Unique,
+    PrimaryKey,
+    NotNull,
+    Check,
+    ForeignKey,
+    /// Value of the wrong type in a STRICT table.
+    Datatype,
+    /// RAISE(ABORT|FAIL|ROLLBACK) in a trigger.
+    Trigger,
+    Other,
+}
+
+/// Structured form of a failed constraint, parsed from the extended code and the message.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ConstraintViolation {
+    pub kind: ConstraintKind,
+    pub table: Option<String>,
+    pub columns: Vec<String>,
+    /// Index name of UNIQUE and PRIMARY KEY constraints, name or expression of CHECK constraints.
+    pub constraint_name: Option<String>,
+    /// Rows from `PRAGMA foreign_key_check`, filled by `SQLite::constraint_violation`.
+    pub foreign_key_rows: Vec<ForeignKeyViolation>,
+    /// The SQLite message it was parsed from.
+    pub message: String,
+}
+
+/// One row of `PRAGMA foreign_key_check`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ForeignKeyViolation {
+    /// Child table holding the row.
+    pub table: String,
+    /// Row id of the row, None for WITHOUT ROWID tables.
+    pub rowid: Option<i64>,
+    /// Referenced (parent) table.
+    pub parent: String,
+    /// Child columns of the foreign key.
+    pub columns: Vec<String>,
+}
+
+/// `table.a, table.b` into the table and its columns.
+fn table_columns(text: &str) -> (Option<String>, Vec<String>) {
+    let mut table = None;
+    let mut columns = Vec::new();
+    for item in text.split(", ") {
+        match item.split_once('.') {
+            Some((t, c)) => {
+                table = Some(t.to_string());
+                columns.push(c.to_string());
+            },
+            None => columns.push(item.to_string())
+        }
+    }
+    (table, columns)
+}
+
+impl Error {
+    /// Constraint violation details, None when the error is not a constraint failure.
+    /// Foreign key failures carry no table or columns, see `SQLite::constraint_violation`.
+    pub fn constraint_violation(&self) -> Option<ConstraintViolation> {
+        let e = self.sqlite()?;
+        if !self.is_constraint() {
+            return None;
+        }
+        let message = e.message.as_str();
+        let detail = message.split_once(" failed: ").map(|(_, detail)| detail.trim());
+        let mut violation = ConstraintViolation {
+            kind: ConstraintKind::Other,
+            table: None,
+            columns: Vec::new(),
+            constraint_name: None,
+            foreign_key_rows: Vec::new(),
+            message: message.to_string(),
+        };
+        violation.kind = match e.extended_code {
+            SQLITE_CONSTRAINT_UNIQUE => ConstraintKind::Unique,
+            SQLITE_CONSTRAINT_PRIMARYKEY | SQLITE_CONSTRAINT_ROWID => ConstraintKind::PrimaryKey,
+            SQLITE_CONSTRAINT_NOTNULL => ConstraintKind::NotNull,
+            SQLITE_CONSTRAINT_CHECK => ConstraintKind::Check,
+            SQLITE_CONSTRAINT_FOREIGNKEY => ConstraintKind::ForeignKey,
+            SQLITE_CONSTRAINT_DATATYPE => ConstraintKind::Datatype,
+            SQLITE_CONSTRAINT_TRIGGER => ConstraintKind::Trigger,
+            _ => ConstraintKind::Other
+        };
+        match (violation.kind, detail) {
+            (ConstraintKind::Unique | ConstraintKind::PrimaryKey, Some(detail)) => {
+                // unique indexes on expressions are reported by name
+                match detail.strip_prefix("index '").and_then(|d| d.strip_suffix('\'')) {
+                    Some(index) => violation.constraint_name = Some(index.to_string()),
+                    None => (violation.table, violation.columns) = table_columns(detail)
+                }
+            },
+            (ConstraintKind::NotNull, Some(detail)) => (violation.table, violation.columns) = table_columns(detail),
+            (ConstraintKind::Check, Some(detail)) => violation.constraint_name = Some(detail.to_string()),
+            // cannot store TEXT value in INTEGER column t.a
+            (ConstraintKind::Datatype, _) => {
+                if let Some((_, column)) = message.rsplit_once(" column ") {
+                    (violation.table, violation.columns) = table_columns(column);
+                }
+            },
+            _ => ()
+        }
+        Some(violation)
+    }
+}
+
+impl SQLite {
+    /// Rows violating foreign keys (`PRAGMA foreign_key_check`), for one table or all of them.
+    pub fn foreign_key_check(&mut self, table: Option<&str>) -> Result<Vec<ForeignKeyViolation>> {
+        let sql = match table {
+            Some(table) => format!("PRAGMA foreign_key_check({})", quote_identifier(table)),
+            None => "PRAGMA foreign_key_check".to_string()
+        };
+        let mut rows = Vec::new();
+        self.select_each(Query::new(&sql), |_, values| {
+            let mut values = values.into_iter();
+            let mut next = || values.next().unwrap_or(Value::Null);
+            let table = next().get::<String>().unwrap_or_default();
+            let rowid = next().get::<i64>();
+            let parent = next().get::<String>().unwrap_or_default();
+            let id = next().get::<i64>().unwrap_or_default();
+            rows.push((table, rowid, parent, id));
+            Ok(())
+        })?;
+        let schema = self.schema()?;
+        Ok(rows
+            .into_iter()
+            .map(|(table, rowid, parent, id)| {
+                let columns = schema
+                    .table(&table)
+                    .and_then(|t| t.foreign_keys.iter().find(|fk| fk.id == id))
+                    .map(|fk| fk.from.clone())
+                    .unwrap_or_default();
+                ForeignKeyViolation { table, rowid, parent, columns }
+            })
+            .collect())
+    }
+
+    /// Constraint violation details completed from the schema: the index of a UNIQUE
+    /// or PRIMARY KEY constraint and, with `foreign_key_rows`, the rows failing foreign keys.
+    /// Immediate foreign key failures are rolled back, so rows are only found for
+    /// deferred constraints inside an open transaction.
+    pub fn constraint_violation(&mut self, e: &Error, foreign_key_rows: bool) -> Result<Option<ConstraintViolation>> {
+        let Some(mut violation) = e.constraint_violation() else {
+            return Ok(None);
+        };
+        match violation.kind {
+            ConstraintKind::Unique | ConstraintKind::PrimaryKey => {
+                let schema = self.schema()?;
+                match (&violation.table, &violation.constraint_name) {
+                    (Some(table), None) => {
+                        let index = schema.table(table).and_then(|t| {
+                            t.indexes.iter().find(|i| {
+                                i.unique && !i.partial && i.columns.len() == violation.columns.len()
+                                    && i.columns.iter().zip(&violation.columns).all(|(ic, c)| ic.name.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(c)))
+                            })
+                        });
+                        violation.constraint_name = index.map(|i| i.name.clone());
+                    },
+                    (None, Some(index)) => {
+                        violation.table = schema.tables.iter().find(|t| t.index(index).is_some()).map(|t| t.name.clone());
+                    },
+                    _ => ()
+                }
+            },
+            ConstraintKind::ForeignKey if foreign_key_rows => {
+                violation.foreign_key_rows = self.foreign_key_check(None)?;
+                if let [row, ..] = violation.foreign_key_rows.as_slice() {
+                    violation.table = Some(row.table.clone());
+                    violation.columns = row.columns.clone();
+                }
+            },
+            _ => ()
+        }
+        Ok(Some(violation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+
+    static CREATE_SCHEMA: &str = r#"
+        CREATE TABLE person (
+            id INTEGER PRIMARY KEY,
+            email TEXT NOT NULL UNIQUE,
+            first TEXT, last TEXT,
+            age INT CONSTRAINT adult CHECK (age >= 18),
+            UNIQUE (first, last)
+        );
+        CREATE UNIQUE INDEX person_lower_email ON person (lower(email));
+        CREATE TABLE note (id INTEGER PRIMARY KEY, person INT REFERENCES person(id) DEFERRABLE INITIALLY DEFERRED);
+        CREATE TABLE strict_note (id INTEGER PRIMARY KEY, n INT) STRICT;
+        PRAGMA foreign_keys = ON;
+        INSERT INTO person (id, email, first, last, age) VALUES (1, 'a@x', 'Ann', 'Lee', 30);
+    "#;
+
+    fn violation(sq: &mut SQLite, sql: &str) -> ConstraintViolation {
+        let e = sq.exec(Query::new(sql)).unwrap_err();
+        sq.constraint_violation(&e, true).unwrap().unwrap()
+    }
+
+    #[test]
+    fn unique_and_not_null_test() {
+        let mut sq = test_database(CREATE_SCHEMA);
+
+        let v = violation(&mut sq, "INSERT INTO person (email, first, last) VALUES ('b@x', 'Ann', 'Lee')");
+        assert_eq!(v.kind, ConstraintKind::Unique);
+        assert_eq!(v.table.as_deref(), Some("person"));
+        assert_eq!(v.columns, vec!["first", "last"]);
+        assert_eq!(v.constraint_name.as_deref(), Some("sqlite_autoindex_person_2"));
+
+        // the expression index is checked before the column one
+        let v = violation(&mut sq, "INSERT INTO person (email) VALUES ('A@X')");
+        assert_eq!(v.constraint_name.as_deref(), Some("person_lower_email"));
+        assert_eq!(v.table.as_deref(), Some("person"));
+
+        let v = violation(&mut sq, "INSERT INTO person (id, email) VALUES (1, 'c@x')");
+        assert_eq!(v.kind, ConstraintKind::PrimaryKey);
+        assert_eq!(v.columns, vec!["id"]);
+
+        let v = violation(&mut sq, "INSERT INTO person (first) VALUES ('x')");
+        assert_eq!(v.kind, ConstraintKind::NotNull);
+        assert_eq!((v.table.as_deref(), v.columns), (Some("person"), vec!["email".to_string()]));
+    }
+
+    #[test]
+    fn check_datatype_and_foreign_key_test() {
+        let mut sq = test_database(CREATE_SCHEMA);
+
+        let v = violation(&mut sq, "INSERT INTO person (email, age) VALUES ('d@x', 10)");
+        assert_eq!(v.kind, ConstraintKind::Check);
+        assert_eq!(v.constraint_name.as_deref(), Some("adult"));
+
+        let v = violation(&mut sq, "INSERT INTO strict_note (n) VALUES ('x')");
+        assert_eq!(v.kind, ConstraintKind::Datatype);
+        assert_eq!((v.table.as_deref(), v.columns), (Some("strict_note"), vec!["n".to_string()]));
+
+        sq.exec_command("BEGIN; INSERT INTO note (person) VALUES (7)").unwrap();
+        let v = violation(&mut sq, "COMMIT");
+        assert_eq!(v.kind, ConstraintKind::ForeignKey);
+        assert_eq!(v.foreign_key_rows, vec![ForeignKeyViolation { table: "note".into(), rowid: Some(1), parent: "person".into(), columns: vec!["person".into()] }]);
+        assert_eq!(v.table.as_deref(), Some("note"));
+        sq.exec_command("ROLLBACK").unwrap();
+
+        assert!(Error::from("not sqlite").constraint_violation().is_none());
+        let e = sq.exec(Query::new("SELECT nope")).unwrap_err();
+        assert!(e.constraint_violation().is_none());
+    }
+}
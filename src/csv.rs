@@ -0,0 +1,424 @@
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use crate::args::Args;
+use crate::db::SQLite;
+use crate::encoding::{base64_decode, base64_encode, hex_decode, hex_encode};
+use crate::error::Result;
+use crate::query::{quote_identifier, Query};
+use crate::value::Value;
+
+/// Text encoding of blobs in CSV fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlobEncoding {
+    #[default]
+    Base64,
+    Hex,
+}
+
+/// Options of CSV import and export.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvOptions {
+    /// First record holds column names.
+    pub header: bool,
+    pub delimiter: u8,
+    pub quote: u8,
+    /// Unquoted field text standing for NULL.
+    pub null: String,
+    pub blob: BlobEncoding,
+    /// Table columns for the CSV fields, in field order. An empty name skips the field.
+    /// When not given, the header or the table's own column order is used.
+    pub columns: Option<Vec<String>>,
+    /// Convert numeric text to integers and reals on import.
+    pub infer_types: bool,
+    /// Create a missing table on import, with column types inferred when `infer_types` is set.
+    pub create_table: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            header: true,
+            delimiter: b',',
+            quote: b'"',
+            null: String::new(),
+            blob: BlobEncoding::Base64,
+            columns: None,
+            infer_types: false,
+            create_table: false,
+        }
+    }
+}
+
+/// One parsed CSV field, quoted fields are never NULL.
+struct Field {
+    text: String,
+    quoted: bool,
+}
+
+/// Parsed record with the line it starts on.
+struct Record {
+    line: usize,
+    fields: Vec<Field>,
+}
+
+fn parse_records(text: &str, options: &CsvOptions) -> Result<Vec<Record>> {
+    let delimiter = options.delimiter as char;
+    let quote = options.quote as char;
+    let mut records = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = Vec::new();
+        loop {
+            let mut field = Field { text: String::new(), quoted: false };
+            if chars.peek() == Some(&quote) {
+                chars.next();
+                field.quoted = true;
+                loop {
+                    match chars.next() {
+                        Some(c) if c == quote => {
+                            if chars.peek() == Some(&quote) {
+                                chars.next();
+                                field.text.push(quote);
+                            } else {
+                                break;
+                            }
+                        },
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.text.push(c);
+                        },
+                        None => {
+                            let message = format!("csv: unterminated quoted field in line {start}");
+                            return Err(message.as_str().into());
+                        }
+                    }
+                }
+            }
+            while let Some(&c) = chars.peek() {
+                if c == delimiter || c == '\n' || c == '\r' {
+                    break;
+                }
+                field.text.push(c);
+                chars.next();
+            }
+            fields.push(field);
+            match chars.next() {
+                Some(c) if c == delimiter => continue,
+                Some('\r') if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                },
+                _ => ()
+            }
+            line += 1;
+            break;
+        }
+        // blank lines are skipped
+        if fields.len() == 1 && !fields[0].quoted && fields[0].text.is_empty() {
+            continue;
+        }
+        records.push(Record { line: start, fields });
+    }
+    Ok(records)
+}
+
+fn write_field<W: Write>(writer: &mut W, text: &str, force_quote: bool, options: &CsvOptions) -> Result<()> {
+    let quote = options.quote as char;
+    let needs_quote = force_quote || text.chars().any(|c| c == options.delimiter as char || c == quote || c == '\n' || c == '\r');
+    match needs_quote {
+        true => {
+            let escaped = text.replace(quote, &format!("{quote}{quote}"));
+            write!(writer, "{quote}{escaped}{quote}")?
+        },
+        false => writer.write_all(text.as_bytes())?
+    }
+    Ok(())
+}
+
+fn write_record<W: Write>(writer: &mut W, values: &[Value], options: &CsvOptions) -> Result<()> {
+    for (idx, value) in values.iter().enumerate() {
+        if idx > 0 {
+            writer.write_all(&[options.delimiter])?;
+        }
+        match value {
+            Value::Null => write_field(writer, &options.null, false, options)?,
+            Value::I64(v) => write_field(writer, &v.to_string(), false, options)?,
+            Value::F64(v) => write_field(writer, &format!("{v:?}"), false, options)?,
+            // text and blobs equal to the NULL marker are quoted to keep them apart from NULL
+            Value::Text(v) => write_field(writer, v, *v == options.null, options)?,
+            Value::Blob(v) => {
+                let text = match options.blob {
+                    BlobEncoding::Base64 => base64_encode(v),
+                    BlobEncoding::Hex => hex_encode(v)
+                };
+                write_field(writer, &text, text == options.null, options)?
+            }
+        }
+    }
+    writer.write_all(b"\r\n")?;
+    Ok(())
+}
+
+/// Numeric value of text when it converts back to the same text.
+fn infer_value(text: &str) -> Option<Value> {
+    if let Ok(v) = text.parse::<i64>() && v.to_string() == text {
+        return Some(Value::I64(v));
+    }
+    // leading zeros are kept as text (codes, phone numbers)
+    let digits = text.trim_start_matches(['+', '-']);
+    let leading_zero = digits.len() > 1 && digits.starts_with('0') && digits.as_bytes()[1].is_ascii_digit();
+    let numeric = !text.is_empty() && !leading_zero && text.chars().all(|c| c.is_ascii_digit() || "+-.eE".contains(c));
+    match numeric {
+        true => text.parse::<f64>().ok().map(Value::F64),
+        false => None
+    }
+}
+
+/// Column type for table creation: INTEGER or REAL when every value fits, TEXT otherwise.
+fn infer_type(records: &[Record], idx: usize, options: &CsvOptions) -> &'static str {
+    let values = records
+        .iter()
+        .filter_map(|r| r.fields.get(idx))
+        .filter(|f| f.quoted || f.text != options.null)
+        .map(|f| infer_value(&f.text))
+        .collect::<Vec<_>>();
+    if values.is_empty() || values.iter().any(Option::is_none) {
+        return "TEXT";
+    }
+    match values.iter().all(|v| matches!(v, Some(Value::I64(_)))) {
+        true => "INTEGER",
+        false => "REAL"
+    }
+}
+
+impl SQLite {
+    /// Write the result of a query as CSV, row by row.
+    /// Returns the number of written rows.
+    pub fn export_csv<W: Write>(&mut self, query: Query, writer: W, options: &CsvOptions) -> Result<usize> {
+        let mut rows = 0;
+        // both callbacks write, the header one only before the first row
+        let writer = RefCell::new(writer);
+        self.select_stream(
+            query,
+            |columns| {
+                if options.header {
+                    let names = columns.iter().map(|c| Value::from(c.as_str())).collect::<Vec<_>>();
+                    write_record(&mut *writer.borrow_mut(), &names, options)?;
+                }
+                Ok(())
+            },
+            |_, values| {
+                rows += 1;
+                write_record(&mut *writer.borrow_mut(), &values, options)
+            })?;
+        writer.borrow_mut().flush()?;
+        Ok(rows)
+    }
+
+    /// Read CSV into a table with one prepared statement inside a transaction.
+    /// A created table is part of the transaction, so a failed import leaves nothing behind.
+    /// Inside a transaction of the caller, rolling back is left to the caller.
+    /// Returns the number of imported rows.
+    pub fn import_csv<R: Read>(&mut self, mut reader: R, table: &str, options: &CsvOptions) -> Result<usize> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        let mut records = parse_records(&text, options)?;
+
+        let header = match options.header && !records.is_empty() {
+            true => Some(records.remove(0).fields.into_iter().map(|f| f.text).collect::<Vec<_>>()),
+            false => None
+        };
+        let existing = self.schema()?.table(table).cloned();
+        let columns = match (&options.columns, header, &existing) {
+            (Some(columns), _, _) => columns.clone(),
+            (None, Some(header), _) => header,
+            (None, None, Some(existing)) => existing.columns.iter().filter(|c| !c.is_generated()).map(|c| c.name.clone()).collect(),
+            (None, None, None) => return Err("import_csv: column names unknown, table does not exist".into())
+        };
+        let used = (0..columns.len()).filter(|&i| !columns[i].is_empty()).collect::<Vec<_>>();
+        if used.is_empty() {
+            return Err("import_csv: no columns to import".into());
+        }
+
+        let mut create = None;
+        let types = match existing {
+            Some(existing) => used
+                .iter()
+                .map(|&i| existing.column(&columns[i]).map(|c| c.decl_type.to_ascii_uppercase()).unwrap_or_default())
+                .collect::<Vec<_>>(),
+            None if options.create_table => {
+                let types = used
+                    .iter()
+                    .map(|&i| match options.infer_types {
+                        true => infer_type(&records, i, options).to_string(),
+                        false => "TEXT".to_string()
+                    })
+                    .collect::<Vec<_>>();
+                let definitions = used
+                    .iter()
+                    .zip(&types)
+                    .map(|(&i, t)| format!("{} {t}", quote_identifier(&columns[i])))
+                    .collect::<Vec<_>>();
+                create = Some(format!("CREATE TABLE {} ({});", quote_identifier(table), definitions.join(", ")));
+                types
+            },
+            None => {
+                let message = format!("import_csv: no such table: {table}");
+                return Err(message.as_str().into());
+            }
+        };
+
+        let mut rows = Vec::with_capacity(records.len());
+        for record in &records {
+            if record.fields.len() != columns.len() {
+                let message = format!("import_csv: line {}: expected {} fields, got: {}", record.line, columns.len(), record.fields.len());
+                return Err(message.as_str().into());
+            }
+            let mut values = Vec::with_capacity(used.len());
+            for (&i, decl_type) in used.iter().zip(&types) {
+                let field = &record.fields[i];
+                let value = if !field.quoted && field.text == options.null {
+                    Value::Null
+                } else if decl_type.contains("BLOB") {
+                    let data = match options.blob {
+                        BlobEncoding::Base64 => base64_decode(&field.text),
+                        BlobEncoding::Hex => hex_decode(&field.text)
+                    };
+                    match data {
+                        Some(data) => Value::Blob(data),
+                        None => {
+                            let message = format!("import_csv: line {}: invalid blob in column {}", record.line, columns[i]);
+                            return Err(message.as_str().into());
+                        }
+                    }
+                } else if options.infer_types && !field.quoted {
+                    infer_value(&field.text).unwrap_or_else(|| Value::from(field.text.as_str()))
+                } else {
+                    Value::from(field.text.as_str())
+                };
+                values.push(value);
+            }
+            rows.push(Args::from(values));
+        }
+
+        let names = used.iter().map(|&i| quote_identifier(&columns[i])).collect::<Vec<_>>();
+        let sql = format!("INSERT INTO {} ({}) VALUES ({})", quote_identifier(table), names.join(", "), vec!["?"; names.len()].join(", "));
+        let own_transaction = !self.in_transaction();
+        if own_transaction {
+            self.exec_command("BEGIN")?;
+        }
+        let result = match &create {
+            Some(create) => self.exec_command(create),
+            None => Ok(())
+        }.and_then(|_| self.execute_many(&sql, rows));
+        match (result, own_transaction) {
+            (Ok(result), true) => self.exec_command("COMMIT").map(|_| result.len()),
+            (Ok(result), false) => Ok(result.len()),
+            (Err(e), own_transaction) => {
+                if own_transaction {
+                    let _ = self.exec_command("ROLLBACK");
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+
+    static CREATE_SCHEMA: &str = "CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT, price REAL, data BLOB);";
+
+    #[test]
+    fn export_test() {
+        let mut sq = test_database(CREATE_SCHEMA);
+        sq.execute_many("INSERT INTO item (name, price, data) VALUES (?, ?, ?)", vec![
+            Args::new().arg("plain").arg(1.5).arg(vec![1u8, 2, 3]),
+            Args::new().arg("with, comma \"quoted\"").arg(2.0).arg(()),
+            Args::new().arg("").arg(()).arg(()),
+        ]).unwrap();
+
+        let mut out = Vec::new();
+        let rows = sq.export_csv(Query::new("SELECT name, price, data FROM item ORDER BY id"), &mut out, &CsvOptions::default()).unwrap();
+        assert_eq!(rows, 3);
+        assert_eq!(String::from_utf8(out).unwrap(),
+            "name,price,data\r\nplain,1.5,AQID\r\n\"with, comma \"\"quoted\"\"\",2.0,\r\n\"\",,\r\n");
+
+        let mut out = Vec::new();
+        let options = CsvOptions { delimiter: b';', null: "NULL".into(), blob: BlobEncoding::Hex, header: false, ..Default::default() };
+        sq.export_csv(Query::new("SELECT data, price FROM item WHERE id < 3 ORDER BY id"), &mut out, &options).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "010203;1.5\r\nNULL;2.0\r\n");
+
+        let mut out = Vec::new();
+        sq.export_csv(Query::new("SELECT name FROM item WHERE 0"), &mut out, &CsvOptions::default()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "name\r\n");
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let mut sq = test_database(CREATE_SCHEMA);
+        let csv = "id,name,price,data\n1,\"multi\nline\",1.25,AQID\n2,,3,\n3,\"\",,\"\"\n";
+        let rows = sq.import_csv(csv.as_bytes(), "item", &CsvOptions::default()).unwrap();
+        assert_eq!(rows, 3);
+
+        let result = sq.select(Query::new("SELECT * FROM item ORDER BY id")).unwrap();
+        assert_eq!(result[0]["name"], Value::from("multi\nline"));
+        assert_eq!(result[0]["price"], Value::F64(1.25));
+        assert_eq!(result[0]["data"], Value::Blob(vec![1, 2, 3]));
+        assert_eq!(result[1]["name"], Value::Null);
+        assert_eq!(result[1]["price"], Value::F64(3.0));
+        assert_eq!(result[2]["name"], Value::from(""));
+        assert_eq!(result[2]["data"], Value::Blob(vec![]));
+
+        let mut out = Vec::new();
+        sq.export_csv(Query::new("SELECT * FROM item ORDER BY id"), &mut out, &CsvOptions::default()).unwrap();
+        let mut copy = test_database(CREATE_SCHEMA);
+        copy.import_csv(out.as_slice(), "item", &CsvOptions::default()).unwrap();
+        assert_eq!(copy.select(Query::new("SELECT * FROM item ORDER BY id")).unwrap(), result);
+    }
+
+    #[test]
+    fn create_and_mapping_test() {
+        let mut sq = test_database(CREATE_SCHEMA);
+        let csv = "a;b;c;skip\r\n1;x;2.5;?\r\n007;y;3;?\r\n";
+        let options = CsvOptions {
+            delimiter: b';',
+            columns: Some(vec!["num".into(), "label".into(), "score".into(), "".into()]),
+            infer_types: true,
+            create_table: true,
+            ..Default::default()
+        };
+        assert_eq!(sq.import_csv(csv.as_bytes(), "imported", &options).unwrap(), 2);
+        let table = sq.schema().unwrap().table("imported").cloned().unwrap();
+        let types = table.columns.iter().map(|c| c.decl_type.as_str()).collect::<Vec<_>>();
+        assert_eq!(types, vec!["TEXT", "TEXT", "REAL"]);
+        let result = sq.select(Query::new("SELECT * FROM imported ORDER BY label")).unwrap();
+        assert_eq!(result[1]["num"], Value::from("007"));
+        assert_eq!(result[1]["score"], Value::F64(3.0));
+
+        // without a header the fields map to the stored columns, generated ones are skipped
+        sq.exec_command("CREATE TABLE priced (name TEXT, price REAL, total REAL AS (price * 2))").unwrap();
+        let options = CsvOptions { header: false, ..Default::default() };
+        assert_eq!(sq.import_csv("a,1.5\n".as_bytes(), "priced", &options).unwrap(), 1);
+        assert_eq!(sq.select(Query::new("SELECT total FROM priced")).unwrap()[0]["total"], Value::F64(3.0));
+
+        assert!(sq.import_csv("a,b\n1\n".as_bytes(), "item", &CsvOptions::default()).is_err());
+        assert!(sq.import_csv("x\n1\n".as_bytes(), "missing", &CsvOptions::default()).is_err());
+        assert!(sq.import_csv("name\n\"open\n".as_bytes(), "item", &CsvOptions::default()).is_err());
+
+        // a failed import doesn't leave the created table behind
+        let options = CsvOptions { create_table: true, ..Default::default() };
+        assert!(sq.import_csv("a,b\n1,2\n3\n".as_bytes(), "broken", &options).is_err());
+        let before = sq.select(Query::new("SELECT * FROM item")).unwrap().len();
+        assert!(sq.import_csv("id,name\n100,a\n100,b\n".as_bytes(), "item", &CsvOptions::default()).is_err());
+        assert_eq!(sq.select(Query::new("SELECT * FROM item")).unwrap().len(), before);
+        assert!(sq.schema().unwrap().table("broken").is_none());
+        assert!(!sq.in_transaction());
+    }
+}
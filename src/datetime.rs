@@ -0,0 +1,500 @@
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use crate::args::ValueConvertible;
+use crate::error::ConversionError;
+use crate::value::Value;
+
+/// Julian day of 1970-01-01 00:00 UTC.
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2440587.5;
+/// Largest Julian day SQLite accepts (9999-12-31 23:59:59.999).
+const MAX_JULIAN_DAY: f64 = 5373484.499999;
+
+/// A time value in one of the forms SQLite's date and time functions accept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeValue {
+    /// Text without an offset, a wall-clock time.
+    Naive(NaiveDateTime),
+    /// Text with `Z` or `±HH:MM`, a Unix epoch integer or a Julian day real.
+    Fixed(DateTime<FixedOffset>),
+}
+
+impl TimeValue {
+    /// Read a time value:
+    /// - `YYYY-MM-DD`, optionally followed by `T` or a space and `HH:MM[:SS[.SSS]]`
+    /// - `HH:MM[:SS[.SSS]]` alone, on 2000-01-01
+    /// - either followed by `Z` or `±HH:MM`
+    /// - a Julian day as a real or numeric text
+    /// - a Unix epoch (seconds) as an integer
+    pub fn parse(v: &Value) -> Option<TimeValue> {
+        match v {
+            Value::I64(secs) => DateTime::from_timestamp(*secs, 0).map(|t| TimeValue::Fixed(t.fixed_offset())),
+            Value::F64(day) => TimeValue::from_julian_day(*day),
+            Value::Text(text) => TimeValue::parse_text(text),
+            _ => None
+        }
+    }
+
+    pub fn parse_text(text: &str) -> Option<TimeValue> {
+        let text = text.trim();
+        if let Ok(day) = text.parse::<f64>() {
+            return TimeValue::from_julian_day(day);
+        }
+        let (text, offset) = split_offset(text)?;
+        let (date, time) = match text.split_once(['T', 't', ' ']) {
+            Some((date, time)) => (parse_date(date)?, parse_time(time.trim_start())?),
+            None => match parse_date(text) {
+                Some(date) => (date, NaiveTime::MIN),
+                None => (NaiveDate::from_ymd_opt(2000, 1, 1)?, parse_time(text)?)
+            }
+        };
+        let naive = date.and_time(time);
+        match offset {
+            Some(offset) => naive.and_local_timezone(offset).single().map(TimeValue::Fixed),
+            None => Some(TimeValue::Naive(naive))
+        }
+    }
+
+    pub fn from_julian_day(day: f64) -> Option<TimeValue> {
+        if !(0.0..=MAX_JULIAN_DAY).contains(&day) {
+            return None;
+        }
+        let millis = ((day - UNIX_EPOCH_JULIAN_DAY) * 86_400_000.0).round() as i64;
+        DateTime::from_timestamp_millis(millis).map(|t| TimeValue::Fixed(t.fixed_offset()))
+    }
+
+    /// Date as written, UTC for epochs and Julian days.
+    pub fn date(&self) -> NaiveDate {
+        match self {
+            TimeValue::Naive(t) => t.date(),
+            TimeValue::Fixed(t) => t.date_naive()
+        }
+    }
+}
+
+/// How a date, time or duration is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeFormat {
+    /// ISO-8601 text as SQLite writes it, `2024-05-01 10:00:00.250`.
+    #[default]
+    Iso,
+    /// Integer seconds since 1970-01-01 UTC, sub-seconds are dropped.
+    UnixSeconds,
+    /// Integer milliseconds since 1970-01-01 UTC.
+    UnixMillis,
+    /// Real days since noon UTC on November 24, 4714 B.C., about millisecond precision.
+    JulianDay,
+}
+
+/// Chrono types storable in any `TimeFormat`.
+/// Times without an offset count as UTC in numeric formats, times of day are on 2000-01-01
+/// like in SQLite, durations are stored as a length.
+pub trait TimeType: Sized {
+    /// Type name for conversion errors.
+    const NAME: &'static str;
+
+    fn encode(&self, format: TimeFormat) -> Value;
+
+    /// Read any form `TimeValue::parse` accepts, with integers as milliseconds for `UnixMillis`.
+    fn decode(v: &Value, format: TimeFormat) -> Option<Self>;
+}
+
+/// Point in time types, stored through their `TimeValue`.
+trait TimePoint: Sized {
+    const NAME: &'static str;
+    const ISO: &'static str;
+    fn to_time_value(&self) -> TimeValue;
+    fn from_time_value(t: TimeValue) -> Option<Self>;
+}
+
+impl<T: TimePoint> TimeType for T {
+    const NAME: &'static str = T::NAME;
+
+    fn encode(&self, format: TimeFormat) -> Value {
+        let t = self.to_time_value();
+        let utc = match t {
+            TimeValue::Naive(t) => t.and_utc(),
+            TimeValue::Fixed(t) => t.to_utc()
+        };
+        match format {
+            TimeFormat::Iso => match t {
+                TimeValue::Naive(t) => Value::Text(t.format(T::ISO).to_string()),
+                TimeValue::Fixed(t) => Value::Text(t.format(T::ISO).to_string())
+            },
+            TimeFormat::UnixSeconds => Value::I64(utc.timestamp()),
+            TimeFormat::UnixMillis => Value::I64(utc.timestamp_millis()),
+            TimeFormat::JulianDay => Value::F64(utc.timestamp_millis() as f64 / 86_400_000.0 + UNIX_EPOCH_JULIAN_DAY)
+        }
+    }
+
+    fn decode(v: &Value, format: TimeFormat) -> Option<Self> {
+        let t = match (format, v) {
+            (TimeFormat::UnixMillis, Value::I64(millis)) => TimeValue::Fixed(DateTime::from_timestamp_millis(*millis)?.fixed_offset()),
+            _ => TimeValue::parse(v)?
+        };
+        T::from_time_value(t)
+    }
+}
+
+impl TimeValue {
+    /// Date and time as written, UTC for epochs and Julian days.
+    fn naive(self) -> NaiveDateTime {
+        match self {
+            TimeValue::Naive(t) => t,
+            TimeValue::Fixed(t) => t.naive_local()
+        }
+    }
+
+    /// Instant, times without an offset count as UTC.
+    fn fixed(self) -> DateTime<FixedOffset> {
+        match self {
+            TimeValue::Naive(t) => t.and_utc().fixed_offset(),
+            TimeValue::Fixed(t) => t
+        }
+    }
+}
+
+impl TimePoint for NaiveDate {
+    const NAME: &'static str = "NaiveDate";
+    const ISO: &'static str = "%Y-%m-%d";
+    fn to_time_value(&self) -> TimeValue {
+        TimeValue::Naive(self.and_time(NaiveTime::MIN))
+    }
+    fn from_time_value(t: TimeValue) -> Option<Self> {
+        Some(t.date())
+    }
+}
+
+impl TimePoint for NaiveDateTime {
+    const NAME: &'static str = "NaiveDateTime";
+    const ISO: &'static str = "%Y-%m-%d %H:%M:%S%.f";
+    fn to_time_value(&self) -> TimeValue {
+        TimeValue::Naive(*self)
+    }
+    fn from_time_value(t: TimeValue) -> Option<Self> {
+        Some(t.naive())
+    }
+}
+
+impl TimePoint for NaiveTime {
+    const NAME: &'static str = "NaiveTime";
+    const ISO: &'static str = "%H:%M:%S%.f";
+    fn to_time_value(&self) -> TimeValue {
+        TimeValue::Naive(NaiveDate::from_ymd_opt(2000, 1, 1).unwrap().and_time(*self))
+    }
+    fn from_time_value(t: TimeValue) -> Option<Self> {
+        Some(t.naive().time())
+    }
+}
+
+impl TimePoint for DateTime<Utc> {
+    const NAME: &'static str = "DateTime<Utc>";
+    const ISO: &'static str = "%Y-%m-%d %H:%M:%S%.fZ";
+    fn to_time_value(&self) -> TimeValue {
+        TimeValue::Fixed(self.fixed_offset())
+    }
+    fn from_time_value(t: TimeValue) -> Option<Self> {
+        Some(t.fixed().to_utc())
+    }
+}
+
+/// Numeric formats keep the instant only, it reads back with a UTC offset.
+impl TimePoint for DateTime<FixedOffset> {
+    const NAME: &'static str = "DateTime<FixedOffset>";
+    const ISO: &'static str = "%Y-%m-%d %H:%M:%S%.f%:z";
+    fn to_time_value(&self) -> TimeValue {
+        TimeValue::Fixed(*self)
+    }
+    fn from_time_value(t: TimeValue) -> Option<Self> {
+        Some(t.fixed())
+    }
+}
+
+/// ISO text carries the local offset, so times repeated at a DST change stay apart.
+/// Text without an offset, as older versions wrote it, reads as local time.
+impl TimePoint for DateTime<Local> {
+    const NAME: &'static str = "DateTime<Local>";
+    const ISO: &'static str = "%Y-%m-%d %H:%M:%S%.f%:z";
+    fn to_time_value(&self) -> TimeValue {
+        TimeValue::Fixed(self.fixed_offset())
+    }
+    fn from_time_value(t: TimeValue) -> Option<Self> {
+        match t {
+            TimeValue::Naive(t) => Local.from_local_datetime(&t).earliest(),
+            TimeValue::Fixed(t) => Some(t.with_timezone(&Local))
+        }
+    }
+}
+
+/// ISO text is `[-]HH:MM:SS[.SSS]` with hours past 24, as SQLite's time modifiers take it.
+/// Numbers are seconds, milliseconds or (real) days.
+impl TimeType for Duration {
+    const NAME: &'static str = "Duration";
+
+    fn encode(&self, format: TimeFormat) -> Value {
+        match format {
+            TimeFormat::Iso => {
+                let sign = if *self < Duration::zero() { "-" } else { "" };
+                let d = self.abs();
+                let secs = d.num_seconds();
+                let mut text = format!("{sign}{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+                match d.subsec_nanos() {
+                    0 => (),
+                    nanos if nanos % 1_000_000 == 0 => text += &format!(".{:03}", nanos / 1_000_000),
+                    nanos => text += &format!(".{nanos:09}")
+                }
+                Value::Text(text)
+            },
+            TimeFormat::UnixSeconds => Value::I64(self.num_seconds()),
+            TimeFormat::UnixMillis => Value::I64(self.num_milliseconds()),
+            TimeFormat::JulianDay => Value::F64(self.num_milliseconds() as f64 / 86_400_000.0)
+        }
+    }
+
+    fn decode(v: &Value, format: TimeFormat) -> Option<Self> {
+        match (format, v) {
+            (TimeFormat::UnixMillis, Value::I64(millis)) => Some(Duration::milliseconds(*millis)),
+            (_, Value::I64(secs)) => Duration::try_seconds(*secs),
+            (_, Value::F64(days)) if days.is_finite() => Duration::try_milliseconds((days * 86_400_000.0).round() as i64),
+            (_, Value::Text(text)) => parse_duration(text.trim()),
+            _ => None
+        }
+    }
+}
+
+fn parse_duration(text: &str) -> Option<Duration> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text))
+    };
+    let mut parts = text.split(':');
+    let (hours, minutes, seconds) = (parts.next()?, parts.next()?, parts.next().unwrap_or("0"));
+    if parts.next().is_some() || !seconds.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        return None;
+    }
+    let (whole, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    let nanos = match fraction.len() {
+        0 => 0,
+        1..=9 => fraction.parse::<i64>().ok()? * 10_i64.pow(9 - fraction.len() as u32),
+        _ => return None
+    };
+    let total = Duration::try_hours(hours.parse().ok()?)?
+        .checked_add(&Duration::try_minutes(minutes.parse().ok()?)?)?
+        .checked_add(&Duration::try_seconds(whole.parse().ok()?)?)?
+        .checked_add(&Duration::nanoseconds(nanos))?;
+    Some(if negative { -total } else { total })
+}
+
+/// Stored as Unix seconds, e.g. `Args::new().arg(UnixSeconds(created))`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnixSeconds<T>(pub T);
+
+/// Stored as Unix milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnixMillis<T>(pub T);
+
+/// Stored as a Julian day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JulianDay<T>(pub T);
+
+fn decode_error(expected: &'static str, v: &Value) -> ConversionError {
+    let e = ConversionError::new(expected, v.type_name(), &v.preview());
+    match v {
+        Value::Null | Value::Blob(_) => e,
+        _ => e.reason("not a date/time value")
+    }
+}
+
+impl<T: TimeType> ValueConvertible for UnixSeconds<T> {
+    fn try_to_value(&self) -> crate::error::Result<Value> {
+        Ok(self.0.encode(TimeFormat::UnixSeconds))
+    }
+}
+impl<T: TimeType> From<UnixSeconds<T>> for Value {
+    fn from(t: UnixSeconds<T>) -> Self {
+        t.0.encode(TimeFormat::UnixSeconds)
+    }
+}
+impl<T: TimeType> TryFrom<Value> for UnixSeconds<T> {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        T::decode(&v, TimeFormat::UnixSeconds).map(UnixSeconds).ok_or_else(|| decode_error(T::NAME, &v))
+    }
+}
+
+impl<T: TimeType> ValueConvertible for UnixMillis<T> {
+    fn try_to_value(&self) -> crate::error::Result<Value> {
+        Ok(self.0.encode(TimeFormat::UnixMillis))
+    }
+}
+impl<T: TimeType> From<UnixMillis<T>> for Value {
+    fn from(t: UnixMillis<T>) -> Self {
+        t.0.encode(TimeFormat::UnixMillis)
+    }
+}
+impl<T: TimeType> TryFrom<Value> for UnixMillis<T> {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        T::decode(&v, TimeFormat::UnixMillis).map(UnixMillis).ok_or_else(|| decode_error(T::NAME, &v))
+    }
+}
+
+impl<T: TimeType> ValueConvertible for JulianDay<T> {
+    fn try_to_value(&self) -> crate::error::Result<Value> {
+        Ok(self.0.encode(TimeFormat::JulianDay))
+    }
+}
+impl<T: TimeType> From<JulianDay<T>> for Value {
+    fn from(t: JulianDay<T>) -> Self {
+        t.0.encode(TimeFormat::JulianDay)
+    }
+}
+impl<T: TimeType> TryFrom<Value> for JulianDay<T> {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        T::decode(&v, TimeFormat::JulianDay).map(JulianDay).ok_or_else(|| decode_error(T::NAME, &v))
+    }
+}
+
+/// Trailing `Z`, `±HH:MM` or `±HHMM`, after a time of day only.
+fn split_offset(text: &str) -> Option<(&str, Option<FixedOffset>)> {
+    if let Some(rest) = text.strip_suffix(['Z', 'z']) {
+        return Some((rest.trim_end(), FixedOffset::east_opt(0)));
+    }
+    let Some(at) = text.rfind(['+', '-']) else {
+        return Some((text, None));
+    };
+    let (rest, offset) = text.split_at(at);
+    if !rest.contains(':') {
+        // the sign belongs to the date
+        return Some((text, None));
+    }
+    let digits = offset[1..].replace(':', "");
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let seconds = digits[..2].parse::<i32>().ok()? * 3600 + digits[2..].parse::<i32>().ok()? * 60;
+    let seconds = if offset.starts_with('-') { -seconds } else { seconds };
+    Some((rest.trim_end(), FixedOffset::east_opt(seconds)))
+}
+
+fn parse_date(text: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()
+}
+
+fn parse_time(text: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(text, "%H:%M:%S%.f")
+        .or_else(|_| NaiveTime::parse_from_str(text, "%H:%M"))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+    use crate::db::SQLite;
+    use crate::query::Query;
+
+    fn naive(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f").unwrap()
+    }
+
+    #[test]
+    fn parse_text_test() {
+        let t = naive("2024-05-01 10:00:00");
+        assert_eq!(TimeValue::parse_text("2024-05-01 10:00:00"), Some(TimeValue::Naive(t)));
+        assert_eq!(TimeValue::parse_text("2024-05-01T10:00"), Some(TimeValue::Naive(t)));
+        assert_eq!(TimeValue::parse_text(" 2024-05-01 "), Some(TimeValue::Naive(naive("2024-05-01 00:00:00"))));
+        assert_eq!(TimeValue::parse_text("2024-05-01 10:00:00.250"), Some(TimeValue::Naive(naive("2024-05-01 10:00:00.25"))));
+        assert_eq!(TimeValue::parse_text("10:00"), Some(TimeValue::Naive(naive("2000-01-01 10:00:00"))));
+
+        let Some(TimeValue::Fixed(z)) = TimeValue::parse_text("2024-05-01T10:00:00Z") else { panic!() };
+        assert_eq!((z.naive_utc(), z.offset().local_minus_utc()), (t, 0));
+        let Some(TimeValue::Fixed(t2)) = TimeValue::parse_text("2024-05-01 12:30:00.5+02:30") else { panic!() };
+        assert_eq!(t2.naive_utc(), naive("2024-05-01 10:00:00.5"));
+        let Some(TimeValue::Fixed(t3)) = TimeValue::parse_text("2024-05-01T05:00-0500") else { panic!() };
+        assert_eq!(t3.naive_utc(), t);
+
+        for bad in ["", "yesterday", "2024-13-01", "2024-05-01 25:00", "2024-05-01 10:00+2"] {
+            assert_eq!(TimeValue::parse_text(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn parse_number_test() {
+        let t = naive("2024-05-01 10:00:00");
+        let Some(TimeValue::Fixed(epoch)) = TimeValue::parse(&Value::I64(1714557600)) else { panic!() };
+        assert_eq!(epoch.naive_utc(), t);
+        let Some(TimeValue::Fixed(julian)) = TimeValue::parse(&Value::F64(2460431.9166666665)) else { panic!() };
+        assert_eq!(julian.naive_utc(), t);
+        assert_eq!(TimeValue::parse(&Value::from("2460431.9166666665")), Some(TimeValue::Fixed(julian)));
+        assert_eq!(TimeValue::parse(&Value::F64(-1.0)), None);
+        assert_eq!(TimeValue::parse(&Value::Null), None);
+    }
+
+    fn round_trip<T>(t: T)
+        where T: TimeType + TryFrom<Value, Error = ConversionError> + Into<Value> + Copy + PartialEq + std::fmt::Debug
+    {
+        assert_eq!(T::try_from(t.into()).unwrap(), t, "iso");
+        assert_eq!(UnixMillis::<T>::try_from(Value::from(UnixMillis(t))).unwrap().0, t, "millis");
+        assert_eq!(JulianDay::<T>::try_from(Value::from(JulianDay(t))).unwrap().0, t, "julian day");
+        // sub-seconds are dropped, what is read back is stored the same way
+        let seconds = Value::from(UnixSeconds(t));
+        assert_eq!(Value::from(UnixSeconds(UnixSeconds::<T>::try_from(seconds.clone()).unwrap().0)), seconds, "seconds");
+    }
+
+    #[test]
+    fn storage_formats_test() {
+        let t = naive("2024-05-01 10:00:00.250");
+        round_trip(t);
+        round_trip(t.date());
+        round_trip(t.time());
+        round_trip(t.and_utc());
+        round_trip(DateTime::parse_from_rfc3339("2024-05-01T12:00:00.250+02:00").unwrap().to_utc());
+        round_trip(Duration::milliseconds(-90_061_250));
+        round_trip(t.and_utc().with_timezone(&Local));
+        let whole = naive("2024-05-01 10:00:00").and_utc();
+        assert_eq!(UnixSeconds::<DateTime<Utc>>::try_from(Value::from(UnixSeconds(whole))).unwrap().0, whole);
+
+        assert_eq!(Value::from(t), Value::from("2024-05-01 10:00:00.250"));
+        assert_eq!(Value::from(t.time()), Value::from("10:00:00.250"));
+        assert_eq!(Value::from(t.and_utc()), Value::from("2024-05-01 10:00:00.250Z"));
+        assert_eq!(Value::from(Duration::milliseconds(-90_061_250)), Value::from("-25:01:01.250"));
+        assert_eq!(Value::from(UnixSeconds(t)), Value::I64(1714557600));
+        assert_eq!(Value::from(UnixMillis(Duration::seconds(2))), Value::I64(2000));
+        assert_eq!(Value::from(JulianDay(Duration::hours(36))), Value::F64(1.5));
+
+        // the offset survives ISO text, numbers keep the instant
+        let fixed = DateTime::parse_from_rfc3339("2024-05-01T12:00:00+02:00").unwrap();
+        assert_eq!(Value::from(fixed), Value::from("2024-05-01 12:00:00+02:00"));
+        assert_eq!(DateTime::<FixedOffset>::try_from(Value::from(fixed)).unwrap().offset(), fixed.offset());
+        let seconds = UnixSeconds::<DateTime<FixedOffset>>::try_from(Value::from(UnixSeconds(fixed))).unwrap().0;
+        assert_eq!((seconds, seconds.offset().local_minus_utc()), (fixed, 0));
+
+        // local times keep their offset, SQLite reads the same instant
+        let local = t.and_utc().with_timezone(&Local);
+        let text = local.format("%Y-%m-%d %H:%M:%S%.3f%:z").to_string();
+        assert_eq!(Value::from(local), Value::from(text.as_str()));
+        assert!(matches!(TimeValue::parse(&Value::from(local)), Some(TimeValue::Fixed(_))));
+        let mut sq = SQLite::new().create(true, |_| Ok(())).unwrap();
+        let rows = sq.select(Query::new("SELECT strftime('%Y-%m-%d %H:%M:%f', ?) AS utc").arg(local)).unwrap();
+        assert_eq!(rows[0]["utc"], Value::from("2024-05-01 10:00:00.250"));
+
+        assert!(Duration::try_from(Value::from("1:xx")).is_err());
+        assert!(UnixMillis::<NaiveTime>::try_from(Value::Null).is_err());
+    }
+
+    #[test]
+    fn sqlite_functions_test() {
+        // SQLite's date functions read each stored form
+        let mut sq = test_database("CREATE TABLE t (a, b, c, d)");
+        let t = naive("2024-05-01 10:00:00").and_utc();
+        let query = Query::new("INSERT INTO t VALUES (?, ?, ?, ?)").arg(t).arg(UnixSeconds(t)).arg(JulianDay(t)).arg(Duration::minutes(90));
+        sq.exec(query).unwrap();
+        let row = sq.select(Query::new(
+            "SELECT datetime(a) AS a, datetime(b, 'unixepoch') AS b, datetime(c) AS c, time('00:00', d) AS d FROM t"
+        )).unwrap().remove(0);
+        for column in ["a", "b", "c"] {
+            assert_eq!(row[column], Value::from("2024-05-01 10:00:00"), "{column}");
+        }
+        assert_eq!(row["d"], Value::from("01:30:00"));
+    }
+}
@@ -5,19 +5,82 @@ use std::io::ErrorKind;
 use std::ptr::{null_mut, null};
 use std::io::ErrorKind::Other;
 use crate::error::Result;
-use crate::error::Error;
-use sqlite3_sys::{sqlite3, sqlite3_close, sqlite3_errcode, sqlite3_errmsg, sqlite3_exec, sqlite3_last_insert_rowid, sqlite3_libversion, sqlite3_open_v2, sqlite3_shutdown, SQLITE_OK, SQLITE_DONE, SQLITE_OPEN_CREATE, SQLITE_OPEN_READONLY, SQLITE_OPEN_READWRITE};
+use crate::error::{ConversionError, Error, SqliteError};
+use sqlite3_sys::{sqlite3, sqlite3_complete, sqlite3_changes64, sqlite3_total_changes64, sqlite3_close, sqlite3_errcode, sqlite3_errmsg, sqlite3_exec, sqlite3_get_autocommit, sqlite3_last_insert_rowid, sqlite3_libversion, sqlite3_open_v2, sqlite3_shutdown, SQLITE_OK, SQLITE_DONE, SQLITE_ROW, SQLITE_OPEN_CREATE, SQLITE_OPEN_READONLY, SQLITE_OPEN_READWRITE};
 use log::error;
+use serde::{Deserialize, Serialize};
 use crate::args::Args;
+use crate::clock::ClockSlot;
 use crate::query::Query;
 use crate::QueryResult;
+use crate::lexer::tokens;
 use crate::stmt::Stmt;
+use crate::value::Value;
+use crate::value_ref::RowRef;
 
 const IN_MEMORY: &str = ":memory:";
 
+/// Outcome of a statement executed with `exec`, `update` or `delete`.
+/// Counts are only meaningful for INSERT, UPDATE and DELETE statements.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExecResult {
+    /// Rows changed by the statement (`sqlite3_changes64`).
+    pub changes: i64,
+    /// Rows changed since the connection was opened (`sqlite3_total_changes64`).
+    pub total_changes: i64,
+    /// Last inserted row id on the connection.
+    pub last_insert_rowid: i64,
+}
+
+impl ExecResult {
+    /// Fail unless exactly `expected` rows were changed.
+    pub fn expect_changes(self, expected: i64) -> Result<Self> {
+        if self.changes != expected {
+            let message = format!("expected {expected} changed row(s), got: {}", self.changes);
+            return Err(message.as_str().into());
+        }
+        Ok(self)
+    }
+    /// Fail unless exactly one row was changed.
+    pub fn expect_one(self) -> Result<Self> {
+        self.expect_changes(1)
+    }
+    /// Fail if no row was changed.
+    pub fn expect_some(self) -> Result<Self> {
+        if self.changes == 0 {
+            return Err("expected changed rows, got: 0".into());
+        }
+        Ok(self)
+    }
+}
+
+/// Result of `SQLite::execute_many`, one entry per executed row.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchResult {
+    /// Last inserted row id after each row.
+    pub ids: Vec<i64>,
+    /// Number of rows changed by each row.
+    pub changes: Vec<i64>,
+}
+
+impl BatchResult {
+    /// Number of executed rows.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+    /// Sum of changed rows for the whole batch.
+    pub fn total_changes(&self) -> i64 {
+        self.changes.iter().sum()
+    }
+}
+
 pub struct SQLite {
-    db: *mut sqlite3,
-    path: String
+    pub(crate) db: *mut sqlite3,
+    path: String,
+    pub(crate) clock: ClockSlot
 }
 
 impl Default for SQLite {
@@ -32,7 +95,8 @@ impl SQLite {
     pub fn new() -> SQLite {
         SQLite {
             db: null_mut(),
-            path: IN_MEMORY.into()       
+            path: IN_MEMORY.into(),
+            clock: ClockSlot::default()
         }
     }
     
@@ -79,8 +143,10 @@ impl SQLite {
             match stat {
                 SQLITE_OK => Ok(()),
                 _ => {
+                    // read the error before close clears the connection
+                    let e = self.error();
                     self.close()?;
-                    Err(self.error())
+                    Err(e)
                 }
             }
         }
@@ -108,8 +174,10 @@ impl SQLite {
                     Ok(self)  
                 },
                 _ => {
+                    // read the error before close clears the connection
+                    let e = self.error();
                     self.close()?;
-                    Err(self.error())
+                    Err(e)
                 }
             }       
         }
@@ -160,7 +228,7 @@ impl SQLite {
             let stat = sqlite3_exec(self.db, sql.as_ptr(), None, null_mut(), null_mut());
             match stat {
                 SQLITE_OK => Ok(()),
-                _ => Err(self.error())
+                _ => Err(SqliteError::from_db(self.db, Some(cmd)).into())
             }
         }
     }
@@ -168,28 +236,28 @@ impl SQLite {
     
     /// Execute a query.
     /// Function for use and call from Query self.
-    pub(crate) fn exec_for_query(&mut self, query: &Query) -> Result<()> {
+    pub(crate) fn exec_for_query(&mut self, query: &Query) -> Result<ExecResult> {
         self.database_opened()?;
         let mut stmt = Stmt::for_command(self.db, query.cmd.as_str())?;
         if query.are_arguments() {
             stmt.bind(query.args.clone())?;
         }
         match stmt.step() {
-            SQLITE_OK | SQLITE_DONE => Ok(()),
-            _ => Err(self.error())
+            SQLITE_OK | SQLITE_DONE => Ok(self.exec_result()),
+            _ => Err(stmt.error())
         }
     }
     /// Execute a query.
     /// Used Query is moved to the function.
-    pub fn exec(&mut self, query: Query) -> Result<()> {
+    pub fn exec(&mut self, query: Query) -> Result<ExecResult> {
         self.database_opened()?;
         let mut stmt = Stmt::for_command(self.db, query.cmd.as_str())?;
         if query.are_arguments() {
             stmt.bind(query.args)?;
         }
         match stmt.step() {
-            SQLITE_OK | SQLITE_DONE => Ok(()),
-            _ => Err(self.error())
+            SQLITE_OK | SQLITE_DONE => Ok(self.exec_result()),
+            _ => Err(stmt.error())
         }
     }
 
@@ -210,13 +278,13 @@ impl SQLite {
 
     /// Execute a query for updating data.
     /// Used Query is moved to the function.
-    pub(crate) fn update_for_query(&mut self, query: &Query) -> Result<()> {
+    pub(crate) fn update_for_query(&mut self, query: &Query) -> Result<ExecResult> {
         self.database_opened()?;
         self.exec_for_query(query)
     }
     /// Execute a query for updating data.
     /// Used Query is moved to the function.   
-    pub fn update(&mut self, query: Query) -> Result<()> {
+    pub fn update(&mut self, query: Query) -> Result<ExecResult> {
         self.database_opened()?;
         self.exec(query)
     }
@@ -242,21 +310,220 @@ impl SQLite {
         stmt.fetch_result()
     }
     
+    /// Execute a query and pass every row to a callback instead of collecting a QueryResult.
+    /// The callback gets column names and values in SELECT order, an error stops the query.
+    pub fn select_each<F>(&mut self, query: Query, f: F) -> Result<()>
+        where F: FnMut(&[String], Vec<Value>) -> Result<()>
+    {
+        self.select_stream(query, |_| Ok(()), f)
+    }
+
+    /// Like `select_each`, but values are borrowed from the statement instead of copied,
+    /// see `ValueRef`. The row is only valid inside the callback.
+    pub fn select_each_ref<F>(&mut self, query: Query, mut f: F) -> Result<()>
+        where F: FnMut(&RowRef<'_>) -> Result<()>
+    {
+        self.database_opened()?;
+        let mut stmt = Stmt::for_command(self.db, query.cmd.as_str())?;
+        if query.are_arguments() {
+            stmt.bind(query.args)?;
+        }
+        let columns = stmt.column_names();
+        stmt.for_each_row_ref(|row| f(&RowRef::new(row, &columns)))
+    }
+
+    /// Like `select_each`, but column names are also reported before the first row,
+    /// so callers learn them for an empty result too.
+    pub(crate) fn select_stream<C, F>(&mut self, query: Query, on_columns: C, mut f: F) -> Result<()>
+        where C: FnOnce(&[String]) -> Result<()>, F: FnMut(&[String], Vec<Value>) -> Result<()>
+    {
+        self.database_opened()?;
+        let mut stmt = Stmt::for_command(self.db, query.cmd.as_str())?;
+        if query.are_arguments() {
+            stmt.bind(query.args)?;
+        }
+        let columns = stmt.column_names();
+        on_columns(&columns).and_then(|_| stmt.for_each_row(|values| f(&columns, values)))
+    }
+
     /// Execute a query for deleting data.
     /// Function for use and call from Query self. 
-    pub(crate) fn delete_for_query(&mut self, query: &Query) -> Result<()> {
+    pub(crate) fn delete_for_query(&mut self, query: &Query) -> Result<ExecResult> {
         self.database_opened()?;
         self.exec_for_query(query)
     }
     /// Execute a query for deleting data.
     /// Used Query is moved to the function. 
-    pub fn delete(&mut self, query: Query) -> Result<()> {
+    pub fn delete(&mut self, query: Query) -> Result<ExecResult> {
         self.database_opened()?;
         self.exec(query)
     }
     
+    /// Execute a statement with a RETURNING clause.
+    /// Function for use and call from Query self.
+    pub(crate) fn returning_for_query(&mut self, query: &Query) -> Result<QueryResult> {
+        self.returning_stmt(query, &["INSERT", "REPLACE", "UPDATE", "DELETE"])?.fetch_result()
+    }
+    /// Execute INSERT ... RETURNING and return the produced rows.
+    /// Used Query is moved to the function.
+    pub fn insert_returning(&mut self, query: Query) -> Result<QueryResult> {
+        self.returning_stmt(&query, &["INSERT", "REPLACE"])?.fetch_result()
+    }
+    /// Execute UPDATE ... RETURNING and return the produced rows.
+    /// Used Query is moved to the function.
+    pub fn update_returning(&mut self, query: Query) -> Result<QueryResult> {
+        self.returning_stmt(&query, &["UPDATE"])?.fetch_result()
+    }
+    /// Execute DELETE ... RETURNING and return the produced rows.
+    /// Used Query is moved to the function.
+    pub fn delete_returning(&mut self, query: Query) -> Result<QueryResult> {
+        self.returning_stmt(&query, &["DELETE"])?.fetch_result()
+    }
+
+    /// Execute INSERT ... RETURNING of one column, e.g. generated ids, converted to `T`.
+    pub fn insert_returning_as<T>(&mut self, query: Query) -> Result<Vec<T>>
+        where T: TryFrom<Value, Error = ConversionError>
+    {
+        Self::returning_column(self.returning_stmt(&query, &["INSERT", "REPLACE"])?)
+    }
+    /// Execute UPDATE ... RETURNING of one column converted to `T`.
+    pub fn update_returning_as<T>(&mut self, query: Query) -> Result<Vec<T>>
+        where T: TryFrom<Value, Error = ConversionError>
+    {
+        Self::returning_column(self.returning_stmt(&query, &["UPDATE"])?)
+    }
+    /// Execute DELETE ... RETURNING of one column converted to `T`.
+    pub fn delete_returning_as<T>(&mut self, query: Query) -> Result<Vec<T>>
+        where T: TryFrom<Value, Error = ConversionError>
+    {
+        Self::returning_column(self.returning_stmt(&query, &["DELETE"])?)
+    }
+
+    /// Prepare and bind a statement of one of `kinds` that returns rows.
+    fn returning_stmt(&mut self, query: &Query, kinds: &[&str]) -> Result<Stmt> {
+        self.database_opened()?;
+        let keyword = statement_keyword(&query.cmd).unwrap_or_default();
+        if !kinds.contains(&keyword.as_str()) {
+            return Err(format!("expected {} statement, got: {keyword}", kinds.join(" or ")).into());
+        }
+        let mut stmt = Stmt::for_command(self.db, query.cmd.as_str())?;
+        if stmt.column_count() == 0 {
+            return Err("query has no RETURNING clause".into());
+        }
+        if query.are_arguments() {
+            stmt.bind_for_query(&query.args)?;
+        }
+        Ok(stmt)
+    }
+
+    fn returning_column<T>(mut stmt: Stmt) -> Result<Vec<T>>
+        where T: TryFrom<Value, Error = ConversionError>
+    {
+        let columns = stmt.column_names();
+        let [column] = columns.as_slice() else {
+            return Err(format!("RETURNING must name one column, got {}", columns.len()).into());
+        };
+        let mut result = Vec::new();
+        stmt.for_each_row(|values| {
+            let value = values.into_iter().next().unwrap_or(Value::Null);
+            result.push(T::try_from(value).map_err(|e| e.column(column))?);
+            Ok(())
+        })?;
+        Ok(result)
+    }
+
+    /// Execute one statement for many sets of arguments.
+    /// The statement is prepared once and all rows run in one transaction.
+    /// On failure the transaction is rolled back and the error is an `Error::Batch` with the failing row.
+    pub fn execute_many<I>(&mut self, sql: &str, rows: I) -> Result<BatchResult>
+        where I: IntoIterator<Item = Args>
+    {
+        self.execute_many_chunked(sql, rows, usize::MAX)
+    }
+
+    /// Like `execute_many`, but commits after every `chunk_size` rows.
+    /// On failure only the current chunk is rolled back, earlier chunks stay committed.
+    /// If a transaction is already open, the caller owns it and no commits are made.
+    pub fn execute_many_chunked<I>(&mut self, sql: &str, rows: I, chunk_size: usize) -> Result<BatchResult>
+        where I: IntoIterator<Item = Args>
+    {
+        self.database_opened()?;
+        if chunk_size == 0 {
+            return Err("execute_many: chunk size must be greater than zero".into());
+        }
+        let mut stmt = Stmt::for_command(self.db, sql)?;
+        self.run_batch(&mut stmt, rows, chunk_size)
+    }
+
+    fn run_batch<I>(&mut self, stmt: &mut Stmt, rows: I, chunk_size: usize) -> Result<BatchResult>
+        where I: IntoIterator<Item = Args>
+    {
+        let own_transaction = !self.in_transaction();
+        if own_transaction {
+            self.exec_command("BEGIN")?;
+        }
+
+        let mut result = BatchResult::default();
+        let mut pending = 0usize;
+        for (index, args) in rows.into_iter().enumerate() {
+            if let Err(e) = Self::execute_row(stmt, args) {
+                if own_transaction {
+                    let _ = self.exec_command("ROLLBACK");
+                }
+                return Err(Error::Batch { row: index, source: Box::new(e), context: None });
+            }
+            result.ids.push(self.last_inserted_id());
+            result.changes.push(self.changes());
+
+            pending += 1;
+            if own_transaction && pending == chunk_size {
+                self.exec_command("COMMIT")?;
+                self.exec_command("BEGIN")?;
+                pending = 0;
+            }
+        }
+
+        if own_transaction {
+            self.exec_command("COMMIT")?;
+        }
+        Ok(result)
+    }
+
+    fn execute_row(stmt: &mut Stmt, args: Args) -> Result<()> {
+        stmt.reset()?;
+        stmt.clear_bindings()?;
+        if args.len() != stmt.parameter_count() {
+            let message = format!("invalid number of arguments. Expected: {}, got: {}", stmt.parameter_count(), args.len());
+            return Err(message.as_str().into());
+        }
+        stmt.bind(args)?;
+        match stmt.step() {
+            SQLITE_DONE | SQLITE_ROW => Ok(()),
+            _ => Err(stmt.error())
+        }
+    }
+
+    /// Check if a statement makes no direct changes to the database (`sqlite3_stmt_readonly`).
+    /// Only the first statement of the text is checked, as only that one is executed.
+    pub fn is_read_only(&mut self, sql: &str) -> Result<bool> {
+        self.database_opened()?;
+        let mut stmt = Stmt::for_command(self.db, sql)?;
+        Ok(stmt.is_read_only())
+    }
+
+    /// Prepare a statement for inspection, it is finalized on drop.
+    pub(crate) fn prepare_stmt(&mut self, sql: &str) -> Result<Stmt> {
+        self.database_opened()?;
+        Stmt::for_command(self.db, sql)
+    }
+
+    /// Check if a transaction is open.
+    pub(crate) fn in_transaction(&self) -> bool {
+        unsafe { sqlite3_get_autocommit(self.db) == 0 }
+    }
+
     /// Check if a database is opened.
-    fn database_opened(&self) -> Result<()> {
+    pub(crate) fn database_opened(&self) -> Result<()> {
         if self.db.is_null() {
             return Err("database not opened".into());
         }
@@ -264,7 +531,7 @@ impl SQLite {
     }
     
     pub fn error(&mut self) -> Error {
-        Error{ code: self.err_code(), message: self.err_string(), kind: Some(Other)}
+        SqliteError::from_db(self.db, None).into()
     }
 
     /// Get the error code from sqlite3.
@@ -283,6 +550,15 @@ impl SQLite {
         }
     }
 
+    /// Check if the text ends with a complete SQL statement (`sqlite3_complete`).
+    /// Semicolons inside strings, comments and trigger bodies don't end a statement.
+    pub fn is_complete(sql: &str) -> bool {
+        match CString::new(sql) {
+            Ok(sql) => unsafe { sqlite3_complete(sql.as_ptr()) != 0 },
+            Err(_) => false
+        }
+    }
+
     /// Get the version of the SQLite library.
     pub fn version() -> String {
         unsafe { CStr::from_ptr(sqlite3_libversion()).to_string_lossy().into_owned() }
@@ -292,7 +568,25 @@ impl SQLite {
     fn last_inserted_id(&self) -> i64 {
         unsafe { sqlite3_last_insert_rowid(self.db) }
     }
-    
+
+    /// Number of rows changed by the last statement.
+    pub(crate) fn changes(&self) -> i64 {
+        unsafe { sqlite3_changes64(self.db) }
+    }
+
+    /// Number of rows changed since the database was opened.
+    fn total_changes(&self) -> i64 {
+        unsafe { sqlite3_total_changes64(self.db) }
+    }
+
+    fn exec_result(&self) -> ExecResult {
+        ExecResult {
+            changes: self.changes(),
+            total_changes: self.total_changes(),
+            last_insert_rowid: self.last_inserted_id(),
+        }
+    }
+
 }
 
 impl Drop for SQLite {
@@ -302,3 +596,130 @@ impl Drop for SQLite {
         }
     }
 }
+
+/// First keyword of a statement, for WITH the one after the common table expressions.
+pub(crate) fn statement_keyword(sql: &str) -> Option<String> {
+    let mut depth = 0;
+    let mut with = false;
+    for (i, (start, end)) in tokens(sql).into_iter().enumerate() {
+        match &sql[start..end] {
+            "(" => depth += 1,
+            ")" => depth -= 1,
+            _ if depth > 0 => (),
+            token => {
+                let word = token.to_ascii_uppercase();
+                if i == 0 && word == "WITH" {
+                    with = true;
+                } else if !with || matches!(word.as_str(), "SELECT" | "VALUES" | "INSERT" | "REPLACE" | "UPDATE" | "DELETE") {
+                    return Some(word);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// In-memory database created by `sql`, for tests.
+#[cfg(test)]
+pub(crate) fn test_database(sql: &str) -> SQLite {
+    SQLite::new().create(true, |sq| sq.exec_command(sql)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static CREATE_SCHEMA: &str = "CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, qty INT);";
+
+    fn count(sq: &mut SQLite) -> i64 {
+        let result = sq.select(Query::new("SELECT count(*) AS n FROM item")).unwrap();
+        result[0]["n"].clone().get::<i64>().unwrap()
+    }
+
+    #[test]
+    fn is_complete_test() {
+        assert!(SQLite::is_complete("SELECT 1;"));
+        assert!(!SQLite::is_complete("SELECT 1"));
+        assert!(!SQLite::is_complete("SELECT ';"));
+        assert!(!SQLite::is_complete("CREATE TRIGGER t AFTER INSERT ON x BEGIN SELECT 1;"));
+        assert!(SQLite::is_complete("CREATE TRIGGER t AFTER INSERT ON x BEGIN SELECT 1; END;"));
+    }
+
+    #[test]
+    fn is_read_only_test() {
+        let mut sq = test_database(CREATE_SCHEMA);
+        assert!(sq.is_read_only("SELECT * FROM item").unwrap());
+        assert!(!sq.is_read_only("DELETE FROM item").unwrap());
+        assert!(!sq.is_read_only("CREATE TABLE x (a)").unwrap());
+        assert!(sq.is_read_only("SELECT * FROM missing").is_err());
+    }
+
+    #[test]
+    fn update_and_delete_report_changes() {
+        let mut sq = test_database(CREATE_SCHEMA);
+        let id = sq.insert(Query::new("INSERT INTO item (name, qty) VALUES ('a', 1)")).unwrap();
+        sq.insert(Query::new("INSERT INTO item (name, qty) VALUES ('b', 1)")).unwrap();
+
+        let result = sq.update(Query::new("UPDATE item SET qty=2 WHERE id=?").arg(id)).unwrap();
+        assert_eq!(result.changes, 1);
+        assert_eq!(result.total_changes, 3);
+        assert!(result.expect_one().is_ok());
+
+        let result = Query::new("UPDATE item SET qty=3 WHERE id=?").arg(100).update(&mut sq).unwrap();
+        assert_eq!(result.changes, 0);
+        assert!(result.expect_one().is_err());
+        assert!(result.expect_some().is_err());
+
+        let result = Query::new("DELETE FROM item WHERE qty=?").arg(1).delete(&mut sq).unwrap();
+        assert_eq!(result.changes, 1);
+        let result = sq.exec(Query::new("DELETE FROM item")).unwrap();
+        assert_eq!(result.changes, 1);
+        assert!(result.expect_changes(1).is_ok());
+    }
+
+    #[test]
+    fn execute_many_inserts() {
+        let mut sq = test_database(CREATE_SCHEMA);
+        let rows = (0..100).map(|i| Args::new().arg(format!("item{i}").as_str()).arg(i));
+        let result = sq.execute_many("INSERT INTO item (name, qty) VALUES (?, ?)", rows).unwrap();
+
+        assert_eq!(result.len(), 100);
+        assert_eq!(result.ids, (1..=100).collect::<Vec<i64>>());
+        assert_eq!(result.total_changes(), 100);
+        assert_eq!(count(&mut sq), 100);
+
+        let rows = vec![Args::new().arg(1000).arg(1), Args::new().arg(2000).arg(2)];
+        let result = sq.execute_many("UPDATE item SET qty=? WHERE id=?", rows).unwrap();
+        assert_eq!(result.changes, vec![1, 1]);
+    }
+
+    #[test]
+    fn execute_many_rolls_back_on_failure() {
+        let mut sq = test_database(CREATE_SCHEMA);
+        let rows = vec![
+            Args::new().arg("a").arg(1),
+            Args::new().arg("b").arg(2),
+            Args::new().arg("a").arg(3),
+        ];
+        let err = sq.execute_many("INSERT INTO item (name, qty) VALUES (?, ?)", rows).unwrap_err();
+        assert_eq!(err.row(), Some(2));
+        assert!(err.is_constraint(), "{}", err);
+        assert!(err.message().starts_with("execute_many: row 2:"), "{}", err);
+        assert_eq!(count(&mut sq), 0);
+
+        let rows = vec![Args::new().arg("a")];
+        let err = sq.execute_many("INSERT INTO item (name, qty) VALUES (?, ?)", rows).unwrap_err();
+        assert_eq!(err.row(), Some(0));
+        assert!(matches!(&err, Error::Batch { source, .. } if matches!(**source, Error::Validation(_))), "{}", err);
+    }
+
+    #[test]
+    fn execute_many_chunked_keeps_committed_chunks() {
+        let mut sq = test_database(CREATE_SCHEMA);
+        let rows = (0..10).map(|i| Args::new().arg(format!("item{}", i.min(7)).as_str()).arg(i));
+        let err = sq.execute_many_chunked("INSERT INTO item (name, qty) VALUES (?, ?)", rows, 4).unwrap_err();
+        assert_eq!(err.row(), Some(8));
+        assert_eq!(count(&mut sq), 8);
+        assert_eq!(sq.execute_many_chunked("SELECT 1", Vec::new(), 0).unwrap_err().row(), None);
+    }
+}
@@ -0,0 +1,201 @@
+use std::io::Write;
+use crate::db::SQLite;
+use crate::error::Result;
+use crate::query::{quote_identifier, Query};
+use crate::schema::Table;
+use crate::value::Value;
+
+/// What `SQLite::dump` writes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DumpMode {
+    #[default]
+    Full,
+    SchemaOnly,
+    DataOnly,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DumpOptions {
+    /// Only these tables (with their indexes and triggers), all objects when None.
+    pub tables: Option<Vec<String>>,
+    pub mode: DumpMode,
+}
+
+impl DumpOptions {
+    fn includes(&self, table: &str) -> bool {
+        match &self.tables {
+            Some(tables) => tables.iter().any(|t| t.eq_ignore_ascii_case(table)),
+            None => true
+        }
+    }
+}
+
+/// Tables with referenced (parent) tables first, cycles keep the schema order.
+fn dependency_order(tables: Vec<Table>) -> Vec<Table> {
+    let mut pending = tables;
+    let mut ordered: Vec<Table> = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|table| {
+            table.foreign_keys.iter().all(|fk| {
+                fk.table.eq_ignore_ascii_case(&table.name)
+                    || !pending.iter().any(|t| t.name.eq_ignore_ascii_case(&fk.table))
+            })
+        });
+        ordered.push(pending.remove(ready.unwrap_or(0)));
+    }
+    ordered
+}
+
+/// Whether an AUTOINCREMENT table created `sqlite_sequence`.
+fn uses_sequence(tables: &[Table]) -> bool {
+    tables.iter().any(|t| t.sql.as_deref().is_some_and(|sql| sql.to_ascii_uppercase().contains("AUTOINCREMENT")))
+}
+
+fn statement(sql: &str) -> String {
+    format!("{};\n", sql.trim_end().trim_end_matches(';'))
+}
+
+impl SQLite {
+    /// Write the database as an SQL script that recreates it, like the sqlite3 shell's `.dump`.
+    pub fn dump<W: Write>(&mut self, mut writer: W, options: &DumpOptions) -> Result<()> {
+        let schema = self.schema()?;
+        let tables = dependency_order(schema.tables)
+            .into_iter()
+            .filter(|t| options.includes(&t.name))
+            .collect::<Vec<_>>();
+        let schema_mode = options.mode != DumpMode::DataOnly;
+        let data_mode = options.mode != DumpMode::SchemaOnly;
+
+        writer.write_all(b"PRAGMA foreign_keys=OFF;\nBEGIN TRANSACTION;\n")?;
+        for table in &tables {
+            if schema_mode && let Some(sql) = &table.sql {
+                writer.write_all(statement(sql).as_bytes())?;
+            }
+            if data_mode {
+                self.dump_rows(&mut writer, table)?;
+            }
+        }
+
+        if data_mode && uses_sequence(&tables) {
+            let sequences = self.select(Query::new("SELECT name, seq FROM sqlite_sequence ORDER BY rowid"))?;
+            for row in sequences {
+                let name = row["name"].clone().get::<String>().unwrap_or_default();
+                if tables.iter().any(|t| t.name == name) {
+                    let name = Value::from(name).to_sql_literal();
+                    let seq = row["seq"].to_sql_literal();
+                    writeln!(writer, "DELETE FROM sqlite_sequence WHERE name={name};")?;
+                    writeln!(writer, "INSERT INTO sqlite_sequence(name,seq) VALUES({name},{seq});")?;
+                }
+            }
+        }
+
+        if schema_mode {
+            for table in &tables {
+                for index in &table.indexes {
+                    if let Some(sql) = &index.sql {
+                        writer.write_all(statement(sql).as_bytes())?;
+                    }
+                }
+            }
+            if options.tables.is_none() {
+                for view in &schema.views {
+                    if let Some(sql) = &view.sql {
+                        writer.write_all(statement(sql).as_bytes())?;
+                    }
+                }
+            }
+            for trigger in schema.triggers.iter().filter(|t| tables.iter().any(|table| table.name.eq_ignore_ascii_case(&t.table))) {
+                if let Some(sql) = &trigger.sql {
+                    writer.write_all(statement(sql).as_bytes())?;
+                }
+            }
+        }
+        writer.write_all(b"COMMIT;\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn dump_rows<W: Write>(&mut self, writer: &mut W, table: &Table) -> Result<()> {
+        // generated columns can't be inserted
+        let columns = table.columns
+            .iter()
+            .filter(|c| !c.is_generated())
+            .map(|c| quote_identifier(&c.name))
+            .collect::<Vec<_>>()
+            .join(",");
+        let name = quote_identifier(&table.name);
+        let query = Query::new(&format!("SELECT {columns} FROM {name}"));
+        self.select_each(query, |_, values| {
+            let values = values.iter().map(Value::to_sql_literal).collect::<Vec<_>>().join(",");
+            writeln!(writer, "INSERT INTO {name}({columns}) VALUES({values});")?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+
+    static CREATE_SCHEMA: &str = r#"
+        CREATE TABLE note (id INTEGER PRIMARY KEY, person INT REFERENCES person(id), text TEXT);
+        CREATE TABLE person (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            score REAL,
+            photo BLOB,
+            upper_name TEXT GENERATED ALWAYS AS (upper(name)) VIRTUAL
+        );
+        CREATE INDEX person_name ON person (name);
+        CREATE VIEW people AS SELECT name FROM person;
+        CREATE TRIGGER note_insert AFTER INSERT ON note BEGIN SELECT 1; END;
+        INSERT INTO person (name, score, photo) VALUES ('O''Brien', 0.1, X'00FF'), ('Ann', 1e300, NULL), ('Bob', 3.0, X'');
+        DELETE FROM person WHERE name='Bob';
+        INSERT INTO note (person, text) VALUES (1, 'line
+break');
+    "#;
+
+    fn dump(sq: &mut SQLite, options: &DumpOptions) -> String {
+        let mut out = Vec::new();
+        sq.dump(&mut out, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn dump_and_restore_test() {
+        let mut sq = test_database(CREATE_SCHEMA);
+        let script = dump(&mut sq, &DumpOptions::default());
+        assert!(script.find("CREATE TABLE person").unwrap() < script.find("CREATE TABLE note").unwrap(), "{script}");
+        assert!(script.contains(r#"INSERT INTO "person"("id","name","score","photo") VALUES(1,'O''Brien',0.1,X'00FF');"#), "{script}");
+        assert!(script.contains("VALUES('person',3);"), "{script}");
+
+        let mut copy = test_database(&script);
+        assert!(copy.schema().unwrap().diff(&sq.schema().unwrap()).is_empty());
+        let query = "SELECT * FROM person ORDER BY id";
+        assert_eq!(copy.select(Query::new(query)).unwrap(), sq.select(Query::new(query)).unwrap());
+        let query = "SELECT * FROM note ORDER BY id";
+        assert_eq!(copy.select(Query::new(query)).unwrap(), sq.select(Query::new(query)).unwrap());
+        let id = copy.insert(Query::new("INSERT INTO person (name) VALUES ('Next')")).unwrap();
+        assert_eq!(id, 4);
+    }
+
+    #[test]
+    fn dump_options_test() {
+        let mut sq = test_database(CREATE_SCHEMA);
+
+        let script = dump(&mut sq, &DumpOptions { mode: DumpMode::SchemaOnly, ..Default::default() });
+        assert!(!script.contains("INSERT INTO"));
+        assert!(script.contains("CREATE VIEW people"));
+
+        let script = dump(&mut sq, &DumpOptions { mode: DumpMode::DataOnly, ..Default::default() });
+        assert!(!script.contains("CREATE"));
+        assert!(script.contains("INSERT INTO \"note\""));
+
+        let script = dump(&mut sq, &DumpOptions { tables: Some(vec!["note".into()]), ..Default::default() });
+        assert!(script.contains("CREATE TABLE note"));
+        assert!(script.contains("CREATE TRIGGER note_insert"));
+        assert!(!script.contains("INSERT INTO \"person\""));
+        assert!(!script.contains("CREATE VIEW"));
+    }
+}
@@ -0,0 +1,84 @@
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64 with padding.
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => text.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char),
+                false => text.push('=')
+            }
+        }
+    }
+    text
+}
+
+/// Decode standard base64, padding is optional.
+/// Padding must complete the last group and a group can't have a single character.
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let data = text.trim_end_matches('=');
+    let padding = text.len() - data.len();
+    if padding > 2 || (padding > 0 && !text.len().is_multiple_of(4)) || data.len() % 4 == 1 {
+        return None;
+    }
+    let text = data;
+    let mut data = Vec::with_capacity(text.len() * 3 / 4);
+    let mut n = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let v = BASE64.iter().position(|x| *x == c)? as u32;
+        n = n << 6 | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((n >> bits) as u8);
+            n &= (1 << bits) - 1;
+        }
+    }
+    Some(data)
+}
+
+/// Lowercase hex digits.
+pub fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Decode hex digits (either case).
+pub fn hex_decode(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_test() {
+        for (data, text) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foobar", "Zm9vYmFy")] {
+            assert_eq!(base64_encode(data.as_bytes()), text);
+            assert_eq!(base64_decode(text).unwrap(), data.as_bytes());
+        }
+        assert_eq!(base64_decode("Zg").unwrap(), b"f");
+        assert!(base64_decode("Z!").is_none());
+        for invalid in ["Z", "Zm9vY", "Zg=", "Zg===", "Zm9v=", "===="] {
+            assert!(base64_decode(invalid).is_none(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn hex_test() {
+        assert_eq!(hex_encode(&[0, 1, 0xab, 0xff]), "0001abff");
+        assert_eq!(hex_decode("0001ABff").unwrap(), vec![0, 1, 0xab, 0xff]);
+        assert!(hex_decode("abc").is_none());
+        assert!(hex_decode("zz").is_none());
+    }
+}
@@ -2,60 +2,387 @@
 use std::ffi::CStr;
 use std::io;
 use std::io::Error as IoError;
-use std::io::ErrorKind;
 use std::fmt::Display;
 use serde_json::Error as JsonError;
+use sqlite3_sys::{
+    sqlite3,
+    sqlite3_errcode,
+    sqlite3_errmsg,
+    sqlite3_error_offset,
+    sqlite3_extended_errcode,
+    SQLITE_BUSY,
+    SQLITE_CONSTRAINT,
+    SQLITE_CORRUPT,
+    SQLITE_LOCKED,
+    SQLITE_NOTADB,
+    SQLITE_READONLY,
+};
 
+/// Failure reported by SQLite.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Error {
+pub struct SqliteError {
+    /// Primary result code (`SQLITE_BUSY`, `SQLITE_CONSTRAINT`, ...).
     pub code: i32,
+    /// Extended result code (`SQLITE_CONSTRAINT_UNIQUE`, ...), the primary code is its low byte.
+    pub extended_code: i32,
     pub message: String,
-    pub kind: Option<ErrorKind>
+    /// Statement that failed, when known.
+    pub sql: Option<String>,
+    /// Byte offset in `sql` the error refers to (`sqlite3_error_offset`).
+    pub offset: Option<usize>,
+}
+
+impl SqliteError {
+    /// Read the last error of a connection.
+    pub(crate) fn from_db(db: *mut sqlite3, sql: Option<&str>) -> Self {
+        unsafe {
+            let offset = sqlite3_error_offset(db);
+            SqliteError {
+                code: sqlite3_errcode(db),
+                extended_code: sqlite3_extended_errcode(db),
+                message: CStr::from_ptr(sqlite3_errmsg(db)).to_string_lossy().into_owned(),
+                sql: sql.map(String::from),
+                offset: (offset >= 0).then_some(offset as usize),
+            }
+        }
+    }
+}
+
+/// A value that doesn't convert to the requested type.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConversionError {
+    /// Requested type (`u8`, `NaiveDate`, ...).
+    pub expected: String,
+    /// What was found: the `Value` variant (`Null`, `I64`, ...) or the source format.
+    pub actual: String,
+    /// Start of the value, empty for NULL.
+    pub preview: String,
+    /// Why a value of a usable variant was rejected (out of range, bad format, ...).
+    pub reason: Option<Box<str>>,
+    /// Column the value came from, when known.
+    pub column: Option<Box<str>>,
+    /// Where the conversion happened, see `Error::context`.
+    pub context: Option<Box<str>>,
+}
+
+impl ConversionError {
+    pub fn new(expected: &str, actual: &str, preview: &str) -> Self {
+        ConversionError {
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+            preview: preview.to_string(),
+            reason: None,
+            column: None,
+            context: None,
+        }
+    }
+
+    pub fn reason(mut self, reason: &str) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    pub fn column(mut self, column: &str) -> Self {
+        self.column = Some(column.into());
+        self
+    }
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(context) = &self.context {
+            write!(f, "{context}: ")?;
+        }
+        write!(f, "cannot convert {}", self.actual)?;
+        if !self.preview.is_empty() {
+            write!(f, " {}", self.preview)?;
+        }
+        write!(f, " to {}", self.expected)?;
+        if let Some(reason) = &self.reason {
+            write!(f, ": {reason}")?;
+        }
+        if let Some(column) = &self.column {
+            write!(f, " (column {column})")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+#[derive(Debug)]
+pub enum Error {
+    /// Failure reported by SQLite.
+    Sqlite(SqliteError),
+    /// A value doesn't convert to the requested type.
+    Conversion(Box<ConversionError>),
+    /// Invalid arguments, options or state detected before reaching SQLite.
+    Validation(String),
+    Io(IoError),
+    Json {
+        error: JsonError,
+        /// Where the error happened, see `Error::context`.
+        context: Option<Box<str>>,
+    },
+    /// A row of `SQLite::execute_many` failed, `row` is its index in the input.
+    Batch {
+        row: usize,
+        source: Box<Error>,
+        /// Where the error happened, see `Error::context`.
+        context: Option<Box<str>>,
+    },
+}
+
+/// Error of an `Io` error given a context, the original error is its source.
+#[derive(Debug)]
+pub struct ContextError {
+    pub context: String,
+    pub source: IoError,
+}
+
+impl Display for ContextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.context, self.source)
+    }
+}
+
+impl std::error::Error for ContextError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Error: {} ({})", self.message, self.code)
+        match self {
+            Error::Sqlite(e) => write!(f, "{}", e.message),
+            Error::Conversion(e) => write!(f, "{e}"),
+            Error::Validation(message) => write!(f, "{message}"),
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Json { error, context: Some(context) } => write!(f, "{context}: {error}"),
+            Error::Json { error, context: None } => write!(f, "{error}"),
+            Error::Batch { row, source, context } => {
+                if let Some(context) = context {
+                    write!(f, "{context}: ")?;
+                }
+                write!(f, "execute_many: row {row}: {source}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Conversion(e) => Some(e.as_ref()),
+            Error::Io(e) => Some(e),
+            Error::Json { error, .. } => Some(error),
+            Error::Batch { source, .. } => Some(source.as_ref()),
+            _ => None
+        }
     }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    /// Error text, without codes.
+    pub fn message(&self) -> String {
+        self.to_string()
+    }
+
+    /// Primary SQLite result code, -1 for errors not reported by SQLite.
+    pub fn code(&self) -> i32 {
+        self.sqlite().map_or(-1, |e| e.code)
+    }
+
+    /// Extended SQLite result code, None for errors not reported by SQLite.
+    pub fn extended_code(&self) -> Option<i32> {
+        self.sqlite().map(|e| e.extended_code)
+    }
+
+    pub fn sqlite(&self) -> Option<&SqliteError> {
+        match self {
+            Error::Sqlite(e) => Some(e),
+            Error::Batch { source, .. } => source.sqlite(),
+            _ => None
+        }
+    }
+
+    pub fn conversion(&self) -> Option<&ConversionError> {
+        match self {
+            Error::Conversion(e) => Some(e.as_ref()),
+            Error::Batch { source, .. } => source.conversion(),
+            _ => None
+        }
+    }
+
+    /// Index of the failed row of a batch, see `SQLite::execute_many`.
+    pub fn row(&self) -> Option<usize> {
+        match self {
+            Error::Batch { row, .. } => Some(*row),
+            _ => None
+        }
+    }
+
+    /// The database is locked by another connection (`SQLITE_BUSY`).
+    pub fn is_busy(&self) -> bool {
+        self.code() == SQLITE_BUSY
+    }
+
+    /// A table is locked by this connection (`SQLITE_LOCKED`).
+    pub fn is_locked(&self) -> bool {
+        self.code() == SQLITE_LOCKED
+    }
+
+    /// A constraint failed (`SQLITE_CONSTRAINT`).
+    pub fn is_constraint(&self) -> bool {
+        self.code() == SQLITE_CONSTRAINT
+    }
+
+    /// The database file is damaged or not a database (`SQLITE_CORRUPT`, `SQLITE_NOTADB`).
+    pub fn is_corrupt(&self) -> bool {
+        matches!(self.code(), SQLITE_CORRUPT | SQLITE_NOTADB)
+    }
+
+    /// Write to a read-only database (`SQLITE_READONLY`).
+    pub fn is_read_only(&self) -> bool {
+        self.code() == SQLITE_READONLY
+    }
+
+    /// Prefix the message with where the error happened, codes and sources are kept.
+    /// An `Io` error keeps its kind and gets the original error as its source.
+    pub fn context(self, context: &str) -> Error {
+        match self {
+            Error::Sqlite(mut e) => {
+                e.message = format!("{context}: {}", e.message);
+                Error::Sqlite(e)
+            },
+            Error::Conversion(mut e) => {
+                e.context = Some(match e.context {
+                    Some(inner) => format!("{context}: {inner}").into(),
+                    None => context.into()
+                });
+                Error::Conversion(e)
+            },
+            Error::Validation(message) => Error::Validation(format!("{context}: {message}")),
+            Error::Io(e) => Error::Io(IoError::new(e.kind(), ContextError { context: context.to_string(), source: e })),
+            Error::Json { error, context: inner } => Error::Json {
+                error,
+                context: Some(match inner {
+                    Some(inner) => format!("{context}: {inner}").into(),
+                    None => context.into()
+                })
+            },
+            Error::Batch { row, source, context: inner } => Error::Batch {
+                row,
+                source,
+                context: Some(match inner {
+                    Some(inner) => format!("{context}: {inner}").into(),
+                    None => context.into()
+                })
+            }
+        }
+    }
+}
+
 impl From<&str> for Error {
     fn from(err: &str) -> Self {
-        Error { code: -1, message: err.to_string(), kind: None }
-    }   
+        Error::Validation(err.to_string())
+    }
+}
+
+impl From<String> for Error {
+    fn from(err: String) -> Self {
+        Error::Validation(err)
+    }
 }
 
 impl From<Box<dyn std::error::Error>> for Error {
     fn from(value: Box<dyn std::error::Error>) -> Self {
-        Error { code: -1, message: value.to_string(), kind: None }   
+        Error::Validation(value.to_string())
     }
 }
 
 impl From<JsonError> for Error {
     fn from(err: JsonError) -> Self {
-        Error {
-            code: -1,
-            message: err.to_string(),
-            kind: err.io_error_kind()
-        }
+        Error::Json { error: err, context: None }
     }
 }
 
 impl From<IoError> for Error {
     fn from(err: IoError) -> Error {
-        Error { code: err.raw_os_error().unwrap(), message: err.to_string(), kind: Some(err.kind()) }
+        Error::Io(err)
+    }
+}
+
+impl From<SqliteError> for Error {
+    fn from(err: SqliteError) -> Error {
+        Error::Sqlite(err)
+    }
+}
+
+impl From<ConversionError> for Error {
+    fn from(err: ConversionError) -> Error {
+        Error::Conversion(Box::new(err))
     }
 }
 
 pub fn from_errno() -> Error {
-    unsafe {
-        let errno = io::Error::last_os_error().raw_os_error().unwrap();
-        let message = libc::strerror(errno);
-        let cstr = CStr::from_ptr(message);
-        let message = cstr.to_str().unwrap().to_string();
-        let kind = io::Error::last_os_error().kind();
-        Error { code: errno, message, kind: Some(kind) }
+    Error::Io(io::Error::last_os_error())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+    use crate::query::Query;
+    use std::error::Error as _;
+
+    #[test]
+    fn sqlite_error_test() {
+        let mut sq = test_database("CREATE TABLE t (a INT UNIQUE)");
+        sq.exec(Query::new("INSERT INTO t VALUES (1)")).unwrap();
+        let e = sq.exec(Query::new("INSERT INTO t VALUES (1)")).unwrap_err();
+        assert!(e.is_constraint());
+        assert!(!e.is_busy());
+        assert_eq!(e.extended_code(), Some(sqlite3_sys::SQLITE_CONSTRAINT_UNIQUE));
+        assert_eq!(e.sqlite().unwrap().sql.as_deref(), Some("INSERT INTO t VALUES (1)"));
+
+        let e = sq.select(Query::new("SELECT nope FROM t")).unwrap_err();
+        let Error::Sqlite(e) = e else { panic!("{e:?}") };
+        assert_eq!(e.message, "no such column: nope");
+        assert_eq!(e.offset, Some(7));
+        assert_eq!(e.sql.as_deref(), Some("SELECT nope FROM t"));
+    }
+
+    #[test]
+    fn other_errors_test() {
+        let e = Error::from(IoError::other("disk"));
+        assert!(e.source().is_some());
+        assert_eq!(e.code(), -1);
+        assert_eq!(e.context("open").to_string(), "open: disk");
+
+        // the original error stays reachable with its OS code
+        let e = Error::from(IoError::from_raw_os_error(2)).context("open");
+        let Error::Io(io) = &e else { panic!("{e:?}") };
+        assert_eq!(io.kind(), io::ErrorKind::NotFound);
+        let inner = e.source().unwrap().source().unwrap().downcast_ref::<IoError>().unwrap();
+        assert_eq!(inner.raw_os_error(), Some(2));
+        assert!(e.to_string().starts_with("open: "));
+
+        let e = Error::from(serde_json::from_str::<i32>("x").unwrap_err());
+        assert!(matches!(e, Error::Json { .. }));
+        assert!(e.source().is_some());
+        let e = e.context("load").context("import");
+        assert!(e.to_string().starts_with("import: load: expected value"), "{e}");
+        assert!(e.source().unwrap().is::<JsonError>());
+
+        let e = Error::from(ConversionError::new("u8", "I64", "300"));
+        assert!(e.source().unwrap().is::<ConversionError>());
+
+        let e = Error::from("bad input").context("step");
+        assert!(matches!(&e, Error::Validation(m) if m == "step: bad input"));
+        assert!(e.source().is_none());
     }
 }
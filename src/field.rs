@@ -1,4 +1,7 @@
+use crate::coerce::Coerce;
+use crate::error::{ConversionError, Error, Result};
 use crate::value::Value;
+use crate::Row;
 
 pub struct Field {
     pub name: String,
@@ -9,4 +12,81 @@ impl Field {
     pub fn new(name: String, value: Value) -> Field {
         Field { name, value }   
     }
+
+    /// Value converted to `T`, errors name the field.
+    pub fn try_get<T>(&self) -> Result<T>
+        where T: TryFrom<Value, Error = ConversionError>
+    {
+        T::try_from(self.value.clone()).map_err(|e| e.column(&self.name).into())
+    }
+}
+
+/// Checked access to the columns of a `Row`, errors name the column.
+pub trait RowExt {
+    /// Column converted to `T`, a missing column or NULL is an error.
+    fn try_get<T>(&self, column: &str) -> Result<T>
+        where T: TryFrom<Value, Error = ConversionError>;
+
+    /// Column converted to `T`, NULL as `None`.
+    fn try_get_opt<T>(&self, column: &str) -> Result<Option<T>>
+        where T: TryFrom<Value, Error = ConversionError>;
+
+    /// Column converted to `T` with SQLite's affinity rules, see `Coerce`.
+    fn coerce<T: Coerce>(&self, column: &str) -> Result<T>;
+}
+
+impl RowExt for Row {
+    fn try_get<T>(&self, column: &str) -> Result<T>
+        where T: TryFrom<Value, Error = ConversionError>
+    {
+        let value = self.get(column).ok_or_else(|| Error::Validation(format!("no such column in row: {column}")))?;
+        T::try_from(value.clone()).map_err(|e| e.column(column).into())
+    }
+
+    fn try_get_opt<T>(&self, column: &str) -> Result<Option<T>>
+        where T: TryFrom<Value, Error = ConversionError>
+    {
+        match self.get(column) {
+            Some(Value::Null) => Ok(None),
+            _ => self.try_get(column).map(Some)
+        }
+    }
+
+    fn coerce<T: Coerce>(&self, column: &str) -> Result<T> {
+        let value = self.get(column).ok_or_else(|| Error::Validation(format!("no such column in row: {column}")))?;
+        T::coerce(value.clone()).map_err(|e| match e {
+            Error::Conversion(e) => e.column(column).into(),
+            e => e
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+    use crate::query::Query;
+
+    #[test]
+    fn row_try_get_test() {
+        let mut sq = test_database("CREATE TABLE t (age INT, name TEXT)");
+        sq.exec(Query::new("INSERT INTO t VALUES (300, NULL)")).unwrap();
+        let row = sq.select(Query::new("SELECT age, name FROM t")).unwrap().remove(0);
+
+        assert_eq!(row.try_get::<i64>("age").unwrap(), 300);
+        let e = row.try_get::<u8>("age").unwrap_err();
+        assert_eq!(e.to_string(), "cannot convert I64 300 to u8: out of range (column age)");
+        assert_eq!(e.conversion().unwrap().column.as_deref(), Some("age"));
+
+        // NULL is told apart from a mismatch
+        assert_eq!(row.try_get_opt::<String>("name").unwrap(), None);
+        assert_eq!(row.try_get::<String>("name").unwrap_err().conversion().unwrap().actual, "Null");
+        assert!(row.try_get_opt::<String>("age").is_err());
+        assert!(matches!(row.try_get::<i64>("nope"), Err(Error::Validation(_))));
+
+        let field = Field::new("age".into(), Value::I64(-5));
+        assert_eq!(field.try_get::<u16>().unwrap_err().to_string(), "cannot convert I64 -5 to u16: out of range (column age)");
+        assert_eq!(Value::I64(7).try_get::<u16>().unwrap(), 7);
+        assert_eq!(Value::Null.try_get_opt::<u16>().unwrap(), None);
+    }
 }
@@ -0,0 +1,188 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Number, Value as Json};
+use crate::encoding::{base64_decode, base64_encode};
+use crate::error::{ConversionError, Error, Result};
+use crate::value::Value;
+use crate::{QueryResult, Row};
+
+/// Key of the object wrapping a base64 blob: `{"$blob": "AQID"}`.
+pub const BLOB_TAG: &str = "$blob";
+
+/// How blobs are written in plain JSON.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlobFormat {
+    /// `{"$blob": "<base64>"}`, read back as a blob.
+    #[default]
+    Tagged,
+    /// Bare base64 string, read back as text.
+    Base64,
+}
+
+impl Value {
+    /// Plain JSON: numbers, strings and null map directly, blobs become tagged base64.
+    /// Non-finite reals have no JSON form and become null.
+    pub fn to_json_value(&self) -> Json {
+        self.to_json_value_with(BlobFormat::Tagged)
+    }
+
+    pub fn to_json_value_with(&self, blobs: BlobFormat) -> Json {
+        match self {
+            Value::Null => Json::Null,
+            Value::I64(v) => Json::from(*v),
+            Value::F64(v) => Number::from_f64(*v).map(Json::Number).unwrap_or(Json::Null),
+            Value::Text(v) => Json::from(v.as_str()),
+            Value::Blob(v) => match blobs {
+                BlobFormat::Tagged => {
+                    let mut object = Map::new();
+                    object.insert(BLOB_TAG.to_string(), Json::from(base64_encode(v)));
+                    Json::Object(object)
+                },
+                BlobFormat::Base64 => Json::from(base64_encode(v))
+            }
+        }
+    }
+
+    /// Read plain JSON, the externally tagged form (`{"I64": 1}`) is accepted too.
+    /// Booleans become 0/1, integers outside i64 become reals.
+    pub fn from_json_value(json: &Json) -> Result<Value> {
+        match json {
+            Json::Null => Ok(Value::Null),
+            Json::Bool(v) => Ok(Value::I64(*v as i64)),
+            Json::Number(v) => match (v.as_i64(), v.as_f64()) {
+                (Some(v), _) => Ok(Value::I64(v)),
+                (None, Some(v)) => Ok(Value::F64(v)),
+                _ => Err(ConversionError::new("Value", "JSON number", &v.to_string()).reason("out of range").into())
+            },
+            Json::String(v) => Ok(Value::Text(v.clone())),
+            Json::Object(object) if object.len() == 1 => {
+                let (key, inner) = object.iter().next().unwrap();
+                match (key.as_str(), inner) {
+                    (BLOB_TAG, Json::String(text)) => base64_decode(text)
+                        .map(Value::Blob)
+                        .ok_or_else(|| ConversionError::new("Blob", "JSON string", &Value::preview_text(text)).reason("invalid base64").into()),
+                    ("Null" | "I64" | "F64" | "Text" | "Blob", _) => Ok(serde_json::from_value(json.clone())?),
+                    _ => Err("json: unsupported object".into())
+                }
+            },
+            _ => Err("json: unsupported value".into())
+        }
+    }
+}
+
+impl From<&Value> for Json {
+    fn from(value: &Value) -> Self {
+        value.to_json_value()
+    }
+}
+
+impl From<Value> for Json {
+    fn from(value: Value) -> Self {
+        value.to_json_value()
+    }
+}
+
+impl TryFrom<Json> for Value {
+    type Error = Error;
+    fn try_from(json: Json) -> Result<Self> {
+        Value::from_json_value(&json)
+    }
+}
+
+impl TryFrom<&Json> for Value {
+    type Error = Error;
+    fn try_from(json: &Json) -> Result<Self> {
+        Value::from_json_value(json)
+    }
+}
+
+/// Row as a plain JSON object.
+pub fn row_to_json(row: &Row) -> Json {
+    Json::Object(row.iter().map(|(k, v)| (k.clone(), v.to_json_value())).collect())
+}
+
+/// Row from a plain JSON object.
+pub fn row_from_json(json: &Json) -> Result<Row> {
+    match json {
+        Json::Object(object) => object
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), Value::from_json_value(v)?)))
+            .collect(),
+        _ => Err("json: row must be an object".into())
+    }
+}
+
+/// Query result as a plain JSON array of objects.
+pub fn result_to_json(result: &QueryResult) -> Json {
+    Json::Array(result.iter().map(row_to_json).collect())
+}
+
+/// Query result from a plain JSON array of objects.
+pub fn result_from_json(json: &Json) -> Result<QueryResult> {
+    match json {
+        Json::Array(rows) => rows.iter().map(row_from_json).collect(),
+        _ => Err("json: result must be an array".into())
+    }
+}
+
+/// Serde adapter for plain JSON values, use with `#[serde(with = "sql3x::json::plain")]`.
+pub mod plain {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &Value, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        value.to_json_value().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Value, D::Error> {
+        let json = Json::deserialize(deserializer)?;
+        Value::from_json_value(&json).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn value_test() {
+        let values = [Value::Null, Value::I64(-5), Value::F64(1.0), Value::from("hello"), Value::Blob(vec![1, 2, 3])];
+        let plain = values.iter().map(Json::from).collect::<Vec<_>>();
+        assert_eq!(Json::Array(plain.clone()), json!([null, -5, 1.0, "hello", {"$blob": "AQID"}]));
+        for (value, json) in values.iter().zip(&plain) {
+            assert_eq!(&Value::try_from(json).unwrap(), value);
+        }
+
+        assert_eq!(Value::Blob(vec![1, 2, 3]).to_json_value_with(BlobFormat::Base64), json!("AQID"));
+        assert_eq!(Value::F64(f64::NAN).to_json_value(), Json::Null);
+        assert_eq!(Value::from_json_value(&json!(true)).unwrap(), Value::I64(1));
+        assert_eq!(Value::from_json_value(&json!(u64::MAX)).unwrap(), Value::F64(u64::MAX as f64));
+        assert_eq!(Value::from_json_value(&json!({"Text": "x"})).unwrap(), Value::from("x"));
+        assert_eq!(Value::from_json_value(&json!({"Blob": [1, 2]})).unwrap(), Value::Blob(vec![1, 2]));
+        assert!(Value::from_json_value(&json!([1])).is_err());
+        assert!(Value::from_json_value(&json!({"$blob": "!"})).is_err());
+    }
+
+    #[test]
+    fn result_test() {
+        let mut row = Row::new();
+        row.insert("id".into(), Value::I64(1));
+        row.insert("data".into(), Value::Blob(vec![0xff]));
+        let result = vec![row];
+        let json = result_to_json(&result);
+        assert_eq!(json, json!([{"id": 1, "data": {"$blob": "/w=="}}]));
+        assert_eq!(result_from_json(&json).unwrap(), result);
+    }
+
+    #[test]
+    fn adapter_test() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Cell {
+            #[serde(with = "plain")]
+            value: Value,
+        }
+        let cell = Cell { value: Value::F64(2.5) };
+        let text = serde_json::to_string(&cell).unwrap();
+        assert_eq!(text, r#"{"value":2.5}"#);
+        assert_eq!(serde_json::from_str::<Cell>(&text).unwrap(), cell);
+    }
+}
@@ -0,0 +1,67 @@
+/// Byte ranges of SQL tokens, comments and whitespace skipped.
+pub(crate) fn tokens(sql: &str) -> Vec<(usize, usize)> {
+    let bytes = sql.as_bytes();
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+        match c {
+            b' ' | b'\t' | b'\n' | b'\r' => {
+                i += 1;
+                continue;
+            },
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            },
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i < bytes.len() && !(bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/')) {
+                    i += 1;
+                }
+                i = (i + 2).min(bytes.len());
+                continue;
+            },
+            b'\'' | b'"' | b'`' | b'[' => {
+                let close = if c == b'[' { b']' } else { c };
+                i += 1;
+                while i < bytes.len() {
+                    if bytes[i] == close {
+                        // doubled quote is an escaped quote
+                        if close != b']' && bytes.get(i + 1) == Some(&close) {
+                            i += 2;
+                            continue;
+                        }
+                        break;
+                    }
+                    i += 1;
+                }
+                i = (i + 1).min(bytes.len());
+            },
+            c if c.is_ascii_alphanumeric() || c == b'_' || c == b'$' || c >= 0x80 => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' || bytes[i] == b'$' || bytes[i] >= 0x80) {
+                    i += 1;
+                }
+            },
+            _ => i += 1
+        }
+        result.push((start, i));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_test() {
+        let sql = "SELECT \"a \"\"b\"\"\", [c d] -- note\nFROM t /* x */ WHERE v='it''s';";
+        let tokens = tokens(sql).into_iter().map(|(s, e)| &sql[s..e]).collect::<Vec<_>>();
+        assert_eq!(tokens, vec!["SELECT", "\"a \"\"b\"\"\"", ",", "[c d]", "FROM", "t", "WHERE", "v", "=", "'it''s'", ";"]);
+        assert_eq!(super::tokens("'open").len(), 1);
+    }
+}
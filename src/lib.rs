@@ -8,30 +8,57 @@ pub mod db;
 pub mod value;
 pub mod error;
 pub mod timestamp;
+pub mod datetime;
+pub mod clock;
 pub mod args;
 pub mod query;
 pub mod stmt;
+pub mod statement;
 pub mod value_try_from;
+pub mod value_ref;
+pub mod coerce;
 pub mod field;
+pub mod builder;
+pub mod schema;
+pub mod schema_diff;
+mod lexer;
+pub mod encoding;
+pub mod csv;
+pub mod json;
+pub mod dump;
+pub mod constraint;
+pub mod pretty;
+#[cfg(feature = "server")]
+pub mod policy;
+#[cfg(feature = "server")]
+pub mod remote;
+#[cfg(feature = "server")]
+pub mod pgwire;
 
 pub type Row = HashMap<String, value::Value>;
 pub type QueryResult = Vec<Row>;
 
 pub mod prelude {
-    pub use crate::db::SQLite;
+    pub use crate::db::{SQLite, BatchResult, ExecResult};
     pub use crate::query::Query;
     pub use crate::value::Value;
     pub use crate::value_try_from;
-    pub use crate::field::Field;
-    pub use crate::timestamp::Timestamp;
-    pub use crate::args::Args;
+    pub use crate::value_ref::{FromValueRef, RowRef, ValueRef};
+    pub use crate::coerce::Coerce;
+    pub use crate::field::{Field, RowExt};
+    pub use crate::timestamp::{TimeUnit, Timestamp};
+    pub use crate::clock::{Clock, ManualClock, SystemClock};
+    pub use crate::datetime::{JulianDay, TimeFormat, TimeType, UnixMillis, UnixSeconds};
+    pub use crate::args::{Args, AsBlob, AsText};
     pub use crate::stmt::Stmt;
+    pub use crate::statement::{Statement, Step};
     pub use crate::Row;
     pub use crate::QueryResult;
-    pub use crate::error::Result;
+    pub use crate::error::{ConversionError, Error, Result};
 }
 
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default, clippy::let_unit_value, clippy::needless_borrows_for_generic_args)]
 mod tests {
     use std::fmt::{write, Debug, Display, Formatter};
     use crate::db::SQLite;
@@ -168,7 +195,8 @@ mod tests {
                 .arg(&self.surname)
                 .arg(&self.birthday)
                 .arg(self.id)
-                .update(sq)
+                .update(sq)?;
+            Ok(())
         }
         
         pub fn all(sq: &mut SQLite) -> Result<QueryResult> {
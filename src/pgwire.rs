@@ -0,0 +1,1110 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::thread;
+use crate::args::Args;
+use crate::db::SQLite;
+use crate::encoding::{hex_decode, hex_encode};
+use crate::error::{ConversionError, Error, Result};
+use crate::query::Query;
+use crate::policy::Policy;
+use crate::value::Value;
+use sqlite3_sys::{
+    SQLITE_BUSY,
+    SQLITE_CONSTRAINT,
+    SQLITE_CONSTRAINT_CHECK,
+    SQLITE_CONSTRAINT_FOREIGNKEY,
+    SQLITE_CONSTRAINT_NOTNULL,
+    SQLITE_CONSTRAINT_PRIMARYKEY,
+    SQLITE_CONSTRAINT_UNIQUE,
+    SQLITE_ERROR,
+    SQLITE_LOCKED,
+    SQLITE_READONLY,
+};
+
+const SSL_REQUEST: i32 = 80877103;
+const GSSENC_REQUEST: i32 = 80877104;
+const CANCEL_REQUEST: i32 = 80877102;
+const PROTOCOL_V3: i32 = 196608;
+
+/// Reported to clients, old enough for every tool to accept it.
+const SERVER_VERSION: &str = "14.0";
+
+/// Largest message accepted from a client, length field included.
+const MAX_MESSAGE_LEN: i32 = 16 << 20;
+
+/// Postgres type OIDs used for parameters and row descriptions.
+pub mod oid {
+    pub const BOOL: u32 = 16;
+    pub const BYTEA: u32 = 17;
+    pub const INT8: u32 = 20;
+    pub const INT2: u32 = 21;
+    pub const INT4: u32 = 23;
+    pub const TEXT: u32 = 25;
+    pub const FLOAT4: u32 = 700;
+    pub const FLOAT8: u32 = 701;
+    pub const VARCHAR: u32 = 1043;
+    pub const NUMERIC: u32 = 1700;
+}
+
+/// Type OID of a value, NULL is reported as text.
+pub fn value_oid(value: &Value) -> u32 {
+    match value {
+        Value::Null | Value::Text(_) => oid::TEXT,
+        Value::I64(_) => oid::INT8,
+        Value::F64(_) => oid::FLOAT8,
+        Value::Blob(_) => oid::BYTEA
+    }
+}
+
+/// Type OID of a declared column type, following SQLite's affinity rules.
+/// NUMERIC affinity and columns without a type are reported as text, their values may be of any kind.
+pub fn decltype_oid(decltype: &str) -> u32 {
+    let decltype = decltype.to_ascii_uppercase();
+    if decltype.contains("INT") {
+        oid::INT8
+    } else if ["CHAR", "CLOB", "TEXT"].iter().any(|t| decltype.contains(t)) {
+        oid::TEXT
+    } else if decltype.contains("BLOB") {
+        oid::BYTEA
+    } else if ["REAL", "FLOA", "DOUB"].iter().any(|t| decltype.contains(t)) {
+        oid::FLOAT8
+    } else {
+        oid::TEXT
+    }
+}
+
+/// Size of a type in row descriptions, -1 for variable length.
+fn type_size(oid: u32) -> i16 {
+    match oid {
+        oid::BOOL => 1,
+        oid::INT2 => 2,
+        oid::INT4 | oid::FLOAT4 => 4,
+        oid::INT8 | oid::FLOAT8 => 8,
+        _ => -1
+    }
+}
+
+/// Postgres text form of a float.
+fn float_text(v: f64) -> String {
+    match v {
+        v if v.is_nan() => "NaN".into(),
+        v if v.is_infinite() => if v > 0.0 { "Infinity".into() } else { "-Infinity".into() },
+        v if v != 0.0 && (v.abs() >= 1e16 || v.abs() < 1e-4) => {
+            let text = format!("{v:e}");
+            match text.contains("e-") {
+                true => text,
+                false => text.replace('e', "e+")
+            }
+        },
+        v => v.to_string()
+    }
+}
+
+/// Text format of a value, None for NULL.
+fn encode_text(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::Null => None,
+        Value::I64(v) => Some(v.to_string().into_bytes()),
+        Value::F64(v) => Some(float_text(*v).into_bytes()),
+        Value::Text(v) => Some(v.as_bytes().to_vec()),
+        Value::Blob(v) => Some(format!("\\x{}", hex_encode(v)).into_bytes())
+    }
+}
+
+/// Binary format of a value for a column of the given type, None for NULL.
+fn encode_binary(value: &Value, oid: u32) -> Result<Option<Vec<u8>>> {
+    let mismatch = || Error::from("pgwire: value doesn't match the column type in binary format");
+    Ok(match (oid, value) {
+        (_, Value::Null) => None,
+        (oid::INT8, Value::I64(v)) => Some(v.to_be_bytes().to_vec()),
+        (oid::INT8, _) => return Err(mismatch()),
+        (oid::FLOAT8, Value::F64(v)) => Some(v.to_be_bytes().to_vec()),
+        (oid::FLOAT8, Value::I64(v)) => Some((*v as f64).to_be_bytes().to_vec()),
+        (oid::FLOAT8, _) => return Err(mismatch()),
+        (oid::BYTEA, Value::Blob(v)) => Some(v.clone()),
+        (oid::BYTEA, Value::Text(v)) => Some(v.as_bytes().to_vec()),
+        (oid::BYTEA, _) => return Err(mismatch()),
+        // text and everything else: the text form
+        (_, Value::Blob(v)) => Some(v.clone()),
+        (_, value) => encode_text(value)
+    })
+}
+
+/// Value of a bound parameter.
+fn decode_param(data: Option<&[u8]>, oid: u32, binary: bool) -> Result<Value> {
+    let Some(data) = data else {
+        return Ok(Value::Null);
+    };
+    let invalid = || {
+        let preview = Value::preview_text(&String::from_utf8_lossy(data));
+        Error::from(ConversionError::new(&format!("parameter of type {oid}"), if binary { "binary" } else { "text" }, &preview))
+    };
+    if binary {
+        return match (oid, data.len()) {
+            (oid::INT2, 2) => Ok(Value::I64(i16::from_be_bytes([data[0], data[1]]) as i64)),
+            (oid::INT4, 4) => Ok(Value::I64(i32::from_be_bytes(data.try_into().unwrap()) as i64)),
+            (oid::INT8, 8) => Ok(Value::I64(i64::from_be_bytes(data.try_into().unwrap()))),
+            (oid::FLOAT4, 4) => Ok(Value::F64(f32::from_be_bytes(data.try_into().unwrap()) as f64)),
+            (oid::FLOAT8, 8) => Ok(Value::F64(f64::from_be_bytes(data.try_into().unwrap()))),
+            (oid::BOOL, 1) => Ok(Value::I64((data[0] != 0) as i64)),
+            (oid::INT2 | oid::INT4 | oid::INT8 | oid::FLOAT4 | oid::FLOAT8 | oid::BOOL, _) => Err(invalid()),
+            (oid::BYTEA, _) => Ok(Value::Blob(data.to_vec())),
+            _ => match std::str::from_utf8(data) {
+                Ok(text) => Ok(Value::from(text)),
+                Err(_) => Ok(Value::Blob(data.to_vec()))
+            }
+        };
+    }
+    let text = std::str::from_utf8(data).map_err(|_| invalid())?;
+    match oid {
+        oid::INT2 | oid::INT4 | oid::INT8 => text.trim().parse().map(Value::I64).map_err(|_| invalid()),
+        oid::FLOAT4 | oid::FLOAT8 | oid::NUMERIC => text.trim().parse().map(Value::F64).map_err(|_| invalid()),
+        oid::BOOL => match text.trim().to_ascii_lowercase().as_str() {
+            "t" | "true" | "1" | "on" | "yes" | "y" => Ok(Value::I64(1)),
+            "f" | "false" | "0" | "off" | "no" | "n" => Ok(Value::I64(0)),
+            _ => Err(invalid())
+        },
+        oid::BYTEA => match text.strip_prefix("\\x") {
+            Some(hex) => hex_decode(hex).map(Value::Blob).ok_or_else(invalid),
+            None => Ok(Value::Blob(data.to_vec()))
+        },
+        _ => Ok(Value::from(text))
+    }
+}
+
+/// Rewrite Postgres `$n` placeholders to SQLite `?n`, outside strings, identifiers and comments.
+pub fn placeholders(sql: &str) -> String {
+    let mut result = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                result.push(c);
+                for inner in chars.by_ref() {
+                    result.push(inner);
+                    if inner == c {
+                        break;
+                    }
+                }
+            },
+            '-' if chars.peek() == Some(&'-') => {
+                result.push(c);
+                for inner in chars.by_ref() {
+                    result.push(inner);
+                    if inner == '\n' {
+                        break;
+                    }
+                }
+            },
+            '$' if chars.peek().is_some_and(|c| c.is_ascii_digit()) => result.push('?'),
+            c => result.push(c)
+        }
+    }
+    result
+}
+
+/// SQLSTATE code for an error.
+fn sqlstate(e: &Error) -> &'static str {
+    let message = e.message();
+    let message = message.as_str();
+    match e {
+        Error::Sqlite(e) => match (e.code, e.extended_code) {
+            (_, SQLITE_CONSTRAINT_UNIQUE | SQLITE_CONSTRAINT_PRIMARYKEY) => "23505",
+            (_, SQLITE_CONSTRAINT_FOREIGNKEY) => "23503",
+            (_, SQLITE_CONSTRAINT_NOTNULL) => "23502",
+            (_, SQLITE_CONSTRAINT_CHECK) => "23514",
+            (SQLITE_CONSTRAINT, _) => "23000",
+            (SQLITE_READONLY, _) => "25006",
+            (SQLITE_BUSY | SQLITE_LOCKED, _) => "55P03",
+            (SQLITE_ERROR, _) if message.contains("syntax error") || message.contains("incomplete input") => "42601",
+            (SQLITE_ERROR, _) if message.contains("no such table") => "42P01",
+            (SQLITE_ERROR, _) if message.contains("no such column") => "42703",
+            (SQLITE_ERROR, _) if message.contains("already exists") => "42P07",
+            _ => "XX000"
+        },
+        Error::Conversion(_) => "22P02",
+        _ if message.starts_with("policy:") => "42501",
+        _ if message.starts_with("pgwire:") => "08P01",
+        _ => "XX000"
+    }
+}
+
+/// Statements answered by the front end instead of SQLite.
+#[derive(Clone, Debug, PartialEq)]
+enum Special {
+    /// `SET ...`, accepted and ignored.
+    Set,
+    /// `SHOW name`.
+    Show(String),
+    /// `SELECT version()`.
+    Version,
+    /// psql's `\d`, `\dt` and `\dv` relation list.
+    Relations { tables: bool, views: bool, pattern: Option<String> },
+}
+
+impl Special {
+    fn parse(sql: &str) -> Option<Special> {
+        let normalized = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+        let normalized = normalized.trim_end_matches(';').trim_end();
+        let lower = normalized.to_ascii_lowercase();
+        if lower.starts_with("set ") {
+            return Some(Special::Set);
+        }
+        if let Some(name) = lower.strip_prefix("show ") {
+            return Some(Special::Show(name.trim().to_string()));
+        }
+        if lower == "select version()" || lower == "select pg_catalog.version()" {
+            return Some(Special::Version);
+        }
+        if normalized.contains("FROM pg_catalog.pg_class c") && normalized.contains("as \"Name\"") {
+            let kinds = between(normalized, "c.relkind IN (", ")")?;
+            let pattern = between(normalized, "OPERATOR(pg_catalog.~) '", "'").map(String::from);
+            return Some(Special::Relations {
+                tables: kinds.contains("'r'"),
+                views: kinds.contains("'v'"),
+                pattern,
+            });
+        }
+        None
+    }
+
+    fn columns(&self) -> Vec<String> {
+        let names: &[&str] = match self {
+            Special::Set => &[],
+            Special::Show(name) => return vec![name.clone()],
+            Special::Version => &["version"],
+            Special::Relations { .. } => &["Schema", "Name", "Type", "Owner"]
+        };
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn run(&self, sq: &mut SQLite) -> Result<Outcome> {
+        let text = |v: &str| Value::from(v);
+        let (rows, tag) = match self {
+            Special::Set => (vec![], "SET".to_string()),
+            Special::Show(name) => match parameter(name) {
+                Some(value) => (vec![vec![text(&value)]], "SHOW".to_string()),
+                None => return Err(format!("unrecognized configuration parameter \"{name}\"").as_str().into())
+            },
+            Special::Version => {
+                let version = format!("PostgreSQL {SERVER_VERSION} (sql3x, SQLite {})", SQLite::version());
+                (vec![vec![text(&version)]], "SELECT 1".to_string())
+            },
+            Special::Relations { tables, views, pattern } => {
+                let schema = sq.schema()?;
+                let matches = |name: &str| pattern.as_deref().is_none_or(|p| regex_match(p, name));
+                let mut rows = Vec::new();
+                if *tables {
+                    rows.extend(schema.tables.iter().filter(|t| matches(&t.name)).map(|t| (t.name.clone(), "table")));
+                }
+                if *views {
+                    rows.extend(schema.views.iter().filter(|v| matches(&v.name)).map(|v| (v.name.clone(), "view")));
+                }
+                rows.sort();
+                let rows = rows.into_iter().map(|(name, kind)| vec![text("main"), text(&name), text(kind), text("sqlite")]).collect::<Vec<_>>();
+                let tag = format!("SELECT {}", rows.len());
+                (rows, tag)
+            }
+        };
+        let columns = self.columns();
+        let types = vec![oid::TEXT; columns.len()];
+        Ok(Outcome { columns, types, rows, tag })
+    }
+}
+
+fn between<'a>(text: &'a str, start: &str, end: &str) -> Option<&'a str> {
+    let from = text.find(start)? + start.len();
+    let to = text[from..].find(end)?;
+    Some(&text[from..from + to])
+}
+
+/// Match a name against the regular expression psql builds from a pattern: `^(name.*)$`.
+/// Only `.`, `.*` and `\` escapes occur there, case is ignored.
+fn regex_match(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match pattern {
+            [] => name.is_empty(),
+            ['.', '*', rest @ ..] => (0..=name.len()).any(|i| matches(rest, &name[i..])),
+            ['.', rest @ ..] => !name.is_empty() && matches(rest, &name[1..]),
+            ['\\', c, rest @ ..] | [c, rest @ ..] => name.first() == Some(c) && matches(rest, &name[1..])
+        }
+    }
+    let pattern = pattern.trim_start_matches('^').trim_end_matches('$');
+    let pattern = pattern.strip_prefix('(').and_then(|p| p.strip_suffix(')')).unwrap_or(pattern);
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+    matches(&pattern, &name.to_lowercase().chars().collect::<Vec<_>>())
+}
+
+/// Server parameters reported at startup and by SHOW.
+fn parameter(name: &str) -> Option<String> {
+    let value = match name.to_ascii_lowercase().as_str() {
+        "server_version" => SERVER_VERSION,
+        "server_encoding" | "client_encoding" => "UTF8",
+        "datestyle" => "ISO, MDY",
+        "integer_datetimes" | "standard_conforming_strings" => "on",
+        "timezone" => "UTC",
+        "transaction_isolation" | "default_transaction_isolation" => "serializable",
+        _ => return None
+    };
+    Some(value.to_string())
+}
+
+/// Columns, types and all rows of an executed statement.
+#[derive(Clone, Debug, Default)]
+struct Outcome {
+    columns: Vec<String>,
+    types: Vec<u32>,
+    rows: Vec<Vec<Value>>,
+    tag: String,
+}
+
+/// Postgres command tag of an executed statement.
+fn command_tag(sql: &str, columns: usize, rows: usize, changes: i64) -> String {
+    let words = sql.split_whitespace().map(|w| w.trim_end_matches(';').to_ascii_uppercase()).collect::<Vec<_>>();
+    let first = words.first().cloned().unwrap_or_default();
+    match first.as_str() {
+        "INSERT" => format!("INSERT 0 {changes}"),
+        "UPDATE" | "DELETE" => format!("{first} {changes}"),
+        "CREATE" | "DROP" | "ALTER" => {
+            let object = words.iter().skip(1).find(|w| ["TABLE", "INDEX", "VIEW", "TRIGGER"].contains(&w.as_str()));
+            match object {
+                Some(object) => format!("{first} {object}"),
+                None => first
+            }
+        },
+        "END" => "COMMIT".to_string(),
+        _ if columns > 0 => format!("SELECT {rows}"),
+        _ => first
+    }
+}
+
+/// Prepared statement of the extended protocol.
+struct Prepared {
+    sql: String,
+    param_types: Vec<u32>,
+    /// Row types sent by Describe, kept so execution reports the same ones.
+    described: Option<Vec<u32>>,
+}
+
+/// Bound statement of the extended protocol.
+struct Portal {
+    sql: String,
+    params: Vec<Value>,
+    formats: Vec<i16>,
+    described: Option<Vec<u32>>,
+    outcome: Option<Outcome>,
+    sent: usize,
+}
+
+/// Format code of a column from Bind's result format codes.
+fn format_of(formats: &[i16], idx: usize) -> i16 {
+    match formats {
+        [] => 0,
+        [format] => *format,
+        formats => formats.get(idx).copied().unwrap_or(0)
+    }
+}
+
+/// Fields of a message body.
+struct Body<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Body<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Body { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(n).ok_or(Error::from("pgwire: message too short"))?;
+        let bytes = self.data.get(self.pos..end).ok_or(Error::from("pgwire: message too short"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn cstr(&mut self) -> Result<String> {
+        let rest = &self.data[self.pos..];
+        let len = rest.iter().position(|b| *b == 0).ok_or(Error::from("pgwire: unterminated string"))?;
+        self.pos += len + 1;
+        Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+}
+
+/// Message body under construction.
+#[derive(Default)]
+struct Buf(Vec<u8>);
+
+impl Buf {
+    fn i16(mut self, v: i16) -> Self {
+        self.0.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+    fn i32(mut self, v: i32) -> Self {
+        self.0.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+    fn cstr(mut self, text: &str) -> Self {
+        self.0.extend_from_slice(text.as_bytes());
+        self.0.push(0);
+        self
+    }
+    fn bytes(mut self, data: &[u8]) -> Self {
+        self.0.extend_from_slice(data);
+        self
+    }
+}
+
+/// One client connection with its own database connection.
+struct Session<S: Read + Write> {
+    stream: S,
+    out: Vec<u8>,
+    sq: SQLite,
+    policy: Policy,
+    statements: HashMap<String, Prepared>,
+    portals: HashMap<String, Portal>,
+    /// After an error in the extended protocol messages are skipped up to Sync.
+    failed: bool,
+}
+
+impl<S: Read + Write> Session<S> {
+    fn run(&mut self) -> Result<()> {
+        if !self.startup()? {
+            return Ok(());
+        }
+        while let Some((tag, body)) = self.read_message()? {
+            if self.failed && tag != b'S' && tag != b'X' {
+                continue;
+            }
+            let result = match tag {
+                b'Q' => {
+                    self.simple_query(&Body::new(&body).cstr()?);
+                    self.ready()
+                },
+                b'P' => self.parse(&body),
+                b'B' => self.bind(&body),
+                b'D' => self.describe(&body),
+                b'E' => self.execute(&body),
+                b'C' => self.close(&body),
+                b'S' => {
+                    self.failed = false;
+                    self.portals.remove("");
+                    self.ready()
+                },
+                b'H' => self.flush(),
+                b'X' => return self.flush(),
+                _ => Err(format!("pgwire: unsupported message '{}'", tag as char).as_str().into())
+            };
+            if let Err(e) = result {
+                self.error(&e);
+                self.failed = true;
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Negotiate the connection, false when the client only wanted to cancel.
+    fn startup(&mut self) -> Result<bool> {
+        loop {
+            let mut header = [0u8; 8];
+            self.stream.read_exact(&mut header)?;
+            let len = i32::from_be_bytes(header[..4].try_into().unwrap());
+            let code = i32::from_be_bytes(header[4..].try_into().unwrap());
+            if !(8..=10_000).contains(&len) {
+                return Err("pgwire: invalid startup message".into());
+            }
+            let mut body = vec![0u8; len as usize - 8];
+            self.stream.read_exact(&mut body)?;
+            match code {
+                SSL_REQUEST | GSSENC_REQUEST => {
+                    // no encryption on a loopback server
+                    self.stream.write_all(b"N")?;
+                    self.stream.flush()?;
+                },
+                CANCEL_REQUEST => return Ok(false),
+                PROTOCOL_V3 => {
+                    let mut body = Body::new(&body);
+                    let mut application = String::new();
+                    loop {
+                        let key = body.cstr()?;
+                        if key.is_empty() {
+                            break;
+                        }
+                        let value = body.cstr()?;
+                        if key == "application_name" {
+                            application = value;
+                        }
+                    }
+                    self.send(b'R', Buf::default().i32(0));
+                    let names = ["server_version", "server_encoding", "client_encoding", "DateStyle", "integer_datetimes", "standard_conforming_strings", "TimeZone"];
+                    for name in names {
+                        let value = parameter(name).unwrap_or_default();
+                        self.send(b'S', Buf::default().cstr(name).cstr(&value));
+                    }
+                    self.send(b'S', Buf::default().cstr("application_name").cstr(&application));
+                    self.send(b'K', Buf::default().i32(std::process::id() as i32).i32(0));
+                    self.ready()?;
+                    return Ok(true);
+                },
+                _ => {
+                    let e = Error::from("pgwire: unsupported protocol version");
+                    self.error(&e);
+                    self.flush()?;
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    fn read_message(&mut self) -> Result<Option<(u8, Vec<u8>)>> {
+        let mut header = [0u8; 5];
+        match self.stream.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into())
+        }
+        let len = i32::from_be_bytes(header[1..].try_into().unwrap());
+        if !(4..=MAX_MESSAGE_LEN).contains(&len) {
+            return Err("pgwire: invalid message length".into());
+        }
+        let mut body = vec![0u8; len as usize - 4];
+        self.stream.read_exact(&mut body)?;
+        Ok(Some((header[0], body)))
+    }
+
+    fn send(&mut self, tag: u8, body: Buf) {
+        self.out.push(tag);
+        self.out.extend_from_slice(&(body.0.len() as i32 + 4).to_be_bytes());
+        self.out.extend_from_slice(&body.0);
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.stream.write_all(&self.out)?;
+        self.stream.flush()?;
+        self.out.clear();
+        Ok(())
+    }
+
+    fn ready(&mut self) -> Result<()> {
+        let status = match self.sq.in_transaction() {
+            true => b'T',
+            false => b'I'
+        };
+        self.send(b'Z', Buf::default().bytes(&[status]));
+        self.flush()
+    }
+
+    fn error(&mut self, e: &Error) {
+        let body = Buf::default()
+            .bytes(b"S").cstr("ERROR")
+            .bytes(b"V").cstr("ERROR")
+            .bytes(b"C").cstr(sqlstate(e))
+            .bytes(b"M").cstr(&e.message())
+            .bytes(&[0]);
+        self.send(b'E', body);
+    }
+
+    fn row_description(&mut self, columns: &[String], types: &[u32], formats: &[i16]) {
+        let mut body = Buf::default().i16(columns.len() as i16);
+        for (idx, (name, &oid)) in columns.iter().zip(types).enumerate() {
+            body = body.cstr(name).i32(0).i16(0).i32(oid as i32).i16(type_size(oid)).i32(-1).i16(format_of(formats, idx));
+        }
+        self.send(b'T', body);
+    }
+
+    fn data_row(&mut self, values: &[Value], types: &[u32], formats: &[i16]) -> Result<()> {
+        let mut body = Buf::default().i16(values.len() as i16);
+        for (idx, (value, &oid)) in values.iter().zip(types).enumerate() {
+            let data = match format_of(formats, idx) {
+                1 => encode_binary(value, oid)?,
+                _ => encode_text(value)
+            };
+            body = match data {
+                Some(data) => body.i32(data.len() as i32).bytes(&data),
+                None => body.i32(-1)
+            };
+        }
+        self.send(b'D', body);
+        Ok(())
+    }
+
+    /// Run one statement, row types come from `described`, declared types or the values.
+    fn run_statement(&mut self, sql: &str, params: Vec<Value>, described: Option<Vec<u32>>) -> Result<Outcome> {
+        if let Some(special) = Special::parse(sql) {
+            return special.run(&mut self.sq);
+        }
+        self.policy.check(&mut self.sq, &Query::new(sql))?;
+        let mut stmt = self.sq.prepare_stmt(sql)?;
+        let columns = stmt.column_names();
+        let decltypes = stmt.column_decltypes();
+        let mut rows = Vec::new();
+        stmt.bind(Args::from(params))?;
+        stmt.for_each_row(|values| {
+            rows.push(values);
+            Ok(())
+        })?;
+
+        let types = described.unwrap_or_else(|| {
+            decltypes
+                .iter()
+                .enumerate()
+                .map(|(idx, decltype)| match decltype {
+                    Some(decltype) => decltype_oid(decltype),
+                    None => {
+                        let mut values = rows.iter().map(|r| &r[idx]).filter(|v| **v != Value::Null);
+                        let first = values.next().map(value_oid).unwrap_or(oid::TEXT);
+                        match values.all(|v| value_oid(v) == first) {
+                            true => first,
+                            false => oid::TEXT
+                        }
+                    }
+                })
+                .collect()
+        });
+        let tag = command_tag(sql, columns.len(), rows.len(), self.sq.changes());
+        Ok(Outcome { columns, types, rows, tag })
+    }
+
+    fn simple_query(&mut self, text: &str) {
+        if text.trim().trim_end_matches(';').trim().is_empty() {
+            self.send(b'I', Buf::default());
+            return;
+        }
+        let mut statements = Vec::new();
+        let mut current = String::new();
+        for piece in text.split_inclusive(';') {
+            current.push_str(piece);
+            if SQLite::is_complete(&current) {
+                statements.push(std::mem::take(&mut current));
+            }
+        }
+        statements.push(current);
+
+        for sql in statements.iter().filter(|s| !s.trim().trim_end_matches(';').trim().is_empty()) {
+            match self.run_statement(sql, vec![], None) {
+                Ok(outcome) => {
+                    if !outcome.columns.is_empty() {
+                        self.row_description(&outcome.columns, &outcome.types, &[]);
+                        for row in &outcome.rows {
+                            // text format can't fail
+                            let _ = self.data_row(row, &outcome.types, &[]);
+                        }
+                    }
+                    self.send(b'C', Buf::default().cstr(&outcome.tag));
+                },
+                Err(e) => {
+                    self.error(&e);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn parse(&mut self, body: &[u8]) -> Result<()> {
+        let mut body = Body::new(body);
+        let name = body.cstr()?;
+        let sql = placeholders(&body.cstr()?);
+        let count = body.i16()?;
+        let param_types = (0..count).map(|_| body.i32().map(|oid| oid as u32)).collect::<Result<Vec<_>>>()?;
+        // syntax errors are reported at Parse, like Postgres does
+        if Special::parse(&sql).is_none() && !sql.trim().is_empty() {
+            self.sq.prepare_stmt(&sql)?;
+        }
+        self.statements.insert(name, Prepared { sql, param_types, described: None });
+        self.send(b'1', Buf::default());
+        Ok(())
+    }
+
+    fn bind(&mut self, body: &[u8]) -> Result<()> {
+        let mut body = Body::new(body);
+        let portal = body.cstr()?;
+        let name = body.cstr()?;
+        let statement = self.statements.get(&name).ok_or(Error::from("pgwire: unknown prepared statement"))?;
+        let count = body.i16()?;
+        let param_formats = (0..count).map(|_| body.i16()).collect::<Result<Vec<_>>>()?;
+        let count = body.i16()? as usize;
+        let mut params = Vec::with_capacity(count);
+        for idx in 0..count {
+            let len = body.i32()?;
+            let data = match len {
+                -1 => None,
+                len if len < -1 => return Err("pgwire: invalid parameter length".into()),
+                len => Some(body.bytes(len as usize)?)
+            };
+            let oid = statement.param_types.get(idx).copied().unwrap_or(0);
+            params.push(decode_param(data, oid, format_of(&param_formats, idx) == 1)?);
+        }
+        let count = body.i16()?;
+        let formats = (0..count).map(|_| body.i16()).collect::<Result<Vec<_>>>()?;
+        let portal_value = Portal {
+            sql: statement.sql.clone(),
+            params,
+            formats,
+            described: statement.described.clone(),
+            outcome: None,
+            sent: 0,
+        };
+        self.portals.insert(portal, portal_value);
+        self.send(b'2', Buf::default());
+        Ok(())
+    }
+
+    fn describe(&mut self, body: &[u8]) -> Result<()> {
+        let mut body = Body::new(body);
+        let kind = body.byte()?;
+        let name = body.cstr()?;
+        match kind {
+            b'S' => {
+                let statement = self.statements.get(&name).ok_or(Error::from("pgwire: unknown prepared statement"))?;
+                let sql = statement.sql.clone();
+                let (count, columns, types) = match Special::parse(&sql) {
+                    Some(special) => {
+                        let columns = special.columns();
+                        (0, columns.clone(), vec![oid::TEXT; columns.len()])
+                    },
+                    None if sql.trim().is_empty() => (0, vec![], vec![]),
+                    None => {
+                        let mut stmt = self.sq.prepare_stmt(&sql)?;
+                        let count = stmt.parameter_count();
+                        let columns = stmt.column_names();
+                        let types = stmt.column_decltypes()
+                            .iter()
+                            .map(|decltype| decltype.as_deref().map(decltype_oid).unwrap_or(oid::TEXT))
+                            .collect::<Vec<_>>();
+                        (count, columns, types)
+                    }
+                };
+                let statement = self.statements.get_mut(&name).unwrap();
+                statement.described = Some(types.clone());
+                let param_types = (0..count)
+                    .map(|idx| statement.param_types.get(idx).copied().filter(|oid| *oid != 0).unwrap_or(oid::TEXT))
+                    .collect::<Vec<_>>();
+                let mut body = Buf::default().i16(param_types.len() as i16);
+                for oid in param_types {
+                    body = body.i32(oid as i32);
+                }
+                self.send(b't', body);
+                match columns.is_empty() {
+                    true => self.send(b'n', Buf::default()),
+                    false => self.row_description(&columns, &types, &[])
+                }
+            },
+            b'P' => {
+                self.run_portal(&name)?;
+                let portal = &self.portals[&name];
+                let outcome = portal.outcome.clone().unwrap_or_default();
+                let formats = portal.formats.clone();
+                match outcome.columns.is_empty() {
+                    true => self.send(b'n', Buf::default()),
+                    false => self.row_description(&outcome.columns, &outcome.types, &formats)
+                }
+            },
+            _ => return Err("pgwire: invalid describe kind".into())
+        }
+        Ok(())
+    }
+
+    /// Execute a portal once, its rows are kept for Execute.
+    fn run_portal(&mut self, name: &str) -> Result<()> {
+        let portal = self.portals.get_mut(name).ok_or(Error::from("pgwire: unknown portal"))?;
+        if portal.outcome.is_some() {
+            return Ok(());
+        }
+        let (sql, params, described) = (portal.sql.clone(), std::mem::take(&mut portal.params), portal.described.clone());
+        let outcome = match sql.trim().is_empty() {
+            true => Outcome::default(),
+            false => self.run_statement(&sql, params, described)?
+        };
+        self.portals.get_mut(name).unwrap().outcome = Some(outcome);
+        Ok(())
+    }
+
+    fn execute(&mut self, body: &[u8]) -> Result<()> {
+        let mut body = Body::new(body);
+        let name = body.cstr()?;
+        let max_rows = body.i32()?;
+        self.run_portal(&name)?;
+        let portal = self.portals.get_mut(&name).unwrap();
+        let outcome = portal.outcome.clone().unwrap_or_default();
+        let formats = portal.formats.clone();
+        let start = portal.sent;
+        let end = match max_rows {
+            n if n > 0 => (start + n as usize).min(outcome.rows.len()),
+            _ => outcome.rows.len()
+        };
+        portal.sent = end;
+        if outcome.tag.is_empty() {
+            self.send(b'I', Buf::default());
+            return Ok(());
+        }
+        for row in &outcome.rows[start..end] {
+            self.data_row(row, &outcome.types, &formats)?;
+        }
+        match end < outcome.rows.len() {
+            true => self.send(b's', Buf::default()),
+            false => self.send(b'C', Buf::default().cstr(&outcome.tag))
+        }
+        Ok(())
+    }
+
+    fn close(&mut self, body: &[u8]) -> Result<()> {
+        let mut body = Body::new(body);
+        let kind = body.byte()?;
+        let name = body.cstr()?;
+        match kind {
+            b'S' => self.statements.remove(&name).map(|_| ()),
+            _ => self.portals.remove(&name).map(|_| ())
+        };
+        self.send(b'3', Buf::default());
+        Ok(())
+    }
+}
+
+/// Postgres protocol v3 front end for a database file.
+/// Every client gets its own SQLite connection, so transactions are per client
+/// and an in-memory database is private to one client.
+#[derive(Clone, Debug)]
+pub struct PgServer {
+    path: String,
+    policy: Policy,
+}
+
+impl PgServer {
+    pub fn new(path: &str, policy: Policy) -> Self {
+        PgServer { path: path.to_string(), policy }
+    }
+
+    /// Accept clients on a loopback `host:port`, a failed accept is logged and skipped.
+    pub fn serve(&self, address: &str) -> Result<()> {
+        let addresses = address.to_socket_addrs()?.collect::<Vec<_>>();
+        let Some(address) = addresses.iter().find(|a| a.ip().is_loopback()) else {
+            return Err("pgwire: only loopback addresses are allowed".into());
+        };
+        let listener = TcpListener::bind(address)?;
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::warn!("pgwire: accept failed: {e}");
+                    continue;
+                }
+            };
+            let server = self.clone();
+            thread::spawn(move || {
+                if let Err(e) = server.handle(stream) {
+                    log::error!("pgwire: {e}");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Serve one client connection until it terminates.
+    pub fn handle<S: Read + Write>(&self, stream: S) -> Result<()> {
+        let mut sq = SQLite::new().dbf(&self.path);
+        sq.open(self.policy.read_only)?;
+        let mut session = Session {
+            stream,
+            out: Vec::new(),
+            sq,
+            policy: self.policy.clone(),
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+            failed: false,
+        };
+        session.run()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpStream;
+
+    fn message(tag: u8, body: Buf) -> Vec<u8> {
+        let mut data = vec![tag];
+        data.extend_from_slice(&(body.0.len() as i32 + 4).to_be_bytes());
+        data.extend_from_slice(&body.0);
+        data
+    }
+
+    /// Read messages up to and including ReadyForQuery.
+    fn until_ready(stream: &mut TcpStream) -> Vec<(u8, Vec<u8>)> {
+        let mut messages = Vec::new();
+        loop {
+            let mut header = [0u8; 5];
+            stream.read_exact(&mut header).unwrap();
+            let len = i32::from_be_bytes(header[1..].try_into().unwrap()) as usize;
+            let mut body = vec![0u8; len - 4];
+            stream.read_exact(&mut body).unwrap();
+            messages.push((header[0], body));
+            if header[0] == b'Z' {
+                return messages;
+            }
+        }
+    }
+
+    fn tags(messages: &[(u8, Vec<u8>)]) -> String {
+        messages.iter().map(|(tag, _)| *tag as char).collect()
+    }
+
+    fn text(body: &[u8]) -> String {
+        String::from_utf8_lossy(body).into_owned()
+    }
+
+    fn connect(name: &str, policy: Policy) -> TcpStream {
+        let path = std::env::temp_dir().join(format!("sql3x-pg-{}-{name}.sqlite", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let init = "CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT, score REAL); CREATE VIEW names AS SELECT name FROM person";
+        SQLite::new().dbf(&path).create(true, |sq| sq.exec_command(init)).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            PgServer::new(&path, policy).handle(stream).unwrap();
+            let _ = std::fs::remove_file(path);
+        });
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(&[0, 0, 0, 8, 4, 210, 22, 47]).unwrap();
+        let mut answer = [0u8; 1];
+        stream.read_exact(&mut answer).unwrap();
+        assert_eq!(&answer, b"N");
+        let body = Buf::default().i32(PROTOCOL_V3).cstr("user").cstr("test").cstr("").0;
+        let mut startup = (body.len() as i32 + 4).to_be_bytes().to_vec();
+        startup.extend_from_slice(&body);
+        stream.write_all(&startup).unwrap();
+        let messages = until_ready(&mut stream);
+        assert!(tags(&messages).starts_with('R'));
+        assert!(messages.iter().any(|(tag, body)| *tag == b'S' && text(body).contains("server_version\u{0}14.0")));
+        stream
+    }
+
+    fn query(stream: &mut TcpStream, sql: &str) -> Vec<(u8, Vec<u8>)> {
+        stream.write_all(&message(b'Q', Buf::default().cstr(sql))).unwrap();
+        until_ready(stream)
+    }
+
+    #[test]
+    fn simple_query_test() {
+        let mut stream = connect("simple", Policy::default());
+        let messages = query(&mut stream, "INSERT INTO person (name, score) VALUES ('Ann', 1.5), ('Bob', NULL); SELECT id, name, score, 'x;y' AS t FROM person ORDER BY id");
+        assert_eq!(tags(&messages), "CTDDCZ");
+        assert_eq!(text(&messages[0].1), "INSERT 0 2\u{0}");
+        let description = &messages[1].1;
+        // id is INTEGER: int8 at the type OID offset of the first field
+        assert_eq!(&description[2 + 3 + 6..2 + 3 + 10], &(oid::INT8 as i32).to_be_bytes());
+        assert!(text(&messages[2].1).contains("Ann"));
+        assert!(text(&messages[2].1).contains("1.5"));
+        assert!(messages[3].1.windows(4).any(|w| w == (-1i32).to_be_bytes()));
+        assert_eq!(text(&messages[4].1), "SELECT 2\u{0}");
+
+        let messages = query(&mut stream, "SELEC 1");
+        assert_eq!(tags(&messages), "EZ");
+        assert!(text(&messages[0].1).contains("C42601"));
+
+        let messages = query(&mut stream, "BEGIN");
+        assert_eq!(messages.last().unwrap().1, b"T");
+        let messages = query(&mut stream, "COMMIT;");
+        assert_eq!(messages.last().unwrap().1, b"I");
+        assert_eq!(tags(&query(&mut stream, "")), "IZ");
+        stream.write_all(&message(b'X', Buf::default())).unwrap();
+    }
+
+    #[test]
+    fn extended_query_test() {
+        let mut stream = connect("extended", Policy::default());
+        query(&mut stream, "INSERT INTO person (name) VALUES ('Ann'), ('Bob'), ('Cid')");
+        let sql = "SELECT id, name FROM person WHERE id >= $1 AND name <> '$2' ORDER BY id";
+        let mut data = message(b'P', Buf::default().cstr("s1").cstr(sql).i16(1).i32(oid::INT4 as i32));
+        data.extend(message(b'B', Buf::default().cstr("").cstr("s1").i16(1).i16(1).i16(1).i32(4).i32(2).i16(1).i16(1)));
+        data.extend(message(b'D', Buf::default().bytes(b"P").cstr("")));
+        data.extend(message(b'E', Buf::default().cstr("").i32(1)));
+        data.extend(message(b'E', Buf::default().cstr("").i32(0)));
+        data.extend(message(b'S', Buf::default()));
+        stream.write_all(&data).unwrap();
+        let messages = until_ready(&mut stream);
+        assert_eq!(tags(&messages), "12TDsDCZ");
+        // binary int8 id 2, then text of the name
+        assert_eq!(&messages[3].1[2..14], &[0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 2]);
+        assert!(text(&messages[3].1).ends_with("Bob"));
+        assert_eq!(text(&messages[6].1), "SELECT 2\u{0}");
+
+        let mut data = message(b'D', Buf::default().bytes(b"S").cstr("s1"));
+        data.extend(message(b'P', Buf::default().cstr("").cstr("SELECT * FROM missing").i16(0)));
+        data.extend(message(b'B', Buf::default().cstr("").cstr("").i16(0).i16(0).i16(0)));
+        data.extend(message(b'S', Buf::default()));
+        stream.write_all(&data).unwrap();
+        let messages = until_ready(&mut stream);
+        assert_eq!(tags(&messages), "tTEZ");
+        assert!(text(&messages[2].1).contains("C42P01"));
+
+        // a negative length other than -1 (NULL) is rejected
+        let mut data = message(b'B', Buf::default().cstr("").cstr("s1").i16(0).i16(1).i32(-2).i16(0));
+        data.extend(message(b'S', Buf::default()));
+        stream.write_all(&data).unwrap();
+        let messages = until_ready(&mut stream);
+        assert_eq!(tags(&messages), "EZ");
+        assert!(text(&messages[0].1).contains("invalid parameter length"));
+        assert!(Body::new(&[1, 2]).bytes(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn catalog_and_policy_test() {
+        let mut stream = connect("catalog", Policy { read_only: true, ..Default::default() });
+        // what psql sends for \dt
+        let sql = r#"SELECT n.nspname as "Schema",
+  c.relname as "Name",
+  CASE c.relkind WHEN 'r' THEN 'table' WHEN 'v' THEN 'view' END as "Type",
+  pg_catalog.pg_get_userbyid(c.relowner) as "Owner"
+FROM pg_catalog.pg_class c
+     LEFT JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+WHERE c.relkind IN ('r','p','')
+      AND n.nspname <> 'pg_catalog'
+  AND pg_catalog.pg_table_is_visible(c.oid)
+ORDER BY 1,2;"#;
+        let messages = query(&mut stream, sql);
+        assert_eq!(tags(&messages), "TDCZ");
+        assert!(text(&messages[1].1).contains("person"));
+        let messages = query(&mut stream, &sql.replace("('r','p','')", "('r','p','v','m','S','f','')").replace("ORDER BY", "AND c.relname OPERATOR(pg_catalog.~) '^(na.*)$' COLLATE pg_catalog.default ORDER BY"));
+        assert_eq!(tags(&messages), "TDCZ");
+        assert!(text(&messages[1].1).contains("names"));
+
+        assert_eq!(tags(&query(&mut stream, "SET extra_float_digits = 3")), "CZ");
+        assert!(text(&query(&mut stream, "SHOW server_encoding")[1].1).contains("UTF8"));
+        assert!(text(&query(&mut stream, "select version();")[1].1).contains("sql3x"));
+        let messages = query(&mut stream, "DELETE FROM person");
+        assert!(text(&messages[0].1).contains("C42501"));
+    }
+
+    #[test]
+    fn conversion_test() {
+        assert_eq!(placeholders("SELECT $1, '$2', \"$3\" -- $4\n, $10"), "SELECT ?1, '$2', \"$3\" -- $4\n, ?10");
+        assert_eq!(decltype_oid("VARCHAR(10)"), oid::TEXT);
+        assert_eq!(decltype_oid("BIGINT"), oid::INT8);
+        assert_eq!(decltype_oid("DOUBLE PRECISION"), oid::FLOAT8);
+        assert_eq!(decltype_oid("BLOB"), oid::BYTEA);
+        assert_eq!(decltype_oid(""), oid::TEXT);
+        assert_eq!(float_text(1e300), "1e+300");
+        assert_eq!(float_text(1.0), "1");
+        assert_eq!(decode_param(Some(b"\\x00ff"), oid::BYTEA, false).unwrap(), Value::Blob(vec![0, 255]));
+        assert_eq!(decode_param(Some(b"t"), oid::BOOL, false).unwrap(), Value::I64(1));
+        assert!(decode_param(Some(b"x"), oid::INT4, false).is_err());
+        assert_eq!(command_tag("create unique index i on t (a)", 0, 0, 0), "CREATE INDEX");
+        assert!(regex_match("^(pe.son)$", "Person"));
+        assert!(!regex_match("^(pe)$", "person"));
+    }
+}
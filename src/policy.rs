@@ -0,0 +1,49 @@
+use crate::db::SQLite;
+use crate::error::Result;
+use crate::query::Query;
+
+/// What a server lets clients do.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Policy {
+    /// Reject statements that change the database.
+    pub read_only: bool,
+    /// Only these statements are accepted, compared with whitespace collapsed.
+    /// All statements are accepted when None.
+    pub allow: Option<Vec<String>>,
+}
+
+/// Statement text with whitespace collapsed and the final ';' removed.
+fn normalize(sql: &str) -> String {
+    sql.split_whitespace().collect::<Vec<_>>().join(" ").trim_end_matches(';').trim_end().to_string()
+}
+
+impl Policy {
+    pub(crate) fn check(&self, sq: &mut SQLite, query: &Query) -> Result<()> {
+        if let Some(allow) = &self.allow {
+            let sql = normalize(&query.cmd);
+            if !allow.iter().any(|a| normalize(a) == sql) {
+                return Err("policy: statement not allowed".into());
+            }
+        }
+        if self.read_only && !sq.is_read_only(&query.cmd)? {
+            return Err("policy: server is read-only".into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+
+    #[test]
+    fn check_test() {
+        let mut sq = test_database("CREATE TABLE item (name TEXT)");
+        let policy = Policy { read_only: true, allow: Some(vec!["SELECT  name FROM item;".into(), "DELETE FROM item".into()]) };
+        assert!(policy.check(&mut sq, &Query::new("SELECT name\n FROM item")).is_ok());
+        assert_eq!(policy.check(&mut sq, &Query::new("SELECT * FROM item")).unwrap_err().to_string(), "policy: statement not allowed");
+        assert_eq!(policy.check(&mut sq, &Query::new("DELETE FROM item;")).unwrap_err().to_string(), "policy: server is read-only");
+        assert!(Policy::default().check(&mut sq, &Query::new("DELETE FROM item")).is_ok());
+    }
+}
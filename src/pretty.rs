@@ -0,0 +1,428 @@
+use std::cell::RefCell;
+use std::io::Write;
+use crate::db::SQLite;
+use crate::error::Result;
+use crate::query::Query;
+use crate::value::Value;
+use crate::QueryResult;
+
+/// Output format of rendered tables.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TableStyle {
+    /// Column-aligned text with ASCII borders.
+    #[default]
+    Text,
+    /// GitHub flavoured Markdown table.
+    Markdown,
+    /// HTML `<table>` element.
+    Html,
+}
+
+/// Options of table rendering.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableOptions {
+    pub style: TableStyle,
+    /// Cell text of NULL values.
+    pub null: String,
+    /// Longer cells are cut to this display width and end with '…'.
+    pub max_width: Option<usize>,
+    /// Text style only: rows buffered to measure column widths before output starts.
+    /// None buffers every row. With a limit output starts earlier, but cells of later rows
+    /// wider than the measured columns are cut, also without `max_width`.
+    pub sample_rows: Option<usize>,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        TableOptions {
+            style: TableStyle::Text,
+            null: "NULL".into(),
+            max_width: None,
+            sample_rows: None,
+        }
+    }
+}
+
+/// Terminal columns taken by a character: 0 for combining and control characters,
+/// 2 for East Asian wide characters and emoji.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0..=0x1f | 0x7f..=0x9f => 0,
+        0x300..=0x36f | 0x1ab0..=0x1aff | 0x1dc0..=0x1dff | 0x200b..=0x200f | 0x20d0..=0x20ff | 0xfe00..=0xfe0f => 0,
+        0x1100..=0x115f | 0x2e80..=0x303e | 0x3041..=0x33ff | 0x3400..=0x4dbf | 0x4e00..=0x9fff | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3 | 0xf900..=0xfaff | 0xfe30..=0xfe4f | 0xff00..=0xff60 | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f | 0x1f900..=0x1f9ff | 0x20000..=0x2fffd | 0x30000..=0x3fffd => 2,
+        _ => 1
+    }
+}
+
+/// Terminal columns taken by text.
+pub fn display_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+/// Cut text to at most `width` columns, marking the cut with '…'.
+fn truncate(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+    let mut result = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = char_width(c);
+        if used + w + 1 > width {
+            break;
+        }
+        result.push(c);
+        used += w;
+    }
+    if width > 0 {
+        result.push('…');
+    }
+    result
+}
+
+/// Text of a value, blobs are summarized by their size.
+fn cell_text(value: &Value, options: &TableOptions) -> String {
+    let text = match value {
+        Value::Null => options.null.clone(),
+        Value::I64(v) => v.to_string(),
+        Value::F64(v) => format!("{v:?}"),
+        Value::Text(v) => v.clone(),
+        Value::Blob(v) => format!("<blob {} bytes>", v.len())
+    };
+    match options.max_width {
+        Some(width) => truncate(&text, width),
+        None => text
+    }
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r").replace('\t', "\\t")
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('|', "\\|").replace("\r\n", "<br>").replace('\n', "<br>")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Cell ready for output with its display width.
+struct Cell {
+    text: String,
+    width: usize,
+    numeric: bool,
+}
+
+/// Writes a table row by row, for results too big to collect first.
+pub struct TableWriter<W: Write> {
+    writer: W,
+    options: TableOptions,
+    columns: Vec<String>,
+    widths: Vec<usize>,
+    pending: Vec<Vec<Cell>>,
+    started: bool,
+    rows: usize,
+}
+
+impl<W: Write> TableWriter<W> {
+    pub fn new(writer: W, columns: &[String], options: &TableOptions) -> Self {
+        TableWriter {
+            writer,
+            options: options.clone(),
+            columns: columns.to_vec(),
+            widths: Vec::new(),
+            pending: Vec::new(),
+            started: false,
+            rows: 0,
+        }
+    }
+
+    /// Add a row with values in column order.
+    pub fn row(&mut self, values: &[Value]) -> Result<()> {
+        if values.len() != self.columns.len() {
+            let message = format!("table: row has {} values, expected {}", values.len(), self.columns.len());
+            return Err(message.as_str().into());
+        }
+        self.rows += 1;
+        match self.options.style {
+            TableStyle::Text => {
+                let row = values.iter().map(|v| self.cell(v)).collect::<Vec<_>>();
+                match self.started {
+                    true => self.write_text_row(&row)?,
+                    false => {
+                        self.pending.push(row);
+                        if self.options.sample_rows.is_some_and(|n| self.pending.len() >= n) {
+                            self.start_text()?;
+                        }
+                    }
+                }
+            },
+            TableStyle::Markdown => {
+                self.start()?;
+                let cells = values.iter().map(|v| escape_markdown(&cell_text(v, &self.options))).collect::<Vec<_>>();
+                writeln!(self.writer, "| {} |", cells.join(" | "))?;
+            },
+            TableStyle::Html => {
+                self.start()?;
+                write!(self.writer, "<tr>")?;
+                for value in values {
+                    let text = escape_html(&cell_text(value, &self.options)).replace('\n', "<br>");
+                    match value {
+                        Value::Null => write!(self.writer, "<td class=\"null\">{text}</td>")?,
+                        _ => write!(self.writer, "<td>{text}</td>")?
+                    }
+                }
+                writeln!(self.writer, "</tr>")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write what is still buffered and the table end.
+    /// Returns the number of rows.
+    pub fn finish(mut self) -> Result<usize> {
+        match self.options.style {
+            TableStyle::Text => {
+                self.start_text()?;
+                self.write_border()?;
+            },
+            TableStyle::Markdown => self.start()?,
+            TableStyle::Html => {
+                self.start()?;
+                writeln!(self.writer, "</tbody>\n</table>")?;
+            }
+        }
+        self.writer.flush()?;
+        Ok(self.rows)
+    }
+
+    fn cell(&self, value: &Value) -> Cell {
+        let text = escape_text(&cell_text(value, &self.options));
+        Cell {
+            width: display_width(&text),
+            text,
+            numeric: matches!(value, Value::I64(_) | Value::F64(_)),
+        }
+    }
+
+    /// Header of the Markdown and HTML styles.
+    fn start(&mut self) -> Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        match self.options.style {
+            TableStyle::Markdown => {
+                let names = self.columns.iter().map(|c| escape_markdown(c)).collect::<Vec<_>>();
+                writeln!(self.writer, "| {} |", names.join(" | "))?;
+                writeln!(self.writer, "|{}", " --- |".repeat(names.len()))?;
+            },
+            TableStyle::Html => {
+                let names = self.columns.iter().map(|c| format!("<th>{}</th>", escape_html(c))).collect::<String>();
+                writeln!(self.writer, "<table>\n<thead>\n<tr>{names}</tr>\n</thead>\n<tbody>")?;
+            },
+            TableStyle::Text => ()
+        }
+        Ok(())
+    }
+
+    /// Fix column widths from the header and buffered rows, then write them.
+    fn start_text(&mut self) -> Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        self.widths = self.columns.iter().map(|c| display_width(&escape_text(c))).collect();
+        for row in &self.pending {
+            for (width, cell) in self.widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.width);
+            }
+        }
+        self.write_border()?;
+        let header = self.columns
+            .iter()
+            .map(|c| {
+                let text = escape_text(c);
+                Cell { width: display_width(&text), text, numeric: false }
+            })
+            .collect::<Vec<_>>();
+        self.write_text_row(&header)?;
+        self.write_border()?;
+        for row in std::mem::take(&mut self.pending) {
+            self.write_text_row(&row)?;
+        }
+        Ok(())
+    }
+
+    fn write_border(&mut self) -> Result<()> {
+        let line = self.widths.iter().map(|w| "-".repeat(w + 2)).collect::<Vec<_>>().join("+");
+        writeln!(self.writer, "+{line}+")?;
+        Ok(())
+    }
+
+    fn write_text_row(&mut self, row: &[Cell]) -> Result<()> {
+        let mut line = String::from("|");
+        for (cell, &width) in row.iter().zip(&self.widths) {
+            let (text, used) = match cell.width > width {
+                true => {
+                    let text = truncate(&cell.text, width);
+                    let used = display_width(&text);
+                    (text, used)
+                },
+                false => (cell.text.clone(), cell.width)
+            };
+            let padding = " ".repeat(width - used);
+            match cell.numeric {
+                true => line.push_str(&format!(" {padding}{text} |")),
+                false => line.push_str(&format!(" {text}{padding} |"))
+            }
+        }
+        writeln!(self.writer, "{line}")?;
+        Ok(())
+    }
+}
+
+/// Render rows given in column order, a row of the wrong length is an error.
+pub fn render(columns: &[String], rows: &[Vec<Value>], options: &TableOptions) -> Result<String> {
+    let mut out = Vec::new();
+    let mut table = TableWriter::new(&mut out, columns, options);
+    for row in rows {
+        table.row(row)?;
+    }
+    table.finish()?;
+    Ok(String::from_utf8(out).unwrap_or_default())
+}
+
+/// Render a query result. Rows don't keep the SELECT column order, so columns are sorted by name;
+/// use `SQLite::render` or `render` to keep it.
+pub fn render_result(result: &QueryResult, options: &TableOptions) -> Result<String> {
+    let mut columns = result.first().map(|row| row.keys().cloned().collect::<Vec<_>>()).unwrap_or_default();
+    columns.sort();
+    let rows = result
+        .iter()
+        .map(|row| columns.iter().map(|c| row.get(c).cloned().unwrap_or(Value::Null)).collect())
+        .collect::<Vec<_>>();
+    render(&columns, &rows, options)
+}
+
+impl SQLite {
+    /// Write the result of a query as a table, in SELECT column order, row by row.
+    /// Statements without result columns write nothing.
+    /// Returns the number of rows.
+    pub fn render<W: Write>(&mut self, query: Query, writer: W, options: &TableOptions) -> Result<usize> {
+        // the table is created by the columns callback and filled by the row one
+        let table: RefCell<Option<TableWriter<W>>> = RefCell::new(None);
+        self.select_stream(
+            query,
+            |columns| {
+                if !columns.is_empty() {
+                    *table.borrow_mut() = Some(TableWriter::new(writer, columns, options));
+                }
+                Ok(())
+            },
+            |_, values| match table.borrow_mut().as_mut() {
+                Some(table) => table.row(&values),
+                None => Ok(())
+            })?;
+        match table.into_inner() {
+            Some(table) => table.finish(),
+            None => Ok(0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+
+    fn columns(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn width_test() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("zażółć"), 6);
+        assert_eq!(display_width("日本語"), 6);
+        assert_eq!(display_width("e\u{301}"), 1);
+        assert_eq!(truncate("日本語", 5), "日本…");
+        assert_eq!(truncate("abcdef", 4), "abc…");
+        assert_eq!(truncate("abc", 4), "abc");
+    }
+
+    #[test]
+    fn text_test() {
+        let rows = vec![
+            vec![Value::I64(1), Value::from("日本"), Value::Blob(vec![1, 2, 3])],
+            vec![Value::F64(12.5), Value::Null, Value::from("a\nb")],
+        ];
+        let text = render(&columns(&["id", "name", "data"]), &rows, &TableOptions::default()).unwrap();
+        let expected = "\
++------+------+----------------+
+| id   | name | data           |
++------+------+----------------+
+|    1 | 日本 | <blob 3 bytes> |
+| 12.5 | NULL | a\\nb           |
++------+------+----------------+
+";
+        assert_eq!(text, expected);
+
+        let options = TableOptions { max_width: Some(5), null: "∅".into(), ..Default::default() };
+        let text = render(&columns(&["x"]), &[vec![Value::from("abcdefgh")], vec![Value::Null]], &options).unwrap();
+        assert!(text.contains("| abcd… |"), "{text}");
+        assert!(text.contains("| ∅     |"), "{text}");
+    }
+
+    #[test]
+    fn streaming_test() {
+        let options = TableOptions { sample_rows: Some(1), ..Default::default() };
+        let mut out = Vec::new();
+        let mut table = TableWriter::new(&mut out, &columns(&["name"]), &options);
+        table.row(&[Value::from("abcd")]).unwrap();
+        table.row(&[Value::from("abcdefgh")]).unwrap();
+        assert!(table.row(&[]).is_err());
+        assert_eq!(table.finish().unwrap(), 2);
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("| abcd |\n| abc… |"), "{text}");
+
+        // without a sample limit every row is measured
+        let rows = (0..1500).map(|i| vec![Value::from("x".repeat(i / 1000 + 1))]).collect::<Vec<_>>();
+        let text = render(&columns(&["n"]), &rows, &TableOptions::default()).unwrap();
+        assert!(text.contains("| x  |") && text.contains("| xx |"), "{text}");
+        assert!(render(&columns(&["a", "b"]), &[vec![Value::Null]], &TableOptions::default()).is_err());
+    }
+
+    #[test]
+    fn markdown_and_html_test() {
+        let rows = vec![vec![Value::from("a|b"), Value::Null], vec![Value::from("<x>"), Value::I64(2)]];
+        let options = TableOptions { style: TableStyle::Markdown, ..Default::default() };
+        let text = render(&columns(&["s", "n"]), &rows, &options).unwrap();
+        assert_eq!(text, "| s | n |\n| --- | --- |\n| a\\|b | NULL |\n| <x> | 2 |\n");
+
+        let options = TableOptions { style: TableStyle::Html, null: String::new(), ..Default::default() };
+        let text = render(&columns(&["s", "n"]), &rows, &options).unwrap();
+        assert!(text.starts_with("<table>\n<thead>\n<tr><th>s</th><th>n</th></tr>"), "{text}");
+        assert!(text.contains("<tr><td>a|b</td><td class=\"null\"></td></tr>"), "{text}");
+        assert!(text.contains("<td>&lt;x&gt;</td>"), "{text}");
+        assert!(text.ends_with("</tbody>\n</table>\n"), "{text}");
+    }
+
+    #[test]
+    fn query_test() {
+        let mut sq = test_database("CREATE TABLE t (z TEXT, a INT); INSERT INTO t VALUES ('x', 1)");
+        let mut out = Vec::new();
+        let options = TableOptions { style: TableStyle::Markdown, ..Default::default() };
+        assert_eq!(sq.render(Query::new("SELECT z, a FROM t"), &mut out, &options).unwrap(), 1);
+        assert_eq!(String::from_utf8(out).unwrap(), "| z | a |\n| --- | --- |\n| x | 1 |\n");
+        let mut out = Vec::new();
+        assert_eq!(sq.render(Query::new("UPDATE t SET a=2"), &mut out, &options).unwrap(), 0);
+        assert!(out.is_empty());
+
+        let result = sq.select(Query::new("SELECT z, a FROM t")).unwrap();
+        assert!(render_result(&result, &options).unwrap().starts_with("| a | z |"));
+    }
+}
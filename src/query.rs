@@ -2,7 +2,7 @@
 
 use serde::{Deserialize, Serialize};
 use crate::args::{Args, ValueConvertible};
-use crate::db::SQLite;
+use crate::db::{ExecResult, SQLite};
 use crate::value::Value;
 use crate::error::Result;
 use crate::QueryResult;
@@ -34,13 +34,90 @@ impl Query {
         }
     }
 
+    /// Read a query from JSON, arguments may be tagged (`{"I64":1}`) or plain (`1`).
     pub fn from_json(json: &str) -> Result<Self> {
         match serde_json::from_str(json) {
             Ok(query) => Ok(query),
-            Err(e) => Err(e.into())
+            Err(e) => match serde_json::from_str::<serde_json::Value>(json) {
+                Ok(value) => Self::from_plain_json(&value),
+                Err(_) => Err(e.into())
+            }
         }
     }
 
+    /// JSON with plain arguments: `{"cmd": "...", "args": [1, "text", null]}`.
+    pub fn to_plain_json(&self) -> Result<String> {
+        let json = serde_json::json!({
+            "cmd": self.cmd,
+            "args": self.args.iter().map(Value::to_json_value).collect::<Vec<_>>(),
+        });
+        Ok(serde_json::to_string(&json)?)
+    }
+
+    fn from_plain_json(json: &serde_json::Value) -> Result<Self> {
+        let Some(cmd) = json.get("cmd").and_then(|cmd| cmd.as_str()) else {
+            return Err("query not valid: missing cmd".into());
+        };
+        let args = match json.get("args") {
+            Some(serde_json::Value::Array(args)) => args
+                .iter()
+                .map(Value::from_json_value)
+                .collect::<Result<Vec<_>>>()?,
+            None | Some(serde_json::Value::Null) => Vec::new(),
+            Some(_) => return Err("query not valid: args must be an array".into())
+        };
+        Ok(Self::with_args(cmd, Args::from(args)))
+    }
+
+    /// Build `INSERT ... ON CONFLICT(conflict) DO UPDATE SET ...` for a table.
+    /// Columns outside the conflict target are updated from the inserted row,
+    /// when there are none the statement becomes `DO NOTHING`.
+    pub fn upsert(table: &str, columns: &[&str], conflict: &[&str], args: Args) -> Result<Self> {
+        if columns.is_empty() || conflict.is_empty() {
+            return Err("upsert: columns and conflict target must not be empty".into());
+        }
+        if columns.len() != args.len() {
+            let message = format!("upsert: invalid number of arguments. Expected: {}, got: {}", columns.len(), args.len());
+            return Err(message.as_str().into());
+        }
+        if let Some(name) = conflict.iter().find(|c| !columns.contains(c)) {
+            let message = format!("upsert: conflict column {name} is not inserted");
+            return Err(message.as_str().into());
+        }
+
+        let names = columns.iter().map(|c| quote_identifier(c)).collect::<Vec<_>>();
+        let placeholders = vec!["?"; columns.len()].join(", ");
+        let target = conflict.iter().map(|c| quote_identifier(c)).collect::<Vec<_>>();
+        let updates = columns
+            .iter()
+            .filter(|c| !conflict.contains(c))
+            .map(|c| format!("{0}=excluded.{0}", quote_identifier(c)))
+            .collect::<Vec<_>>();
+        let action = match updates.is_empty() {
+            true => "DO NOTHING".to_string(),
+            false => format!("DO UPDATE SET {}", updates.join(", "))
+        };
+
+        let cmd = format!("INSERT INTO {} ({}) VALUES ({}) ON CONFLICT({}) {}",
+            quote_identifier(table),
+            names.join(", "),
+            placeholders,
+            target.join(", "),
+            action);
+        Ok(Self::with_args(cmd.as_str(), args))
+    }
+
+    /// Append a RETURNING clause with the given columns (`*` when empty).
+    pub fn returning(mut self, columns: &[&str]) -> Self {
+        let columns = match columns.is_empty() {
+            true => "*".to_string(),
+            false => columns.iter().map(|c| quote_identifier(c)).collect::<Vec<_>>().join(", ")
+        };
+        let cmd = self.cmd.trim_end().trim_end_matches(';').to_string();
+        self.cmd = format!("{cmd} RETURNING {columns}");
+        self
+    }
+
     pub fn arg<T:ValueConvertible>(mut self, arg: T) -> Self {
         self.args = self.args.arg(arg);
         self
@@ -54,7 +131,7 @@ impl Query {
         self.validate()?;
         sq.insert_for_query(self)
     }
-    pub fn update(&self, sq: &mut SQLite) -> Result<()> {
+    pub fn update(&self, sq: &mut SQLite) -> Result<ExecResult> {
         self.validate()?;       
         sq.update_for_query(self)
     }
@@ -62,10 +139,15 @@ impl Query {
         self.validate()?;
         sq.select_for_query(self)
     }
-    pub fn delete(&self, sq: &mut SQLite) -> Result<()> {
+    pub fn delete(&self, sq: &mut SQLite) -> Result<ExecResult> {
         self.validate()?;
         sq.delete_for_query(self)
     }
+    /// Execute INSERT, UPDATE or DELETE with a RETURNING clause.
+    pub fn returning_rows(&self, sq: &mut SQLite) -> Result<QueryResult> {
+        self.validate()?;
+        sq.returning_for_query(self)
+    }
     
     fn validate(&self) -> Result<()> {
         let placeholder_number = self.cmd
@@ -81,9 +163,15 @@ impl Query {
     }
 }
 
+/// Quote an SQL identifier (table or column name), doubling embedded quotes.
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::test_database;
 
     #[test]
     fn to_from_json_test() {
@@ -92,5 +180,75 @@ mod tests {
             .arg("Piotr")
             .arg(3.54);
         println!("{query:?}");
+
+        let tagged = query.to_json().unwrap();
+        let plain = query.to_plain_json().unwrap();
+        assert_eq!(plain, r#"{"args":[1,"Piotr",3.54],"cmd":"SELECT * FROM users WHERE id=? and name=? and pi=?"}"#);
+        assert_eq!(Query::from_json(&tagged).unwrap().args, query.args);
+        assert_eq!(Query::from_json(&plain).unwrap().args, query.args);
+
+        let mixed = Query::from_json(r#"{"cmd":"SELECT ?, ?, ?","args":[{"Text":"a"},{"$blob":"AQ=="},null]}"#).unwrap();
+        assert_eq!(mixed.args, Args::new().arg("a").arg(vec![1u8]).arg(()));
+        assert!(Query::from_json(r#"{"args":[]}"#).is_err());
+    }
+
+    static CREATE_SCHEMA: &str = "CREATE TABLE kv (key TEXT PRIMARY KEY, value INT, created INT DEFAULT 7) WITHOUT ROWID;";
+
+    #[test]
+    fn upsert_test() {
+        let query = Query::upsert("kv", &["key", "value"], &["key"], Args::new().arg("a").arg(1)).unwrap();
+        assert_eq!(query.cmd, r#"INSERT INTO "kv" ("key", "value") VALUES (?, ?) ON CONFLICT("key") DO UPDATE SET "value"=excluded."value""#);
+
+        let mut sq = test_database(CREATE_SCHEMA);
+        query.insert(&mut sq).unwrap();
+        Query::upsert("kv", &["key", "value"], &["key"], Args::new().arg("a").arg(2)).unwrap()
+            .insert(&mut sq).unwrap();
+        let result = Query::new("SELECT value FROM kv WHERE key='a'").select(&mut sq).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0]["value"], Value::I64(2));
+
+        let query = Query::upsert("kv", &["key"], &["key"], Args::new().arg("a")).unwrap();
+        assert!(query.cmd.ends_with("DO NOTHING"));
+        assert!(Query::upsert("kv", &["key", "value"], &["key"], Args::new().arg("a")).is_err());
+        assert!(Query::upsert("kv", &["value"], &["key"], Args::new().arg(1)).is_err());
+    }
+
+    #[test]
+    fn returning_test() {
+        let mut sq = test_database(CREATE_SCHEMA);
+        let result = sq.insert_returning(
+            Query::new("INSERT INTO kv (key, value) VALUES (?, ?), (?, ?)")
+                .arg("a").arg(1)
+                .arg("b").arg(2)
+                .returning(&["key", "created"]))
+            .unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[1]["key"], Value::from("b"));
+        assert_eq!(result[1]["created"], Value::I64(7));
+
+        let result = sq.update_returning(Query::new("UPDATE kv SET value=value+10 RETURNING value")).unwrap();
+        assert_eq!(result.len(), 2);
+
+        let result = Query::new("DELETE FROM kv WHERE key=? RETURNING *").arg("a").returning_rows(&mut sq).unwrap();
+        assert_eq!(result[0]["value"], Value::I64(11));
+        assert!(sq.delete_returning(Query::new("DELETE FROM kv")).is_err());
+
+        // a column or a literal named like the clause is not a RETURNING clause
+        sq.exec_command("CREATE TABLE log (returning_date TEXT)").unwrap();
+        assert!(sq.insert_returning(Query::new("INSERT INTO log VALUES ('RETURNING')")).is_err());
+        assert!(sq.update_returning(Query::new("UPDATE log SET returning_date = 1")).is_err());
+        // the statement kind is checked
+        assert!(sq.insert_returning(Query::new("DELETE FROM kv RETURNING key")).is_err());
+        assert!(sq.delete_returning(Query::new("SELECT * FROM kv")).is_err());
+        let result = sq.update_returning(Query::new("WITH k(key) AS (SELECT 'b') UPDATE kv SET value=0 WHERE key IN k RETURNING key")).unwrap();
+        assert_eq!(result.len(), 1);
+
+        let ids: Vec<i64> = sq.insert_returning_as(Query::new("INSERT INTO kv (key, value) VALUES ('c', 3), ('d', 4) RETURNING value")).unwrap();
+        assert_eq!(ids, vec![3, 4]);
+        let keys: Vec<String> = sq.delete_returning_as(Query::new("DELETE FROM kv WHERE value > 2 RETURNING key")).unwrap();
+        assert_eq!(keys.len(), 2);
+        let e = sq.update_returning_as::<u8>(Query::new("UPDATE kv SET value = 300 RETURNING value")).unwrap_err();
+        assert_eq!(e.conversion().unwrap().column.as_deref(), Some("value"));
+        assert!(sq.update_returning_as::<i64>(Query::new("UPDATE kv SET value = 1 RETURNING key, value")).is_err());
     }
 }
\ No newline at end of file
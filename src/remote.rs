@@ -0,0 +1,454 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::mpsc::{channel, Sender};
+use std::{fs, thread};
+use serde::{Deserialize, Serialize};
+use crate::db::{statement_keyword, ExecResult, SQLite};
+use crate::error::{Error, Result, SqliteError};
+pub use crate::policy::Policy;
+use crate::query::Query;
+use crate::QueryResult;
+
+/// Longest request line accepted from a client, newline included.
+const MAX_REQUEST_LEN: u64 = 16 << 20;
+
+/// One request line sent to the server.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    Exec { query: Query },
+    Insert { query: Query },
+    Select { query: Query },
+    /// All queries or none, executed in order inside a savepoint.
+    Transaction { queries: Vec<Query> },
+}
+
+/// One response line sent back for a request.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    Exec { result: ExecResult },
+    Insert { id: i64 },
+    Rows { rows: QueryResult },
+    Transaction { results: Vec<ExecResult> },
+    /// Codes are SQLite result codes, -1 for errors not reported by SQLite.
+    Error {
+        code: i32,
+        #[serde(default = "no_code")]
+        extended_code: i32,
+        message: String,
+    },
+}
+
+fn no_code() -> i32 {
+    -1
+}
+
+impl From<Error> for Response {
+    fn from(e: Error) -> Self {
+        Response::Error { code: e.code(), extended_code: e.extended_code().unwrap_or(-1), message: e.message() }
+    }
+}
+
+/// Serves one database to clients, requests are executed one at a time.
+/// Clients share the connection, so they can't open transactions of their own,
+/// a `Request::Transaction` runs its queries together instead.
+pub struct Server {
+    sq: SQLite,
+    policy: Policy,
+}
+
+impl Server {
+    pub fn new(sq: SQLite, policy: Policy) -> Self {
+        Server { sq, policy }
+    }
+
+    /// Execute a request under the server policy.
+    pub fn handle(&mut self, request: Request) -> Response {
+        let result = match request {
+            Request::Exec { query } => self.checked(&query).and_then(|_| self.sq.exec(query)).map(|result| Response::Exec { result }),
+            Request::Insert { query } => self.checked(&query).and_then(|_| self.sq.insert(query)).map(|id| Response::Insert { id }),
+            Request::Select { query } => self.checked(&query).and_then(|_| self.sq.select(query)).map(|rows| Response::Rows { rows }),
+            Request::Transaction { queries } => self.transaction(queries).map(|results| Response::Transaction { results })
+        };
+        result.unwrap_or_else(Response::from)
+    }
+
+    /// Execute one JSON request line, the response is one JSON line without the newline.
+    pub fn handle_line(&mut self, line: &str) -> String {
+        let response = match serde_json::from_str::<Request>(line) {
+            Ok(request) => self.handle(request),
+            Err(e) => Response::from(Error::from(e))
+        };
+        serde_json::to_string(&response).unwrap_or_default()
+    }
+
+    fn checked(&mut self, query: &Query) -> Result<()> {
+        let keyword = statement_keyword(&query.cmd).unwrap_or_default();
+        if matches!(keyword.as_str(), "BEGIN" | "COMMIT" | "END" | "ROLLBACK" | "SAVEPOINT" | "RELEASE") {
+            return Err("remote: transaction statements are not allowed, send a transaction request".into());
+        }
+        self.policy.check(&mut self.sq, query)
+    }
+
+    fn transaction(&mut self, queries: Vec<Query>) -> Result<Vec<ExecResult>> {
+        for query in &queries {
+            self.checked(query)?;
+        }
+        // a savepoint also works inside a transaction the client opened itself
+        self.sq.exec_command("SAVEPOINT sql3x_remote")?;
+        let mut results = Vec::with_capacity(queries.len());
+        for (index, query) in queries.into_iter().enumerate() {
+            match self.sq.exec(query) {
+                Ok(result) => results.push(result),
+                Err(e) => {
+                    let _ = self.sq.exec_command("ROLLBACK TO sql3x_remote; RELEASE sql3x_remote");
+                    return Err(e.context(&format!("transaction: query {index}")));
+                }
+            }
+        }
+        self.sq.exec_command("RELEASE sql3x_remote")?;
+        Ok(results)
+    }
+
+    /// Accept clients on `address` until the listener fails.
+    /// The address is a Unix socket path (`unix:/path` or any path with '/', Unix only),
+    /// or `host:port` resolving to a loopback address.
+    pub fn serve(mut self, address: &str) -> Result<()> {
+        let (sender, receiver) = channel::<(String, Sender<String>)>();
+        match Address::parse(address)? {
+            #[cfg(unix)]
+            Address::Unix(path) => {
+                remove_stale_socket(&path);
+                let listener = UnixListener::bind(&path)?;
+                thread::spawn(move || {
+                    for stream in listener.incoming().flatten() {
+                        spawn_connection(stream, sender.clone());
+                    }
+                });
+            },
+            Address::Tcp(address) => {
+                let listener = TcpListener::bind(address)?;
+                thread::spawn(move || {
+                    for stream in listener.incoming().flatten() {
+                        spawn_connection(stream, sender.clone());
+                    }
+                });
+            }
+        }
+        // connections read and write on their own threads, the database stays on this one
+        for (line, reply) in receiver {
+            let _ = reply.send(self.handle_line(&line));
+        }
+        Err("remote: listener stopped".into())
+    }
+}
+
+/// Remove a Unix socket file left by a server that is gone.
+#[cfg(unix)]
+fn remove_stale_socket(path: &str) {
+    let is_socket = fs::metadata(path).map(|m| m.file_type().is_socket()).unwrap_or(false);
+    if is_socket && UnixStream::connect(path).is_err() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn spawn_connection<S: Read + Write + Send + 'static>(stream: S, requests: Sender<(String, Sender<String>)>) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while (&mut reader).take(MAX_REQUEST_LEN).read_line(&mut line).unwrap_or(0) > 0 {
+            if line.len() as u64 == MAX_REQUEST_LEN && !line.ends_with('\n') {
+                // the rest of the line isn't read, the connection can't continue
+                let response = Response::from(Error::from("remote: request too long"));
+                let _ = writeln!(reader.get_mut(), "{}", serde_json::to_string(&response).unwrap_or_default());
+                break;
+            }
+            if !line.trim().is_empty() {
+                let (reply, response) = channel();
+                if requests.send((line.trim().to_string(), reply)).is_err() {
+                    break;
+                }
+                let Ok(response) = response.recv() else { break };
+                let stream = reader.get_mut();
+                if writeln!(stream, "{response}").and_then(|_| stream.flush()).is_err() {
+                    break;
+                }
+            }
+            line.clear();
+        }
+    });
+}
+
+enum Address {
+    #[cfg(unix)]
+    Unix(String),
+    Tcp(std::net::SocketAddr),
+}
+
+impl Address {
+    fn parse(address: &str) -> Result<Address> {
+        if let Some(path) = address.strip_prefix("unix:") {
+            return Address::unix(path);
+        }
+        if address.contains('/') {
+            return Address::unix(address);
+        }
+        let address = address.strip_prefix("tcp:").unwrap_or(address);
+        let addresses = address.to_socket_addrs()?.collect::<Vec<_>>();
+        match addresses.iter().find(|a| a.ip().is_loopback()) {
+            Some(address) => Ok(Address::Tcp(*address)),
+            None => Err("remote: only loopback TCP addresses are allowed".into())
+        }
+    }
+
+    #[cfg(unix)]
+    fn unix(path: &str) -> Result<Address> {
+        Ok(Address::Unix(path.to_string()))
+    }
+
+    #[cfg(not(unix))]
+    fn unix(_path: &str) -> Result<Address> {
+        Err("remote: Unix sockets are not supported on this platform".into())
+    }
+}
+
+enum Connection {
+    #[cfg(unix)]
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Connection {
+    fn try_clone(&self) -> Result<Connection> {
+        Ok(match self {
+            #[cfg(unix)]
+            Connection::Unix(stream) => Connection::Unix(stream.try_clone()?),
+            Connection::Tcp(stream) => Connection::Tcp(stream.try_clone()?)
+        })
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.read(buf),
+            Connection::Tcp(stream) => stream.read(buf)
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.write(buf),
+            Connection::Tcp(stream) => stream.write(buf)
+        }
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.flush(),
+            Connection::Tcp(stream) => stream.flush()
+        }
+    }
+}
+
+/// Client of a `Server`, with the method shape of `SQLite`.
+pub struct RemoteSQLite {
+    reader: BufReader<Connection>,
+    writer: Connection,
+}
+
+impl RemoteSQLite {
+    /// Connect to a server, the address has the form accepted by `Server::serve`.
+    pub fn connect(address: &str) -> Result<RemoteSQLite> {
+        let writer = match Address::parse(address)? {
+            #[cfg(unix)]
+            Address::Unix(path) => Connection::Unix(UnixStream::connect(path)?),
+            Address::Tcp(address) => Connection::Tcp(TcpStream::connect(address)?)
+        };
+        Ok(RemoteSQLite { reader: BufReader::new(writer.try_clone()?), writer })
+    }
+
+    /// Send a request and wait for its response, error responses are returned as errors.
+    /// Arguments that didn't convert fail here, as they do with a local connection.
+    pub fn request(&mut self, request: &Request) -> Result<Response> {
+        let queries = match request {
+            Request::Exec { query } | Request::Insert { query } | Request::Select { query } => std::slice::from_ref(query),
+            Request::Transaction { queries } => queries.as_slice()
+        };
+        if let Some(e) = queries.iter().find_map(|query| query.args.error()) {
+            return Err(e.clone().into());
+        }
+        writeln!(self.writer, "{}", serde_json::to_string(request)?)?;
+        self.writer.flush()?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err("remote: connection closed".into());
+        }
+        match serde_json::from_str(&line)? {
+            Response::Error { code: -1, message, .. } => Err(Error::Validation(message)),
+            Response::Error { code, extended_code, message } => {
+                Err(SqliteError { code, extended_code, message, sql: None, offset: None }.into())
+            },
+            response => Ok(response)
+        }
+    }
+
+    pub fn exec(&mut self, query: Query) -> Result<ExecResult> {
+        match self.request(&Request::Exec { query })? {
+            Response::Exec { result } => Ok(result),
+            _ => Err("remote: unexpected response".into())
+        }
+    }
+
+    pub fn insert(&mut self, query: Query) -> Result<i64> {
+        match self.request(&Request::Insert { query })? {
+            Response::Insert { id } => Ok(id),
+            _ => Err("remote: unexpected response".into())
+        }
+    }
+
+    pub fn update(&mut self, query: Query) -> Result<ExecResult> {
+        self.exec(query)
+    }
+
+    pub fn delete(&mut self, query: Query) -> Result<ExecResult> {
+        self.exec(query)
+    }
+
+    pub fn select(&mut self, query: Query) -> Result<QueryResult> {
+        match self.request(&Request::Select { query })? {
+            Response::Rows { rows } => Ok(rows),
+            _ => Err("remote: unexpected response".into())
+        }
+    }
+
+    /// Execute all queries or none of them.
+    pub fn transaction(&mut self, queries: Vec<Query>) -> Result<Vec<ExecResult>> {
+        match self.request(&Request::Transaction { queries })? {
+            Response::Transaction { results } => Ok(results),
+            _ => Err("remote: unexpected response".into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+    use crate::value::Value;
+
+    fn database_server(policy: Policy) -> Server {
+        let sq = test_database("CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT UNIQUE)");
+        Server::new(sq, policy)
+    }
+
+    #[test]
+    fn handle_test() {
+        let mut server = database_server(Policy::default());
+        let response = server.handle_line(r#"{"op":"insert","query":{"cmd":"INSERT INTO item (name) VALUES (?)","args":[{"Text":"a"}]}}"#);
+        assert_eq!(response, r#"{"status":"insert","id":1}"#);
+
+        let queries = vec![Query::new("INSERT INTO item (name) VALUES ('b')"), Query::new("INSERT INTO item (name) VALUES ('a')")];
+        let Response::Error { message, .. } = server.handle(Request::Transaction { queries }) else { panic!() };
+        assert!(message.starts_with("transaction: query 1:"), "{message}");
+        let Response::Rows { rows } = server.handle(Request::Select { query: Query::new("SELECT name FROM item") }) else { panic!() };
+        assert_eq!(rows.len(), 1);
+
+        let queries = vec![Query::new("INSERT INTO item (name) VALUES ('b')"), Query::new("UPDATE item SET name = 'c' WHERE name = 'a'")];
+        let Response::Transaction { results } = server.handle(Request::Transaction { queries }) else { panic!() };
+        assert_eq!(results.iter().map(|r| r.changes).collect::<Vec<_>>(), vec![1, 1]);
+
+        assert!(server.handle_line("not json").contains(r#""status":"error""#));
+    }
+
+    #[test]
+    fn policy_test() {
+        let mut server = database_server(Policy { read_only: true, ..Default::default() });
+        let response = server.handle(Request::Exec { query: Query::new("DELETE FROM item") });
+        assert_eq!(response, Response::Error { code: -1, extended_code: -1, message: "policy: server is read-only".into() });
+        assert!(matches!(server.handle(Request::Select { query: Query::new("SELECT * FROM item") }), Response::Rows { .. }));
+
+        let allow = vec!["SELECT name FROM item WHERE id = ?;".to_string()];
+        let mut server = database_server(Policy { allow: Some(allow), ..Default::default() });
+        let query = Query::new("SELECT name\n  FROM item WHERE id = ?").arg(1);
+        assert!(matches!(server.handle(Request::Select { query }), Response::Rows { .. }));
+        let response = server.handle(Request::Select { query: Query::new("SELECT * FROM item") });
+        assert!(matches!(response, Response::Error { .. }));
+    }
+
+    fn connect(address: &str) -> RemoteSQLite {
+        (0..100)
+            .find_map(|_| RemoteSQLite::connect(address).ok().or_else(|| { thread::sleep(std::time::Duration::from_millis(10)); None }))
+            .unwrap()
+    }
+
+    #[test]
+    fn transaction_statement_test() {
+        let mut server = database_server(Policy::default());
+        for sql in ["BEGIN", "begin immediate", "COMMIT", "END TRANSACTION", "ROLLBACK", "SAVEPOINT a", "RELEASE a"] {
+            let response = server.handle(Request::Exec { query: Query::new(sql) });
+            assert!(matches!(&response, Response::Error { message, .. } if message.contains("transaction statements")), "{sql}: {response:?}");
+        }
+        let query = Query::new("INSERT INTO item (name) VALUES ('begin')");
+        assert!(matches!(server.handle(Request::Exec { query }), Response::Exec { .. }));
+    }
+
+    #[test]
+    fn tcp_client_test() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let serve_address = address.clone();
+        thread::spawn(move || database_server(Policy::default()).serve(&serve_address));
+        let mut client = connect(&address);
+        assert_eq!(client.insert(Query::new("INSERT INTO item (name) VALUES (?)").arg("t")).unwrap(), 1);
+        assert!(client.exec(Query::new("BEGIN")).is_err());
+
+        // an argument that didn't convert isn't sent as NULL
+        let query = Query::new("INSERT INTO item (name) VALUES (?)").arg(u64::MAX);
+        assert!(serde_json::to_string(&query).is_err());
+        assert!(client.insert(query).unwrap_err().conversion().is_some());
+        assert_eq!(client.select(Query::new("SELECT * FROM item")).unwrap().len(), 1);
+
+        // an endless line is cut off and the connection closed
+        let mut stream = TcpStream::connect(&address).unwrap();
+        stream.write_all(&vec![b' '; MAX_REQUEST_LEN as usize]).unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.contains("request too long"), "{line}");
+        assert_eq!(reader.read_line(&mut line).unwrap(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn client_test() {
+        let path = std::env::temp_dir().join(format!("sql3x-remote-{}.sock", std::process::id()));
+        let address = format!("unix:{}", path.display());
+        let serve_address = address.clone();
+        // the database isn't Send, the server thread creates its own
+        thread::spawn(move || database_server(Policy::default()).serve(&serve_address));
+        let mut client = connect(&address);
+
+        let id = client.insert(Query::new("INSERT INTO item (name) VALUES (?)").arg("x")).unwrap();
+        assert_eq!(id, 1);
+        let rows = client.select(Query::new("SELECT id, name FROM item")).unwrap();
+        assert_eq!(rows[0]["name"], Value::from("x"));
+        assert_eq!(client.update(Query::new("UPDATE item SET name = 'y'")).unwrap().changes, 1);
+        let e = client.exec(Query::new("INSERT INTO item (id) VALUES (1)")).unwrap_err();
+        assert!(e.is_constraint());
+        assert!(e.message().contains("UNIQUE"), "{e}");
+        let _ = fs::remove_file(path);
+
+        assert!(RemoteSQLite::connect("192.0.2.1:80").is_err());
+    }
+}
@@ -0,0 +1,334 @@
+use serde::{Deserialize, Serialize};
+use crate::db::SQLite;
+use crate::error::Result;
+use crate::query::Query;
+use crate::Row;
+
+/// Database schema read from `sqlite_schema` and the table pragmas.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Schema {
+    pub tables: Vec<Table>,
+    pub views: Vec<View>,
+    pub triggers: Vec<Trigger>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Table {
+    pub name: String,
+    /// CREATE statement as stored in `sqlite_schema`.
+    pub sql: Option<String>,
+    pub without_rowid: bool,
+    pub strict: bool,
+    pub columns: Vec<Column>,
+    pub indexes: Vec<Index>,
+    pub foreign_keys: Vec<ForeignKey>,
+}
+
+/// Kind of column reported by the `hidden` field of `PRAGMA table_xinfo`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColumnKind {
+    #[default]
+    Normal,
+    /// Hidden column of a virtual table.
+    Hidden,
+    /// `GENERATED ALWAYS AS (...) VIRTUAL`.
+    GeneratedVirtual,
+    /// `GENERATED ALWAYS AS (...) STORED`.
+    GeneratedStored,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Column {
+    pub cid: i64,
+    pub name: String,
+    /// Declared type, empty when the column has none.
+    pub decl_type: String,
+    pub not_null: bool,
+    /// Default value as SQL text.
+    pub default: Option<String>,
+    /// Position in the primary key (1-based), 0 when not part of it.
+    pub pk: i64,
+    pub kind: ColumnKind,
+}
+
+impl Column {
+    pub fn is_generated(&self) -> bool {
+        matches!(self.kind, ColumnKind::GeneratedVirtual | ColumnKind::GeneratedStored)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Index {
+    pub name: String,
+    pub unique: bool,
+    /// How the index was created: `c` (CREATE INDEX), `u` (UNIQUE constraint) or `pk` (PRIMARY KEY).
+    pub origin: String,
+    pub partial: bool,
+    pub columns: Vec<IndexColumn>,
+    /// CREATE statement, None for indexes created by constraints.
+    pub sql: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct IndexColumn {
+    /// Column name, None for expressions and the rowid.
+    pub name: Option<String>,
+    pub desc: bool,
+    pub collation: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ForeignKey {
+    pub id: i64,
+    /// Referenced (parent) table.
+    pub table: String,
+    pub from: Vec<String>,
+    /// Referenced columns, None when the parent primary key is implied.
+    pub to: Vec<Option<String>>,
+    pub on_update: String,
+    pub on_delete: String,
+    #[serde(rename = "match")]
+    pub match_: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct View {
+    pub name: String,
+    pub sql: Option<String>,
+    pub columns: Vec<Column>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Trigger {
+    pub name: String,
+    pub table: String,
+    pub sql: Option<String>,
+}
+
+impl Schema {
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.iter().find(|t| t.name.eq_ignore_ascii_case(name))
+    }
+    pub fn view(&self, name: &str) -> Option<&View> {
+        self.views.iter().find(|v| v.name.eq_ignore_ascii_case(name))
+    }
+    pub fn trigger(&self, name: &str) -> Option<&Trigger> {
+        self.triggers.iter().find(|t| t.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl Table {
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|c| c.name.eq_ignore_ascii_case(name))
+    }
+    pub fn index(&self, name: &str) -> Option<&Index> {
+        self.indexes.iter().find(|i| i.name.eq_ignore_ascii_case(name))
+    }
+    /// Primary key columns in key order.
+    pub fn primary_key(&self) -> Vec<&Column> {
+        let mut columns = self.columns.iter().filter(|c| c.pk > 0).collect::<Vec<_>>();
+        columns.sort_by_key(|c| c.pk);
+        columns
+    }
+}
+
+fn text(row: &Row, name: &str) -> String {
+    opt_text(row, name).unwrap_or_default()
+}
+
+fn opt_text(row: &Row, name: &str) -> Option<String> {
+    row.get(name).and_then(|v| v.clone().get::<String>())
+}
+
+fn int(row: &Row, name: &str) -> i64 {
+    row.get(name).and_then(|v| v.clone().get::<i64>()).unwrap_or_default()
+}
+
+impl SQLite {
+    /// Read the schema of the main database.
+    /// Internal `sqlite_*` tables and shadow tables of virtual tables (FTS5 `*_data`, ...) are skipped.
+    pub fn schema(&mut self) -> Result<Schema> {
+        let objects = self.select(Query::new(
+            "SELECT type, name, tbl_name, sql FROM sqlite_schema \
+             WHERE name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+             AND name NOT IN (SELECT name FROM pragma_table_list WHERE schema='main' AND type='shadow') ORDER BY rowid"))?;
+
+        let mut schema = Schema::default();
+        for row in &objects {
+            let name = text(row, "name");
+            let sql = opt_text(row, "sql");
+            match text(row, "type").as_str() {
+                "table" => schema.tables.push(self.table_schema(name, sql)?),
+                "view" => {
+                    let columns = self.columns(&name)?;
+                    schema.views.push(View { name, sql, columns })
+                },
+                "trigger" => schema.triggers.push(Trigger { name, table: text(row, "tbl_name"), sql }),
+                _ => ()
+            }
+        }
+        Ok(schema)
+    }
+
+    fn table_schema(&mut self, name: String, sql: Option<String>) -> Result<Table> {
+        let list = self.select(Query::new("SELECT wr, strict FROM pragma_table_list(?) WHERE schema='main'").arg(name.as_str()))?;
+        let (without_rowid, strict) = match list.first() {
+            Some(row) => (int(row, "wr") != 0, int(row, "strict") != 0),
+            None => (false, false)
+        };
+        Ok(Table {
+            columns: self.columns(&name)?,
+            indexes: self.indexes(&name)?,
+            foreign_keys: self.foreign_keys(&name)?,
+            name,
+            sql,
+            without_rowid,
+            strict,
+        })
+    }
+
+    fn columns(&mut self, table: &str) -> Result<Vec<Column>> {
+        let rows = self.select(Query::new("SELECT * FROM pragma_table_xinfo(?) ORDER BY cid").arg(table))?;
+        Ok(rows
+            .iter()
+            .map(|row| Column {
+                cid: int(row, "cid"),
+                name: text(row, "name"),
+                decl_type: text(row, "type"),
+                not_null: int(row, "notnull") != 0,
+                default: opt_text(row, "dflt_value"),
+                pk: int(row, "pk"),
+                kind: match int(row, "hidden") {
+                    1 => ColumnKind::Hidden,
+                    2 => ColumnKind::GeneratedVirtual,
+                    3 => ColumnKind::GeneratedStored,
+                    _ => ColumnKind::Normal
+                },
+            })
+            .collect())
+    }
+
+    fn indexes(&mut self, table: &str) -> Result<Vec<Index>> {
+        let rows = self.select(Query::new(
+            "SELECT l.name, l.\"unique\", l.origin, l.partial, s.sql \
+             FROM pragma_index_list(?) AS l LEFT JOIN sqlite_schema AS s ON s.type='index' AND s.name=l.name \
+             ORDER BY l.seq DESC").arg(table))?;
+
+        let mut indexes = Vec::with_capacity(rows.len());
+        for row in &rows {
+            let name = text(row, "name");
+            let columns = self.select(Query::new("SELECT * FROM pragma_index_xinfo(?) WHERE key=1 ORDER BY seqno").arg(name.as_str()))?
+                .iter()
+                .map(|row| IndexColumn {
+                    name: opt_text(row, "name"),
+                    desc: int(row, "desc") != 0,
+                    collation: opt_text(row, "coll"),
+                })
+                .collect();
+            indexes.push(Index {
+                name,
+                unique: int(row, "unique") != 0,
+                origin: text(row, "origin"),
+                partial: int(row, "partial") != 0,
+                columns,
+                sql: opt_text(row, "sql"),
+            });
+        }
+        Ok(indexes)
+    }
+
+    fn foreign_keys(&mut self, table: &str) -> Result<Vec<ForeignKey>> {
+        let rows = self.select(Query::new("SELECT * FROM pragma_foreign_key_list(?) ORDER BY id, seq").arg(table))?;
+
+        let mut keys: Vec<ForeignKey> = Vec::new();
+        for row in &rows {
+            let id = int(row, "id");
+            if keys.last().is_none_or(|key| key.id != id) {
+                keys.push(ForeignKey {
+                    id,
+                    table: text(row, "table"),
+                    on_update: text(row, "on_update"),
+                    on_delete: text(row, "on_delete"),
+                    match_: text(row, "match"),
+                    ..Default::default()
+                });
+            }
+            if let Some(key) = keys.last_mut() {
+                key.from.push(text(row, "from"));
+                key.to.push(opt_text(row, "to"));
+            }
+        }
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+
+    static CREATE_SCHEMA: &str = r#"
+        CREATE TABLE person (
+            id INTEGER PRIMARY KEY,
+            email TEXT NOT NULL UNIQUE,
+            name TEXT DEFAULT 'anonymous',
+            upper_name TEXT GENERATED ALWAYS AS (upper(name)) VIRTUAL
+        );
+        CREATE TABLE pet (
+            owner INT NOT NULL,
+            name TEXT NOT NULL,
+            kind TEXT,
+            PRIMARY KEY (owner, name),
+            FOREIGN KEY (owner) REFERENCES person(id) ON DELETE CASCADE
+        ) WITHOUT ROWID;
+        CREATE INDEX pet_kind ON pet (kind DESC) WHERE kind IS NOT NULL;
+        CREATE VIEW person_names AS SELECT id, name FROM person;
+        CREATE TRIGGER person_delete AFTER DELETE ON person BEGIN SELECT 1; END;
+    "#;
+
+    #[test]
+    fn schema_test() {
+        let mut sq = test_database(CREATE_SCHEMA);
+        let schema = sq.schema().unwrap();
+
+        assert_eq!(schema.tables.len(), 2);
+        let person = schema.table("person").unwrap();
+        assert!(!person.without_rowid);
+        assert_eq!(person.columns.len(), 4);
+        assert_eq!(person.primary_key()[0].name, "id");
+        let email = person.column("email").unwrap();
+        assert_eq!(email.decl_type, "TEXT");
+        assert!(email.not_null);
+        assert_eq!(person.column("name").unwrap().default.as_deref(), Some("'anonymous'"));
+        assert_eq!(person.column("upper_name").unwrap().kind, ColumnKind::GeneratedVirtual);
+        assert_eq!(person.indexes.len(), 1);
+        assert!(person.indexes[0].unique);
+        assert_eq!(person.indexes[0].origin, "u");
+        assert_eq!(person.indexes[0].sql, None);
+
+        let pet = schema.table("pet").unwrap();
+        assert!(pet.without_rowid);
+        assert_eq!(pet.primary_key().iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["owner", "name"]);
+        let kind = pet.index("pet_kind").unwrap();
+        assert!(kind.partial);
+        assert!(kind.columns[0].desc);
+        assert_eq!(kind.columns[0].name.as_deref(), Some("kind"));
+        assert!(kind.sql.is_some());
+        assert_eq!(pet.foreign_keys.len(), 1);
+        assert_eq!(pet.foreign_keys[0].table, "person");
+        assert_eq!(pet.foreign_keys[0].from, vec!["owner"]);
+        assert_eq!(pet.foreign_keys[0].to, vec![Some("id".to_string())]);
+        assert_eq!(pet.foreign_keys[0].on_delete, "CASCADE");
+
+        assert_eq!(schema.view("person_names").unwrap().columns.len(), 2);
+        assert_eq!(schema.trigger("person_delete").unwrap().table, "person");
+
+        sq.exec_command("CREATE VIRTUAL TABLE doc USING fts5(body)").unwrap();
+        let names = sq.schema().unwrap().tables.into_iter().map(|t| t.name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["person", "pet", "doc"]);
+
+        let json = serde_json::to_string(&schema).unwrap();
+        let back: Schema = serde_json::from_str(&json).unwrap();
+        assert_eq!(schema, back);
+    }
+}
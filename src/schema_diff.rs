@@ -0,0 +1,565 @@
+use serde::{Deserialize, Serialize};
+use crate::db::SQLite;
+use crate::error::{Error, Result};
+use crate::lexer::tokens;
+use crate::query::quote_identifier;
+use crate::schema::{Column, Index, Schema, Table, Trigger, View};
+
+/// Object present in both schemas with a different definition.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Change<T> {
+    pub from: T,
+    pub to: T,
+}
+
+/// Differences of one table present in both schemas.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TableDiff {
+    pub name: String,
+    pub added_columns: Vec<Column>,
+    pub removed_columns: Vec<Column>,
+    pub changed_columns: Vec<Change<Column>>,
+    pub added_indexes: Vec<Index>,
+    pub removed_indexes: Vec<Index>,
+    pub changed_indexes: Vec<Change<Index>>,
+    /// Table can't be migrated with `ALTER TABLE ADD COLUMN` and has to be rebuilt.
+    pub rebuild: bool,
+    pub from: Table,
+    pub to: Table,
+    /// Expected triggers of the table, recreated after a rebuild.
+    pub triggers: Vec<Trigger>,
+}
+
+/// Differences between two schemas, `from` is the current one and `to` the expected one.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SchemaDiff {
+    pub added_tables: Vec<Table>,
+    pub removed_tables: Vec<Table>,
+    pub changed_tables: Vec<TableDiff>,
+    pub added_views: Vec<View>,
+    pub removed_views: Vec<View>,
+    pub changed_views: Vec<Change<View>>,
+    pub added_triggers: Vec<Trigger>,
+    pub removed_triggers: Vec<Trigger>,
+    pub changed_triggers: Vec<Change<Trigger>>,
+}
+
+const FOREIGN_KEY_CHECK: &str = "PRAGMA foreign_key_check;";
+
+/// Compare schemas of two databases.
+/// The migration of the result turns the `from` database into `to`.
+pub fn schema_diff(from: &mut SQLite, to: &mut SQLite) -> Result<SchemaDiff> {
+    Ok(from.schema()?.diff(&to.schema()?))
+}
+
+impl SQLite {
+    /// Run the migration of a diff, see `SchemaDiff::migration`.
+    /// Fails and rolls back when a statement fails or rebuilt tables break foreign keys.
+    pub fn migrate(&mut self, diff: &SchemaDiff) -> Result<()> {
+        let statements = diff.migration();
+        let result = statements.iter().try_for_each(|statement| match statement.as_str() {
+            FOREIGN_KEY_CHECK => match self.foreign_key_check(None)?.as_slice() {
+                [] => Ok(()),
+                rows => Err(Error::Validation(format!("migration breaks foreign keys: {} rows, first in {} referencing {}",
+                    rows.len(), rows[0].table, rows[0].parent)))
+            },
+            statement => self.exec_command(statement)
+        });
+        if result.is_err() {
+            if self.in_transaction() {
+                let _ = self.exec_command("ROLLBACK;");
+            }
+            // restore the pragmas switched for a rebuild
+            for statement in statements.iter().rev().take_while(|s| s.starts_with("PRAGMA") && s.as_str() != FOREIGN_KEY_CHECK) {
+                let _ = self.exec_command(statement);
+            }
+        }
+        result
+    }
+}
+
+/// Split objects into (added, removed, changed) by name.
+fn compare<T, F>(from: &[T], to: &[T], name: F, same: impl Fn(&T, &T) -> bool) -> (Vec<T>, Vec<T>, Vec<Change<T>>)
+    where T: Clone, F: Fn(&T) -> &str
+{
+    let find = |items: &[T], key: &str| items.iter().find(|x| name(x).eq_ignore_ascii_case(key)).cloned();
+    let added = to.iter().filter(|x| find(from, name(x)).is_none()).cloned().collect();
+    let removed = from.iter().filter(|x| find(to, name(x)).is_none()).cloned().collect();
+    let changed = from
+        .iter()
+        .filter_map(|x| find(to, name(x)).map(|y| Change { from: x.clone(), to: y }))
+        .filter(|change| !same(&change.from, &change.to))
+        .collect();
+    (added, removed, changed)
+}
+
+fn same_sql(a: &Option<String>, b: &Option<String>) -> bool {
+    a.as_deref().map(normalize) == b.as_deref().map(normalize)
+}
+
+fn same_index(a: &Index, b: &Index) -> bool {
+    let without_sql = |i: &Index| Index { sql: None, ..i.clone() };
+    without_sql(a) == without_sql(b) && same_sql(&a.sql, &b.sql)
+}
+
+impl Schema {
+    /// Compare with the expected schema `to`.
+    pub fn diff(&self, to: &Schema) -> SchemaDiff {
+        let (added_tables, removed_tables, changed) = compare(&self.tables, &to.tables, |t| t.name.as_str(), |_, _| false);
+        let changed_tables = changed
+            .into_iter()
+            .filter_map(|change| {
+                let triggers = to.triggers.iter().filter(|t| t.table.eq_ignore_ascii_case(&change.to.name)).cloned().collect();
+                TableDiff::new(change.from, change.to, triggers)
+            })
+            .collect();
+        let (added_views, removed_views, changed_views) = compare(&self.views, &to.views, |v| v.name.as_str(), |a, b| same_sql(&a.sql, &b.sql));
+        let (added_triggers, removed_triggers, changed_triggers) = compare(&self.triggers, &to.triggers, |t| t.name.as_str(), |a, b| same_sql(&a.sql, &b.sql));
+        SchemaDiff {
+            added_tables,
+            removed_tables,
+            changed_tables,
+            added_views,
+            removed_views,
+            changed_views,
+            added_triggers,
+            removed_triggers,
+            changed_triggers,
+        }
+    }
+}
+
+impl TableDiff {
+    /// Returns None when both tables are the same.
+    fn new(from: Table, to: Table, triggers: Vec<Trigger>) -> Option<TableDiff> {
+        let (added_columns, removed_columns, changed_columns) = compare(&from.columns, &to.columns, |c| c.name.as_str(), |a, b| a == b);
+        let (added_indexes, removed_indexes, changed_indexes) = compare(&from.indexes, &to.indexes, |i| i.name.as_str(), same_index);
+
+        let old = Definition::parse(from.sql.as_deref().unwrap_or_default());
+        let new = Definition::parse(to.sql.as_deref().unwrap_or_default());
+        let same_definition = old == new;
+        let appended = old.constraints == new.constraints
+            && old.suffix == new.suffix
+            && old.columns.len() < new.columns.len()
+            && new.columns.starts_with(&old.columns);
+
+        let alterable = appended
+            && removed_columns.is_empty()
+            && changed_columns.is_empty()
+            && new.columns[old.columns.len()..].iter().all(|c| addable(c));
+        let rebuild = !(same_definition || alterable)
+            || from.without_rowid != to.without_rowid
+            || from.strict != to.strict
+            || from.foreign_keys != to.foreign_keys;
+
+        let diff = TableDiff {
+            name: to.name.clone(),
+            added_columns,
+            removed_columns,
+            changed_columns,
+            added_indexes,
+            removed_indexes,
+            changed_indexes,
+            rebuild,
+            from,
+            to,
+            triggers,
+        };
+        match same_definition && !diff.rebuild && diff.is_empty() {
+            true => None,
+            false => Some(diff)
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.added_columns.is_empty()
+            && self.removed_columns.is_empty()
+            && self.changed_columns.is_empty()
+            && self.added_indexes.is_empty()
+            && self.removed_indexes.is_empty()
+            && self.changed_indexes.is_empty()
+    }
+}
+
+impl SchemaDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_tables.is_empty()
+            && self.removed_tables.is_empty()
+            && self.changed_tables.is_empty()
+            && self.added_views.is_empty()
+            && self.removed_views.is_empty()
+            && self.changed_views.is_empty()
+            && self.added_triggers.is_empty()
+            && self.removed_triggers.is_empty()
+            && self.changed_triggers.is_empty()
+    }
+
+    /// Statements migrating the `from` schema to `to`.
+    /// Tables that `ALTER TABLE` can't change are rebuilt with SQLite's 12-step procedure,
+    /// in that case foreign keys are switched off for the migration. The script then only
+    /// lists violations with `PRAGMA foreign_key_check` before commit, `SQLite::migrate`
+    /// rolls back when there are any.
+    pub fn migration(&self) -> Vec<String> {
+        let rebuild = self.changed_tables.iter().any(|t| t.rebuild);
+        let rebuilt = |table: &str| self.changed_tables.iter().any(|t| t.rebuild && t.name.eq_ignore_ascii_case(table));
+        let mut sql = Vec::new();
+
+        if rebuild {
+            // views and triggers keep referring to the rebuilt tables by name
+            sql.push("PRAGMA foreign_keys=OFF;".to_string());
+            sql.push("PRAGMA legacy_alter_table=ON;".to_string());
+        }
+        sql.push("BEGIN;".to_string());
+
+        for trigger in self.removed_triggers.iter().chain(self.changed_triggers.iter().map(|c| &c.from)) {
+            sql.push(format!("DROP TRIGGER IF EXISTS {};", quote_identifier(&trigger.name)));
+        }
+        for view in self.removed_views.iter().chain(self.changed_views.iter().map(|c| &c.from)) {
+            sql.push(format!("DROP VIEW IF EXISTS {};", quote_identifier(&view.name)));
+        }
+        for table in self.changed_tables.iter().filter(|t| !t.rebuild) {
+            let indexes = table.removed_indexes.iter().chain(table.changed_indexes.iter().map(|c| &c.from));
+            for index in indexes.filter(|i| i.sql.is_some()) {
+                sql.push(format!("DROP INDEX IF EXISTS {};", quote_identifier(&index.name)));
+            }
+        }
+        for table in &self.removed_tables {
+            sql.push(format!("DROP TABLE IF EXISTS {};", quote_identifier(&table.name)));
+        }
+        for table in &self.added_tables {
+            sql.extend(statement(&table.sql));
+            for index in &table.indexes {
+                sql.extend(statement(&index.sql));
+            }
+        }
+
+        for table in &self.changed_tables {
+            match table.rebuild {
+                true => sql.extend(rebuild_table(table)),
+                false => {
+                    let old = Definition::parse(table.from.sql.as_deref().unwrap_or_default());
+                    let new = Definition::parse(table.to.sql.as_deref().unwrap_or_default());
+                    for column in &new.raw_columns[old.columns.len()..] {
+                        sql.push(format!("ALTER TABLE {} ADD COLUMN {column};", quote_identifier(&table.name)));
+                    }
+                    let indexes = table.added_indexes.iter().chain(table.changed_indexes.iter().map(|c| &c.to));
+                    for index in indexes {
+                        sql.extend(statement(&index.sql));
+                    }
+                }
+            }
+        }
+
+        for view in self.added_views.iter().chain(self.changed_views.iter().map(|c| &c.to)) {
+            sql.extend(statement(&view.sql));
+        }
+        // triggers of rebuilt tables were already recreated
+        let triggers = self.added_triggers.iter().chain(self.changed_triggers.iter().map(|c| &c.to));
+        for trigger in triggers.filter(|t| !rebuilt(&t.table)) {
+            sql.extend(statement(&trigger.sql));
+        }
+
+        if rebuild {
+            sql.push(FOREIGN_KEY_CHECK.to_string());
+        }
+        sql.push("COMMIT;".to_string());
+        if rebuild {
+            sql.push("PRAGMA legacy_alter_table=OFF;".to_string());
+            sql.push("PRAGMA foreign_keys=ON;".to_string());
+        }
+        sql
+    }
+
+    /// Migration as one SQL script.
+    pub fn migration_sql(&self) -> String {
+        self.migration().join("\n")
+    }
+}
+
+fn statement(sql: &Option<String>) -> Option<String> {
+    sql.as_ref().map(|sql| format!("{};", sql.trim_end().trim_end_matches(';')))
+}
+
+/// The 12-step table rebuild: create the new table, copy data, drop the old one, rename
+/// and recreate the table's indexes and triggers.
+fn rebuild_table(table: &TableDiff) -> Vec<String> {
+    let name = quote_identifier(&table.name);
+    let temp = quote_identifier(&format!("sql3x_new_{}", table.name));
+    let create = table.to.sql.as_deref().map(|sql| rename_create(sql, &temp)).unwrap_or_default();
+
+    let common = table.to.columns
+        .iter()
+        .filter(|c| !c.is_generated() && table.from.column(&c.name).is_some_and(|c| !c.is_generated()))
+        .map(|c| quote_identifier(&c.name))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut sql = vec![format!("{create};")];
+    if !common.is_empty() {
+        sql.push(format!("INSERT INTO {temp} ({common}) SELECT {common} FROM {name};"));
+    }
+    sql.push(format!("DROP TABLE {name};"));
+    sql.push(format!("ALTER TABLE {temp} RENAME TO {name};"));
+    for index in &table.to.indexes {
+        sql.extend(statement(&index.sql));
+    }
+    for trigger in &table.triggers {
+        sql.extend(statement(&trigger.sql));
+    }
+    sql
+}
+
+/********************************************************************
+*                                                                   *
+*              C R E A T E   T A B L E   P A R S I N G              *
+*                                                                   *
+********************************************************************/
+
+/// Token text in a comparable form: keywords and identifiers lowercased, string literals kept.
+fn canonical(token: &str) -> String {
+    match token.starts_with('\'') {
+        true => token.to_string(),
+        false => unquote(token).to_lowercase()
+    }
+}
+
+fn unquote(token: &str) -> String {
+    let bytes = token.as_bytes();
+    match bytes.first() {
+        Some(b'"') | Some(b'`') if token.len() >= 2 => {
+            let quote = &token[..1];
+            token[1..token.len() - 1].replace(&quote.repeat(2), quote)
+        },
+        Some(b'[') if token.len() >= 2 => token[1..token.len() - 1].to_string(),
+        _ => token.to_string()
+    }
+}
+
+/// SQL with whitespace, comments, quoting and case differences removed.
+fn normalize(sql: &str) -> String {
+    tokens(sql.trim_end().trim_end_matches(';'))
+        .iter()
+        .map(|&(s, e)| canonical(&sql[s..e]))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// CREATE TABLE split into column definitions, table constraints and table options.
+/// Definitions are compared in normalized form, `raw_columns` keeps the original text.
+#[derive(Debug, Default)]
+struct Definition {
+    columns: Vec<String>,
+    constraints: Vec<String>,
+    suffix: String,
+    raw_columns: Vec<String>,
+}
+
+impl PartialEq for Definition {
+    fn eq(&self, other: &Self) -> bool {
+        self.columns == other.columns && self.constraints == other.constraints && self.suffix == other.suffix
+    }
+}
+
+impl Definition {
+    fn parse(sql: &str) -> Definition {
+        let tokens = tokens(sql);
+        let text = |idx: usize| &sql[tokens[idx].0..tokens[idx].1];
+        let Some(open) = (0..tokens.len()).find(|&i| text(i) == "(") else {
+            return Definition { suffix: normalize(sql), ..Default::default() };
+        };
+
+        let mut definition = Definition::default();
+        let mut depth = 0;
+        let mut item_start = open + 1;
+        let mut close = tokens.len();
+        for i in open..tokens.len() {
+            match text(i) {
+                "(" => depth += 1,
+                ")" | "," if depth == 1 => {
+                    if item_start < i {
+                        let raw = &sql[tokens[item_start].0..tokens[i - 1].1];
+                        let canonical = (item_start..i).map(|j| canonical(text(j))).collect::<Vec<_>>().join(" ");
+                        match ["constraint", "primary", "unique", "check", "foreign"].contains(&canonical.split(' ').next().unwrap_or_default()) && !text(item_start).starts_with(['"', '`', '[']) {
+                            true => definition.constraints.push(canonical),
+                            false => {
+                                definition.columns.push(canonical);
+                                definition.raw_columns.push(raw.to_string());
+                            }
+                        }
+                    }
+                    item_start = i + 1;
+                    if text(i) == ")" {
+                        close = i;
+                        break;
+                    }
+                },
+                ")" => depth -= 1,
+                _ => ()
+            }
+        }
+        definition.suffix = ((close + 1)..tokens.len()).map(|j| canonical(text(j))).collect::<Vec<_>>().join(" ");
+        definition
+    }
+}
+
+/// Whether `ALTER TABLE ADD COLUMN` accepts the column definition.
+fn addable(column: &str) -> bool {
+    let words = column.split(' ').collect::<Vec<_>>();
+    if words.iter().any(|w| ["primary", "unique", "stored"].contains(w)) {
+        return false;
+    }
+    let default = words.iter().position(|w| *w == "default").and_then(|i| words.get(i + 1));
+    if default.is_some_and(|d| *d == "(" || d.starts_with("current_")) {
+        return false;
+    }
+    let not_null = words.windows(2).any(|w| w == ["not", "null"]);
+    !not_null || default.is_some_and(|d| *d != "null")
+}
+
+/// Replace the table name of a CREATE TABLE statement.
+fn rename_create(sql: &str, name: &str) -> String {
+    let tokens = tokens(sql);
+    let mut idx = 0;
+    while idx < tokens.len() {
+        let word = sql[tokens[idx].0..tokens[idx].1].to_ascii_lowercase();
+        idx += 1;
+        if word == "table" {
+            break;
+        }
+    }
+    // IF NOT EXISTS
+    if idx < tokens.len() && sql[tokens[idx].0..tokens[idx].1].eq_ignore_ascii_case("if") {
+        idx += 3;
+    }
+    let Some(&(start, mut end)) = tokens.get(idx) else {
+        return sql.to_string();
+    };
+    // schema.name
+    if tokens.get(idx + 1).is_some_and(|t| &sql[t.0..t.1] == ".") && let Some(t) = tokens.get(idx + 2) {
+        end = t.1;
+    }
+    format!("{}{}{}", &sql[..start], name, &sql[end..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+    use crate::query::Query;
+    use crate::value::Value;
+
+    static CURRENT: &str = r#"
+        CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT NOT NULL, age INT);
+        CREATE TABLE note (id INTEGER PRIMARY KEY, person INT REFERENCES person(id), text TEXT);
+        CREATE TABLE legacy (x);
+        CREATE INDEX person_name ON person (name);
+        CREATE VIEW adults AS SELECT * FROM person WHERE age >= 18;
+        CREATE TRIGGER note_touch AFTER INSERT ON note BEGIN SELECT 1; END;
+        INSERT INTO person (name, age) VALUES ('Piotr', 64), ('Ala', 7);
+        INSERT INTO note (person, text) VALUES (1, 'hello');
+    "#;
+
+    static EXPECTED: &str = r#"
+        CREATE TABLE person (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            age INT,
+            email TEXT COLLATE NOCASE DEFAULT ''
+        );
+        CREATE TABLE note (id INTEGER PRIMARY KEY, person INT NOT NULL REFERENCES person(id), text TEXT, CHECK (length(text) < 100));
+        CREATE TABLE tag (name TEXT PRIMARY KEY) WITHOUT ROWID;
+        CREATE INDEX person_name ON person (name, age);
+        CREATE VIEW adults AS SELECT id, name FROM person WHERE age >= 18;
+        CREATE TRIGGER note_touch AFTER INSERT ON note BEGIN SELECT 1; END;
+    "#;
+
+    #[test]
+    fn diff_test() {
+        let mut current = test_database(CURRENT);
+        let mut expected = test_database(EXPECTED);
+        let diff = schema_diff(&mut current, &mut expected).unwrap();
+
+        assert_eq!(diff.added_tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), vec!["tag"]);
+        assert_eq!(diff.removed_tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), vec!["legacy"]);
+        assert_eq!(diff.changed_views.len(), 1);
+        assert!(diff.changed_triggers.is_empty());
+
+        let person = diff.changed_tables.iter().find(|t| t.name == "person").unwrap();
+        assert!(!person.rebuild);
+        assert_eq!(person.added_columns[0].name, "email");
+        assert_eq!(person.changed_indexes.len(), 1);
+        let note = diff.changed_tables.iter().find(|t| t.name == "note").unwrap();
+        assert!(note.rebuild);
+        assert_eq!(note.changed_columns[0].to.name, "person");
+
+        let sql = diff.migration_sql();
+        assert!(sql.contains(r#"ALTER TABLE "person" ADD COLUMN email TEXT COLLATE NOCASE DEFAULT '';"#), "{sql}");
+        assert!(sql.contains(r#"ALTER TABLE "sql3x_new_note" RENAME TO "note";"#), "{sql}");
+
+        current.migrate(&diff).unwrap();
+        assert!(schema_diff(&mut current, &mut expected).unwrap().is_empty());
+
+        let notes = current.select(Query::new("SELECT text FROM note")).unwrap();
+        assert_eq!(notes[0]["text"], Value::from("hello"));
+        let people = current.select(Query::new("SELECT * FROM adults")).unwrap();
+        assert_eq!(people.len(), 1);
+        let triggers = current.schema().unwrap().triggers;
+        assert_eq!(triggers.len(), 1);
+    }
+
+    #[test]
+    fn migrate_foreign_key_test() {
+        let mut current = test_database(r#"
+            CREATE TABLE person (id INTEGER PRIMARY KEY);
+            CREATE TABLE note (id INTEGER PRIMARY KEY, person INT);
+            INSERT INTO note (person) VALUES (7);
+        "#);
+        let mut expected = test_database(r#"
+            CREATE TABLE person (id INTEGER PRIMARY KEY);
+            CREATE TABLE note (id INTEGER PRIMARY KEY, person INT REFERENCES person(id));
+        "#);
+        let diff = schema_diff(&mut current, &mut expected).unwrap();
+        assert!(diff.migration().contains(&FOREIGN_KEY_CHECK.to_string()));
+
+        let e = current.migrate(&diff).unwrap_err();
+        assert!(e.to_string().starts_with("migration breaks foreign keys: 1 rows"), "{e}");
+        assert!(!current.in_transaction());
+        // rolled back, and foreign keys are enforced again
+        assert_eq!(schema_diff(&mut current, &mut expected).unwrap(), diff);
+        let fk = current.select(Query::new("PRAGMA foreign_keys")).unwrap();
+        assert_eq!(fk[0]["foreign_keys"], Value::I64(1));
+
+        current.exec_command("INSERT INTO person (id) VALUES (7)").unwrap();
+        current.migrate(&diff).unwrap();
+        assert!(schema_diff(&mut current, &mut expected).unwrap().is_empty());
+    }
+
+    #[test]
+    fn same_schema_test() {
+        let mut a = test_database("CREATE TABLE t (a INT, b TEXT); CREATE INDEX t_a ON t(a);");
+        let mut b = test_database("create table t (\n  a int,\n  b text\n);\ncreate index t_a on t (a);");
+        let diff = schema_diff(&mut a, &mut b).unwrap();
+        assert!(diff.is_empty(), "{diff:?}");
+        assert_eq!(diff.migration(), vec!["BEGIN;", "COMMIT;"]);
+
+        // shadow tables come and go with their virtual table
+        let mut c = test_database("CREATE TABLE t (a INT, b TEXT); CREATE INDEX t_a ON t(a); CREATE VIRTUAL TABLE doc USING fts5(body);");
+        let diff = schema_diff(&mut a, &mut c).unwrap();
+        assert_eq!(diff.added_tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), vec!["doc"]);
+        a.migrate(&diff).unwrap();
+        assert!(schema_diff(&mut a, &mut c).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_test() {
+        let definition = Definition::parse(r#"CREATE TABLE "a b" ( x INT /* note */, "y" TEXT DEFAULT 'A,B', PRIMARY KEY (x, y)) STRICT"#);
+        assert_eq!(definition.columns, vec!["x int", "y text default 'A,B'"]);
+        assert_eq!(definition.constraints, vec!["primary key ( x , y )"]);
+        assert_eq!(definition.suffix, "strict");
+        assert_eq!(rename_create(r#"CREATE TABLE IF NOT EXISTS main."a b" (x)"#, "\"c\""), r#"CREATE TABLE IF NOT EXISTS "c" (x)"#);
+        assert!(addable("c int default 0 not null"));
+        assert!(!addable("c int not null"));
+        assert!(!addable("c int default current_timestamp"));
+        assert!(!addable("c int unique"));
+    }
+}
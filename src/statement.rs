@@ -0,0 +1,245 @@
+use std::ffi::{CStr, c_void};
+use std::marker::PhantomData;
+use sqlite3_sys::{
+    sqlite3_bind_parameter_name,
+    sqlite3_expanded_sql,
+    sqlite3_free,
+    sqlite3_sql,
+    SQLITE_DONE,
+    SQLITE_ROW,
+};
+use crate::args::{Args, ValueConvertible};
+use crate::db::SQLite;
+use crate::error::{Error, Result};
+use crate::stmt::Stmt;
+use crate::value::Value;
+use crate::value_ref::{FromValueRef, ValueRef};
+
+/// Outcome of `Statement::step`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// A row is available through the column accessors.
+    Row,
+    /// The statement has finished, the next step runs it again.
+    Done,
+}
+
+/// Prepared statement borrowing its connection, finalized on drop.
+/// Parameters are bound once and the statement can be reset and run again.
+pub struct Statement<'conn> {
+    stmt: Stmt,
+    _conn: PhantomData<&'conn SQLite>,
+}
+
+impl SQLite {
+    /// Prepare the first statement of `sql`.
+    pub fn prepare(&self, sql: &str) -> Result<Statement<'_>> {
+        self.database_opened()?;
+        let stmt = Stmt::for_command(self.db, sql)?;
+        if stmt.stmt.is_null() {
+            return Err(Error::Validation("no statement to prepare".into()));
+        }
+        Ok(Statement { stmt, _conn: PhantomData })
+    }
+}
+
+impl Statement<'_> {
+    /// Text the statement was prepared from.
+    pub fn sql(&self) -> String {
+        unsafe { CStr::from_ptr(sqlite3_sql(self.stmt.stmt)).to_string_lossy().into_owned() }
+    }
+
+    /// Text with the bound parameters filled in, None when SQLite is out of memory.
+    pub fn expanded_sql(&self) -> Option<String> {
+        unsafe {
+            let ptr = sqlite3_expanded_sql(self.stmt.stmt);
+            if ptr.is_null() {
+                return None;
+            }
+            let sql = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+            sqlite3_free(ptr as *mut c_void);
+            Some(sql)
+        }
+    }
+
+    /// The statement makes no direct changes to the database file.
+    pub fn readonly(&self) -> bool {
+        self.stmt.is_read_only()
+    }
+
+    /// Number of parameters, the largest index when `?NNN` is used.
+    pub fn parameter_count(&self) -> usize {
+        self.stmt.parameter_count()
+    }
+
+    /// Name of a parameter with its prefix (`:id`, `@id`, `$id`, `?2`), None for a plain `?`.
+    /// Indexes start at 1 like in SQL.
+    pub fn parameter_name(&self, idx: usize) -> Option<String> {
+        unsafe {
+            let ptr = sqlite3_bind_parameter_name(self.stmt.stmt, idx as i32);
+            (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_string_lossy().into_owned())
+        }
+    }
+
+    /// Index of a named parameter, the prefix may be left out.
+    pub fn parameter_index(&self, name: &str) -> Option<usize> {
+        let idx = match name.starts_with([':', '@', '$', '?']) {
+            true => self.stmt.parameter_index(name),
+            false => [":", "@", "$"]
+                .iter()
+                .map(|prefix| self.stmt.parameter_index(&format!("{prefix}{name}")))
+                .find(|idx| *idx > 0)
+                .unwrap_or(0)
+        };
+        (idx > 0).then_some(idx as usize)
+    }
+
+    /// Bind arguments to the parameters in order, starting at the first one.
+    pub fn bind(&mut self, args: Args) -> Result<()> {
+        self.stmt.bind(args)
+    }
+
+    /// Bind a value to a named parameter, see `parameter_index`.
+    pub fn bind_named<T: ValueConvertible>(&mut self, name: &str, value: T) -> Result<()> {
+        let Some(idx) = self.parameter_index(name) else {
+            return Err(Error::Validation(format!("no such parameter: {name}")));
+        };
+        let value = value.try_to_value().map_err(|e| e.context(&format!("parameter {name}")))?;
+        self.stmt.bind_at(idx as i32 - 1, &value)
+    }
+
+    /// Set all parameters back to NULL.
+    pub fn clear_bindings(&mut self) -> Result<()> {
+        self.stmt.clear_bindings()
+    }
+
+    /// Rewind the statement to run it again, bindings are kept.
+    /// Fails with the error of the last step when that step failed.
+    pub fn reset(&mut self) -> Result<()> {
+        self.stmt.reset()
+    }
+
+    /// Run the statement to the next row.
+    pub fn step(&mut self) -> Result<Step> {
+        match self.stmt.step() {
+            SQLITE_ROW => Ok(Step::Row),
+            SQLITE_DONE => Ok(Step::Done),
+            _ => Err(self.stmt.error())
+        }
+    }
+
+    pub fn column_count(&self) -> usize {
+        self.stmt.column_count() as usize
+    }
+
+    /// Name of a result column, None past the last column.
+    pub fn column_name(&self, idx: usize) -> Option<String> {
+        (idx < self.column_count()).then(|| self.stmt.column_name_for_idx(idx as i32))
+    }
+
+    /// Names of the result columns in SELECT order.
+    pub fn column_names(&self) -> Vec<String> {
+        self.stmt.column_names()
+    }
+
+    /// Value of a column of the current row, borrowed until the next step or reset.
+    /// Null past the last column.
+    pub fn value_ref(&self, idx: usize) -> ValueRef<'_> {
+        match idx < self.column_count() {
+            true => self.stmt.value_ref(idx as i32),
+            false => ValueRef::Null
+        }
+    }
+
+    /// Owned value of a column of the current row.
+    pub fn value(&self, idx: usize) -> Value {
+        self.value_ref(idx).to_value()
+    }
+
+    /// Value of a column of the current row converted to `T`, errors name the column.
+    pub fn get<'a, T: FromValueRef<'a>>(&'a self, idx: usize) -> Result<T> {
+        let Some(column) = self.column_name(idx) else {
+            return Err(Error::Validation(format!("no such column index in row: {idx}")));
+        };
+        T::from_value_ref(self.value_ref(idx)).map_err(|e| e.column(&column).into())
+    }
+
+    /// Finalize now and report the error, dropping the statement ignores it.
+    pub fn finalize(mut self) -> Result<()> {
+        self.stmt.finalize()
+    }
+}
+
+impl std::fmt::Debug for Statement<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Statement").field("sql", &self.sql()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+    use crate::query::Query;
+
+    #[test]
+    fn reuse_statement_test() {
+        let mut sq = test_database("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT)");
+        {
+            let mut insert = sq.prepare("INSERT INTO t (name) VALUES (:name)").unwrap();
+            assert!(!insert.readonly());
+            assert_eq!(insert.parameter_count(), 1);
+            assert_eq!(insert.parameter_name(1).as_deref(), Some(":name"));
+            assert_eq!(insert.parameter_index("name"), Some(1));
+            for name in ["Ann", "Bob"] {
+                insert.bind_named("name", name).unwrap();
+                assert_eq!(insert.step().unwrap(), Step::Done);
+                insert.reset().unwrap();
+            }
+            insert.bind_named(":name", "it's").unwrap();
+            assert_eq!(insert.expanded_sql().as_deref(), Some("INSERT INTO t (name) VALUES ('it''s')"));
+            insert.step().unwrap();
+            insert.reset().unwrap();
+            insert.clear_bindings().unwrap();
+            insert.step().unwrap();
+            assert!(insert.bind_named("nope", 1).unwrap_err().to_string().contains("no such parameter"));
+        }
+
+        let mut select = sq.prepare("SELECT id, name FROM t WHERE id >= ? ORDER BY id").unwrap();
+        assert!(select.readonly());
+        assert_eq!(select.sql(), "SELECT id, name FROM t WHERE id >= ? ORDER BY id");
+        assert_eq!(select.column_names(), vec!["id", "name"]);
+        select.bind(Args::new().arg(2)).unwrap();
+        let mut rows = Vec::new();
+        while select.step().unwrap() == Step::Row {
+            rows.push((select.get::<i64>(0).unwrap(), select.get::<Option<&str>>(1).unwrap().map(String::from)));
+        }
+        assert_eq!(rows, vec![(2, Some("Bob".to_string())), (3, Some("it's".to_string())), (4, None)]);
+        assert_eq!(select.value(5), Value::Null);
+        assert!(select.get::<i64>(5).is_err());
+        select.finalize().unwrap();
+
+        // every statement is finalized, so the connection closes
+        sq.exec(Query::new("UPDATE t SET name = upper(name)")).unwrap();
+        sq.select(Query::new("SELECT * FROM t")).unwrap();
+        drop(sq.prepare("SELECT 1").unwrap());
+        sq.close().unwrap();
+    }
+
+    #[test]
+    fn statement_error_test() {
+        let mut sq = test_database("CREATE TABLE t (a INT UNIQUE)");
+        assert!(sq.prepare("SELECT nope").unwrap_err().sqlite().is_some());
+        assert!(matches!(sq.prepare("  -- nothing"), Err(Error::Validation(_))));
+        assert!(matches!(sq.prepare("SELECT '\0'"), Err(Error::Validation(_))));
+
+        let mut insert = sq.prepare("INSERT INTO t VALUES (:a)").unwrap();
+        insert.bind(Args::new().arg(1)).unwrap();
+        insert.step().unwrap();
+        insert.reset().unwrap();
+        assert!(insert.step().unwrap_err().is_constraint());
+        assert!(insert.reset().unwrap_err().is_constraint());
+        assert!(insert.bind(Args::new().arg(1).arg(2)).is_err());
+        assert!(insert.bind_named("a", u64::MAX).unwrap_err().to_string().starts_with("parameter a: cannot convert"));
+    }
+}
@@ -7,14 +7,15 @@ use std::{
     },
     io::ErrorKind::*,
     mem::transmute,
-    ptr::{copy, null_mut}
+    ptr::null_mut
 };
 use sqlite3_sys::*;
 use crate::args::Args;
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, SqliteError};
 use crate::{Row, QueryResult};
 use crate::query::Query;
 use crate::value::Value;
+use crate::value_ref::ValueRef;
 
 pub struct Stmt {
     pub stmt: *mut sqlite3_stmt,
@@ -29,10 +30,12 @@ impl Stmt {
     }
     
     pub(crate) fn prepare(&mut self, query: &str) -> Result<()> {
+        let sql = CString::new(query).map_err(|_| Error::Validation("SQL contains a NUL character".into()))?;
+        self.finalize()?;
         unsafe {
-            match sqlite3_prepare_v2(self.db, CString::new(query).unwrap().into_raw(), -1, &mut self.stmt, null_mut()) {
+            match sqlite3_prepare_v2(self.db, sql.as_ptr(), -1, &mut self.stmt, null_mut()) {
                 SQLITE_OK => Ok(()),
-                _ => Err(self.error())
+                _ => Err(SqliteError::from_db(self.db, Some(query)).into())
             }
         }
     }
@@ -46,15 +49,28 @@ impl Stmt {
         }
     }
     
-    pub(crate)fn finalize(&mut self) -> Result<()> {
+    pub(crate) fn clear_bindings(&mut self) -> Result<()> {
         unsafe {
-            match sqlite3_finalize(self.stmt) {
+            match sqlite3_clear_bindings(self.stmt) {
                 SQLITE_OK => Ok(()),
-                _ => Err(self.error())   
+                _ => Err(self.error())
             }
         }
     }
     
+    /// Release the statement, it is also done on drop. Calling it again does nothing.
+    pub(crate) fn finalize(&mut self) -> Result<()> {
+        if self.stmt.is_null() {
+            return Ok(());
+        }
+        let rc = unsafe { sqlite3_finalize(self.stmt) };
+        self.stmt = null_mut();
+        match rc {
+            SQLITE_OK => Ok(()),
+            _ => Err(self.error())
+        }
+    }
+    
     #[inline]
     pub(crate) fn step(&mut self) -> i32 {
         unsafe { sqlite3_step(self.stmt) }
@@ -62,7 +78,7 @@ impl Stmt {
 
     /// Columns count in a result set
     #[inline]
-    fn column_count(&self) -> i32 {
+    pub(crate) fn column_count(&self) -> i32 {
         unsafe { sqlite3_column_count(self.stmt) }
     }
     
@@ -71,15 +87,30 @@ impl Stmt {
         unsafe { sqlite3_column_type(self.stmt, index) }
     }
     
-    /// Returns index of column with given name
+    /// Check if the statement makes no direct changes to the database file.
     #[inline]
-    fn column_idx_for_name(&self, name: &str) -> i32 {
-        unsafe { sqlite3_bind_parameter_index(self.stmt, CString::new(name).unwrap().into_raw()) }
+    pub(crate) fn is_read_only(&self) -> bool {
+        unsafe { sqlite3_stmt_readonly(self.stmt) != 0 }
+    }
+
+    /// Number of parameters (placeholders) in the statement.
+    #[inline]
+    pub(crate) fn parameter_count(&self) -> usize {
+        unsafe { sqlite3_bind_parameter_count(self.stmt) as usize }
+    }
+    
+    /// Returns index of a named parameter, 0 when there is no such parameter.
+    #[inline]
+    pub(crate) fn parameter_index(&self, name: &str) -> i32 {
+        match CString::new(name) {
+            Ok(name) => unsafe { sqlite3_bind_parameter_index(self.stmt, name.as_ptr()) },
+            Err(_) => 0
+        }
     }
     
     /// Returns name of column with given index
     #[inline]
-    fn column_name_for_idx(&self, idx: i32) -> String {
+    pub(crate) fn column_name_for_idx(&self, idx: i32) -> String {
         unsafe {
             let ptr = sqlite3_column_name(self.stmt, idx);
             String::from_utf8_lossy(CStr::from_ptr(ptr).to_bytes()).into()
@@ -89,6 +120,9 @@ impl Stmt {
     /// Binds all arguments of a query to a statement.
     /// Function for use and call from Query self.
     pub(crate) fn bind_for_query(&mut self, args: &Args) -> Result<()> {
+        if let Some(e) = args.error() {
+            return Err(e.clone().into());
+        }
         args
             .iter()
             .enumerate()
@@ -99,6 +133,9 @@ impl Stmt {
     /// Binds all arguments of a query to a statement.
     /// Args are moved from an external query.
     pub(crate) fn bind(&mut self, args: Args) -> Result<()> {
+        if let Some(e) = args.error() {
+            return Err(e.clone().into());
+        }
         args
             .iter()
             .enumerate()
@@ -107,7 +144,7 @@ impl Stmt {
     }
     
     
-    fn bind_at(&mut self, idx: i32, value: &Value) -> Result<()> {
+    pub(crate) fn bind_at(&mut self, idx: i32, value: &Value) -> Result<()> {
         let idx = idx + 1;
         match value {
             Value::Null => self.bind_null(idx)?,
@@ -120,25 +157,76 @@ impl Stmt {
     }
     
     
+    fn fetch_value(&self, idx: i32) -> Value {
+        self.value_ref(idx).to_value()
+    }
+
+    /// Value of a column of the current row, borrowed until the next step.
+    pub(crate) fn value_ref(&self, idx: i32) -> ValueRef<'_> {
+        let column_type = self.column_type(idx);
+        // https://www.sqlite.org/c3ref/c_blob.html
+        match column_type {
+            SQLITE_INTEGER => ValueRef::I64(self.get_i64(idx)),
+            SQLITE_FLOAT => ValueRef::F64(self.get_f64(idx)),
+            SQLITE_TEXT => ValueRef::Text(self.get_bytes(idx, true)),
+            SQLITE_BLOB => ValueRef::Blob(self.get_bytes(idx, false)),
+            SQLITE_NULL => ValueRef::Null,
+            _ => panic!("Unknown column type: {column_type} for column: {}", self.column_name_for_idx(idx)),
+        }
+    }
+
     fn fetch_row(&self, columns: i32) -> Row {
         (0..columns)
-            .map(|idx| {
-                let column_type = self.column_type(idx);
-                let column_name = self.column_name_for_idx(idx);
-                // https://www.sqlite.org/c3ref/c_blob.html
-                let column_value = match column_type {
-                    1 => Value::from(self.get_i64(idx)),
-                    2 => Value::from(self.get_f64(idx)),
-                    3 => Value::from(self.get_text(idx)),
-                    4 => Value::from(self.get_blob(idx)),
-                    5 => Value::from(()),
-                    _ => panic!("Unknown column type: {column_type} for column: {column_name}"),
-                };
-                (column_name, column_value)
+            .map(|idx| (self.column_name_for_idx(idx), self.fetch_value(idx)))
+            .collect()
+    }
+
+    /// Names of the result columns in SELECT order.
+    pub(crate) fn column_names(&self) -> Vec<String> {
+        (0..self.column_count())
+            .map(|idx| self.column_name_for_idx(idx))
+            .collect()
+    }
+
+    /// Declared types of the result columns, None for expressions.
+    pub(crate) fn column_decltypes(&self) -> Vec<Option<String>> {
+        (0..self.column_count())
+            .map(|idx| unsafe {
+                let ptr = sqlite3_column_decltype(self.stmt, idx);
+                (!ptr.is_null()).then(|| String::from_utf8_lossy(CStr::from_ptr(ptr).to_bytes()).into())
             })
             .collect()
     }
 
+    /// Passes values of every row, in column order, to a callback.
+    pub(crate) fn for_each_row<F>(&mut self, mut f: F) -> Result<()>
+        where F: FnMut(Vec<Value>) -> Result<()>
+    {
+        let columns = self.column_count();
+        while SQLITE_ROW == self.step() {
+            f((0..columns).map(|idx| self.fetch_value(idx)).collect())?;
+        }
+
+        match self.err_code() {
+            SQLITE_OK | SQLITE_DONE => Ok(()),
+            _ => Err(self.error())
+        }
+    }
+
+    /// Passes the statement positioned on every row to a callback, for reading values in place.
+    pub(crate) fn for_each_row_ref<F>(&mut self, mut f: F) -> Result<()>
+        where F: FnMut(&Stmt) -> Result<()>
+    {
+        while SQLITE_ROW == self.step() {
+            f(self)?;
+        }
+
+        match self.err_code() {
+            SQLITE_OK | SQLITE_DONE => Ok(()),
+            _ => Err(self.error())
+        }
+    }
+
     pub fn fetch_result(&mut self) -> Result<QueryResult> {
         let columns = self.column_count();
 
@@ -240,30 +328,34 @@ impl Stmt {
     fn get_f64(&self, idx: i32) -> f64 {
         unsafe { sqlite3_column_double(self.stmt, idx) }
     }
-    /// Returns text as String.
-    fn get_text(&self, idx: i32) -> String {
+    /// Returns text or blob bytes, the length comes from `sqlite3_column_bytes`
+    /// so texts with embedded NULs are read whole.
+    fn get_bytes(&self, idx: i32, text: bool) -> &[u8] {
         unsafe {
-            let ptr = sqlite3_column_text(self.stmt, idx) as *const c_char;
-            let data = CStr::from_ptr(ptr).to_bytes();
-            String::from_utf8_lossy(data).into_owned()
-        }
-    }
-    /// Returns blob as Vec<u8>.
-    fn get_blob(&self, idx: i32) -> Vec<u8> {
-        unsafe {
-            let nbytes= sqlite3_column_bytes(self.stmt, idx) as usize;
-            let mut data = vec![0u8; nbytes];
-            let ptr = sqlite3_column_blob(self.stmt, idx);
-            copy(ptr, data.as_mut_ptr() as *mut c_void, nbytes);
-            data
+            // the pointer must be fetched before the length, see sqlite3_column_bytes
+            let ptr = match text {
+                true => sqlite3_column_text(self.stmt, idx),
+                false => sqlite3_column_blob(self.stmt, idx) as *const u8
+            };
+            let nbytes = sqlite3_column_bytes(self.stmt, idx) as usize;
+            match ptr.is_null() || nbytes == 0 {
+                true => &[],
+                false => std::slice::from_raw_parts(ptr, nbytes)
+            }
         }
     }
-    
-    
+
     /// Returns error from sqlite3.
     #[inline]
     pub fn error(&mut self) -> Error {
-        Error{ code: self.err_code(), message: self.err_string(), kind: Some(Other)}
+        let sql = match self.stmt.is_null() {
+            true => None,
+            false => unsafe {
+                let ptr = sqlite3_sql(self.stmt);
+                (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_string_lossy().into_owned())
+            }
+        };
+        SqliteError::from_db(self.db, sql.as_deref()).into()
     }
 
     /// Get the error code from sqlite3.
@@ -277,4 +369,10 @@ impl Stmt {
     fn err_string(&self) -> String {
         unsafe { CStr::from_ptr(sqlite3_errmsg(self.db)).to_string_lossy().into_owned() }
     }
-}
\ No newline at end of file
+}
+
+impl Drop for Stmt {
+    fn drop(&mut self) {
+        let _ = self.finalize();
+    }
+}
@@ -1,23 +1,332 @@
 #![allow(unused)]
-use chrono::Local;
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, Sub};
+use chrono::{DateTime, Duration, Local, SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::datetime::TimeValue;
+use crate::error::ConversionError;
+use crate::value::Value;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Timestamp(i64);
+/// Resolution of a `Timestamp`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TimeUnit {
+    Seconds,
+    Millis,
+    Micros,
+    Nanos,
+}
+
+impl TimeUnit {
+    /// Nanoseconds in one unit.
+    pub fn nanos(self) -> i64 {
+        match self {
+            TimeUnit::Seconds => 1_000_000_000,
+            TimeUnit::Millis => 1_000_000,
+            TimeUnit::Micros => 1_000,
+            TimeUnit::Nanos => 1
+        }
+    }
+
+    fn seconds_format(self) -> SecondsFormat {
+        match self {
+            TimeUnit::Seconds => SecondsFormat::Secs,
+            TimeUnit::Millis => SecondsFormat::Millis,
+            TimeUnit::Micros => SecondsFormat::Micros,
+            TimeUnit::Nanos => SecondsFormat::Nanos
+        }
+    }
+}
+
+/// Time since 1970-01-01 UTC counted in a unit, stored as that integer.
+/// The unit isn't stored, read the integer back with `from_value_in`.
+/// Timestamps of different units compare by the instant they stand for.
+#[derive(Clone, Copy, Debug)]
+pub struct Timestamp {
+    value: i64,
+    unit: TimeUnit,
+}
 
 impl Timestamp {
+    /// Now, in seconds.
     pub fn now() -> Self {
-        Timestamp(Local::now().timestamp())
+        Timestamp::now_in(TimeUnit::Seconds)
     }
+
+    /// Now from the global clock, see `clock::set_global_clock`.
+    pub fn now_in(unit: TimeUnit) -> Self {
+        Timestamp::from(crate::clock::now()).to_unit(unit)
+    }
+
+    pub fn new(value: i64, unit: TimeUnit) -> Self {
+        Timestamp { value, unit }
+    }
+
+    /// Count of units.
     pub fn value(self) -> i64 {
-        self.0
+        self.value
     }
+
+    pub fn unit(self) -> TimeUnit {
+        self.unit
+    }
+
+    /// Timestamp in seconds.
     pub fn from_value(value: i64) -> Self {
-        Timestamp(value)
+        Timestamp::new(value, TimeUnit::Seconds)
+    }
+
+    /// Read a value, integers counting `unit`s. Text and reals are read as in `TryFrom<Value>`.
+    pub fn from_value_in(v: &Value, unit: TimeUnit) -> crate::error::Result<Self> {
+        match v {
+            Value::I64(value) => Ok(Timestamp::new(*value, unit)),
+            v => Ok(Timestamp::try_from(v.clone())?)
+        }
+    }
+
+    pub fn as_nanos(self) -> i128 {
+        self.value as i128 * self.unit.nanos() as i128
+    }
+
+    /// Same instant in another unit, rounded down when the unit is coarser.
+    /// Saturates when it doesn't fit.
+    pub fn to_unit(self, unit: TimeUnit) -> Self {
+        let value = self.as_nanos().div_euclid(unit.nanos() as i128);
+        Timestamp::new(value.clamp(i64::MIN as i128, i64::MAX as i128) as i64, unit)
+    }
+
+    /// None outside chrono's range.
+    pub fn to_datetime(self) -> Option<DateTime<Utc>> {
+        let nanos = self.as_nanos();
+        let secs = i64::try_from(nanos.div_euclid(1_000_000_000)).ok()?;
+        DateTime::from_timestamp(secs, nanos.rem_euclid(1_000_000_000) as u32)
+    }
+
+    /// None on overflow. The duration is rounded down to the unit.
+    pub fn checked_add(self, d: Duration) -> Option<Self> {
+        let units = d.num_nanoseconds().map(i128::from)
+            .unwrap_or(d.num_microseconds()? as i128 * 1_000)
+            .div_euclid(self.unit.nanos() as i128);
+        let value = self.value.checked_add(i64::try_from(units).ok()?)?;
+        Some(Timestamp::new(value, self.unit))
+    }
+
+    pub fn checked_sub(self, d: Duration) -> Option<Self> {
+        self.checked_add(-d)
+    }
+
+    /// Time from `earlier` to this, None when it doesn't fit a `Duration`.
+    pub fn duration_since(self, earlier: Timestamp) -> Option<Duration> {
+        let nanos = self.as_nanos() - earlier.as_nanos();
+        let secs = i64::try_from(nanos.div_euclid(1_000_000_000)).ok()?;
+        Duration::new(secs, nanos.rem_euclid(1_000_000_000) as u32)
+    }
+}
+
+impl PartialEq for Timestamp {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_nanos() == other.as_nanos()
+    }
+}
+
+impl Eq for Timestamp {}
+
+impl PartialOrd for Timestamp {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timestamp {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_nanos().cmp(&other.as_nanos())
+    }
+}
+
+impl Hash for Timestamp {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_nanos().hash(state);
+    }
+}
+
+/// Panics on overflow, see `checked_add`.
+impl Add<Duration> for Timestamp {
+    type Output = Timestamp;
+    fn add(self, d: Duration) -> Timestamp {
+        self.checked_add(d).expect("timestamp overflow")
     }
 }
 
+impl Sub<Duration> for Timestamp {
+    type Output = Timestamp;
+    fn sub(self, d: Duration) -> Timestamp {
+        self.checked_sub(d).expect("timestamp overflow")
+    }
+}
+
+impl Sub for Timestamp {
+    type Output = Duration;
+    fn sub(self, earlier: Timestamp) -> Duration {
+        self.duration_since(earlier).expect("duration overflow")
+    }
+}
+
+/// Seconds.
 impl From<i64> for Timestamp {
     fn from(value: i64) -> Self {
-        Timestamp(value)
+        Timestamp::from_value(value)
+    }
+}
+
+/// In nanoseconds, microseconds outside 1677..2262.
+impl<Tz: TimeZone> From<DateTime<Tz>> for Timestamp {
+    fn from(t: DateTime<Tz>) -> Self {
+        match t.timestamp_nanos_opt() {
+            Some(nanos) => Timestamp::new(nanos, TimeUnit::Nanos),
+            None => Timestamp::new(t.timestamp_micros(), TimeUnit::Micros)
+        }
+    }
+}
+
+/// RFC 3339 in UTC with the fraction digits of the unit, `2024-05-01T10:00:00.250Z`.
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.to_datetime() {
+            Some(t) => write!(f, "{}", t.to_rfc3339_opts(self.unit.seconds_format(), true)),
+            None => write!(f, "{} {:?} since 1970-01-01", self.value, self.unit)
+        }
+    }
+}
+
+/// Text with fractions gets the unit of its digits, reals are Julian days.
+/// Integers are rejected, they are stored in a unit of their own, see `from_value_in`.
+impl TryFrom<Value> for Timestamp {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        let unit = match &v {
+            Value::I64(_) => {
+                return Err(ConversionError::new("Timestamp", v.type_name(), &v.preview()).reason("integer without a unit, use Timestamp::from_value_in"));
+            }
+            Value::Text(text) => fraction_unit(text),
+            _ => TimeUnit::Millis
+        };
+        match TimeValue::parse(&v) {
+            Some(TimeValue::Fixed(t)) => Ok(Timestamp::from(t).to_unit(unit)),
+            // SQLite reads text without an offset as UTC
+            Some(TimeValue::Naive(t)) => Ok(Timestamp::from(t.and_utc()).to_unit(unit)),
+            None => {
+                let e = ConversionError::new("Timestamp", v.type_name(), &v.preview());
+                Err(if matches!(v, Value::Null | Value::Blob(_)) { e } else { e.reason("not a date/time value") })
+            }
+        }
+    }
+}
+
+/// Unit matching the fraction digits of a time text.
+fn fraction_unit(text: &str) -> TimeUnit {
+    let digits = text.split_once('.').map_or(0, |(_, fraction)| fraction.bytes().take_while(u8::is_ascii_digit).count());
+    match digits {
+        0 => TimeUnit::Seconds,
+        1..=3 => TimeUnit::Millis,
+        4..=6 => TimeUnit::Micros,
+        _ => TimeUnit::Nanos
+    }
+}
+
+/// As the `Display` text, an error outside chrono's range since that text doesn't read back.
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.to_datetime().is_none() {
+            return Err(serde::ser::Error::custom(format!("timestamp out of range: {} {:?}", self.value, self.unit)));
+        }
+        serializer.collect_str(self)
+    }
+}
+
+/// From text as in `TryFrom<Value>`, integers are rejected since their unit is unknown.
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Timestamp::try_from(Value::Text(text)).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+    use crate::query::Query;
+
+    #[test]
+    fn unit_and_arithmetic_test() {
+        let t = Timestamp::new(1714557600250, TimeUnit::Millis);
+        assert_eq!(t, Timestamp::new(1714557600250000, TimeUnit::Micros));
+        assert!(t > Timestamp::from_value(1714557600));
+        assert_eq!(t.to_unit(TimeUnit::Seconds).value(), 1714557600);
+        assert_eq!(Timestamp::new(-1, TimeUnit::Millis).to_unit(TimeUnit::Seconds).value(), -1);
+
+        assert_eq!((t + Duration::milliseconds(750)).value(), 1714557601000);
+        assert_eq!((t + Duration::microseconds(1999)).value(), 1714557600251);
+        assert_eq!(t - Timestamp::from_value(1714557600), Duration::milliseconds(250));
+        assert_eq!(Timestamp::new(i64::MAX, TimeUnit::Seconds).checked_add(Duration::seconds(1)), None);
+
+        let _lock = crate::clock::GLOBAL_CLOCK_TEST.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Timestamp::now_in(TimeUnit::Micros);
+        assert_eq!(now.unit(), TimeUnit::Micros);
+        assert!(now > Timestamp::now() - Duration::seconds(1));
+    }
+
+    #[test]
+    fn chrono_and_text_test() {
+        let t = Timestamp::new(1714557600250, TimeUnit::Millis);
+        assert_eq!(t.to_string(), "2024-05-01T10:00:00.250Z");
+        assert_eq!(Timestamp::from_value(0).to_string(), "1970-01-01T00:00:00Z");
+        let dt = t.to_datetime().unwrap();
+        assert_eq!(Timestamp::from(dt), t);
+        assert_eq!(Timestamp::from(dt.with_timezone(&Local)).unit(), TimeUnit::Nanos);
+
+        let json = serde_json::to_string(&t).unwrap();
+        assert_eq!(json, "\"2024-05-01T10:00:00.250Z\"");
+        let back: Timestamp = serde_json::from_str(&json).unwrap();
+        assert_eq!((back, back.unit()), (t, TimeUnit::Millis));
+        assert!(serde_json::from_str::<Timestamp>("1714557600").is_err());
+        let far = Timestamp::new(i64::MAX, TimeUnit::Seconds);
+        assert!(far.to_string().ends_with("Seconds since 1970-01-01"));
+        assert!(serde_json::to_string(&far).is_err());
+
+        assert_eq!(Value::from("2024-05-01T12:00:00.250+02:00").get::<Timestamp>(), Some(t));
+        assert_eq!(Value::from("2024-05-01 10:00:00.250").get::<Timestamp>().unwrap().unit(), TimeUnit::Millis);
+        assert!(Value::I64(1714557600).get::<Timestamp>().is_none());
+        assert_eq!(Timestamp::from_value_in(&Value::I64(1714557600), TimeUnit::Seconds).unwrap(), t.to_unit(TimeUnit::Seconds));
+        assert!(Value::from("soon").get::<Timestamp>().is_none());
+    }
+
+    #[test]
+    fn event_table_test() {
+        let mut sq = test_database("CREATE TABLE event (at INTEGER, name TEXT)");
+        let start = Timestamp::new(1714557600000000, TimeUnit::Micros);
+        for (i, name) in ["b", "a", "c"].iter().enumerate() {
+            let at = start + Duration::microseconds(i as i64);
+            sq.exec(Query::new("INSERT INTO event VALUES (?, ?)").arg(at).arg(*name)).unwrap();
+        }
+        let rows = sq.select(Query::new("SELECT at, name FROM event ORDER BY at DESC")).unwrap();
+        let at = Timestamp::from_value_in(&rows[0]["at"], TimeUnit::Micros).unwrap();
+        assert_eq!((at - start, &rows[0]["name"]), (Duration::microseconds(2), &Value::from("c")));
+    }
+
+    #[test]
+    fn unit_round_trip_test() {
+        let mut sq = test_database("CREATE TABLE event (at INTEGER)");
+        let t = Timestamp::new(1714557600123456789, TimeUnit::Nanos);
+        for unit in [TimeUnit::Seconds, TimeUnit::Millis, TimeUnit::Micros, TimeUnit::Nanos] {
+            let written = t.to_unit(unit);
+            sq.exec(Query::new("DELETE FROM event")).unwrap();
+            sq.exec(Query::new("INSERT INTO event VALUES (?)").arg(written)).unwrap();
+            let rows = sq.select(Query::new("SELECT at FROM event")).unwrap();
+            let read = Timestamp::from_value_in(&rows[0]["at"], unit).unwrap();
+            assert_eq!((read, read.unit()), (written, unit));
+            assert!(Timestamp::try_from(rows[0]["at"].clone()).is_err());
+        }
     }
 }
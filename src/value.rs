@@ -1,8 +1,9 @@
 use std::option::Option;
 use std::fmt::Display;
-use chrono::{DateTime, Local, NaiveDate};
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
-use crate::error::{Result};
+use crate::datetime::{TimeFormat, TimeType};
+use crate::error::{ConversionError, Result};
 use crate::timestamp::Timestamp;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, PartialOrd)]
@@ -32,6 +33,70 @@ impl Value {
     {
         T::try_from(self).ok()
     }
+
+    /// Like `get`, but says why the value doesn't convert. NULL is an error too, see `try_get_opt`.
+    pub fn try_get<T>(self) -> Result<T>
+        where T: TryFrom<Value, Error = ConversionError>
+    {
+        Ok(T::try_from(self)?)
+    }
+
+    /// Like `try_get`, with NULL as `None`.
+    pub fn try_get_opt<T>(self) -> Result<Option<T>>
+        where T: TryFrom<Value, Error = ConversionError>
+    {
+        match self {
+            Value::Null => Ok(None),
+            v => Ok(Some(T::try_from(v)?))
+        }
+    }
+
+    /// Variant name: `Null`, `I64`, `F64`, `Text` or `Blob`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "Null",
+            Value::I64(_) => "I64",
+            Value::F64(_) => "F64",
+            Value::Text(_) => "Text",
+            Value::Blob(_) => "Blob",
+        }
+    }
+
+    /// Short form for messages: numbers as they are, texts quoted and cut, blobs by size.
+    pub fn preview(&self) -> String {
+        match self {
+            Value::Null => String::new(),
+            Value::I64(v) => v.to_string(),
+            Value::F64(v) => v.to_string(),
+            Value::Text(v) => Value::preview_text(v),
+            Value::Blob(v) => format!("<blob {} bytes>", v.len())
+        }
+    }
+
+    pub(crate) fn preview_text(text: &str) -> String {
+        const PREVIEW_CHARS: usize = 20;
+        match text.char_indices().nth(PREVIEW_CHARS) {
+            Some((end, _)) => format!("'{}…'", &text[..end]),
+            None => format!("'{text}'")
+        }
+    }
+
+    /// Value as an SQL literal, e.g. `'it''s'`, `X'0102'` or `NULL`.
+    /// Reals keep their exact (round-trip) text, infinities use SQLite's `1e999`.
+    pub fn to_sql_literal(&self) -> String {
+        match self {
+            Value::Null => "NULL".to_string(),
+            Value::I64(v) => v.to_string(),
+            Value::F64(v) if v.is_nan() => "NULL".to_string(),
+            Value::F64(v) if v.is_infinite() => match v.is_sign_positive() {
+                true => "1e999".to_string(),
+                false => "-1e999".to_string()
+            },
+            Value::F64(v) => format!("{v:?}"),
+            Value::Text(v) => format!("'{}'", v.replace('\'', "''")),
+            Value::Blob(v) => format!("X'{}'", v.iter().map(|b| format!("{b:02X}")).collect::<String>()),
+        }
+    }
 }
 
 /// Convert integer to Value.
@@ -134,10 +199,10 @@ impl From<Option<NaiveDate>> for Value {
     }
 }
 
-/// Convert DateTime to Value.
+/// Convert DateTime to ISO text with the local offset.
 impl From<DateTime<Local>> for Value {
     fn from(d: DateTime<Local>) -> Self {
-        Value::Text(d.format("%Y-%m-%d %H:%M:%S").to_string())
+        d.encode(TimeFormat::Iso)
     }
 }
 impl From<Option<DateTime<Local>>> for Value {
@@ -149,6 +214,78 @@ impl From<Option<DateTime<Local>>> for Value {
     }
 }
 
+/// Convert the other chrono types to ISO text, wrap them in `UnixSeconds`, `UnixMillis`
+/// or `JulianDay` for other storage formats.
+impl From<NaiveDateTime> for Value {
+    fn from(d: NaiveDateTime) -> Self {
+        d.encode(TimeFormat::Iso)
+    }
+}
+impl From<Option<NaiveDateTime>> for Value {
+    fn from(d: Option<NaiveDateTime>) -> Self {
+        match d {
+            Some(d) => Value::from(d),
+            None => Value::from(())
+        }
+    }
+}
+
+impl From<NaiveTime> for Value {
+    fn from(d: NaiveTime) -> Self {
+        d.encode(TimeFormat::Iso)
+    }
+}
+impl From<Option<NaiveTime>> for Value {
+    fn from(d: Option<NaiveTime>) -> Self {
+        match d {
+            Some(d) => Value::from(d),
+            None => Value::from(())
+        }
+    }
+}
+
+impl From<DateTime<Utc>> for Value {
+    fn from(d: DateTime<Utc>) -> Self {
+        d.encode(TimeFormat::Iso)
+    }
+}
+impl From<Option<DateTime<Utc>>> for Value {
+    fn from(d: Option<DateTime<Utc>>) -> Self {
+        match d {
+            Some(d) => Value::from(d),
+            None => Value::from(())
+        }
+    }
+}
+
+impl From<DateTime<FixedOffset>> for Value {
+    fn from(d: DateTime<FixedOffset>) -> Self {
+        d.encode(TimeFormat::Iso)
+    }
+}
+impl From<Option<DateTime<FixedOffset>>> for Value {
+    fn from(d: Option<DateTime<FixedOffset>>) -> Self {
+        match d {
+            Some(d) => Value::from(d),
+            None => Value::from(())
+        }
+    }
+}
+
+impl From<Duration> for Value {
+    fn from(d: Duration) -> Self {
+        d.encode(TimeFormat::Iso)
+    }
+}
+impl From<Option<Duration>> for Value {
+    fn from(d: Option<Duration>) -> Self {
+        match d {
+            Some(d) => Value::from(d),
+            None => Value::from(())
+        }
+    }
+}
+
 
 
 /// Convert Timestamp to Value.
@@ -225,4 +362,16 @@ mod tests {
         let v = Value::from(vec![1, 2, 3]);
         println!("{:?}", v.get::<Vec<u8>>());
     }
+
+    #[test]
+    fn to_sql_literal_test() {
+        assert_eq!(Value::Null.to_sql_literal(), "NULL");
+        assert_eq!(Value::I64(-42).to_sql_literal(), "-42");
+        assert_eq!(Value::F64(1.0).to_sql_literal(), "1.0");
+        assert_eq!(Value::F64(0.1).to_sql_literal(), "0.1");
+        assert_eq!(Value::F64(1e300).to_sql_literal(), "1e300");
+        assert_eq!(Value::F64(f64::NEG_INFINITY).to_sql_literal(), "-1e999");
+        assert_eq!(Value::from("it's").to_sql_literal(), "'it''s'");
+        assert_eq!(Value::Blob(vec![0, 0xab]).to_sql_literal(), "X'00AB'");
+    }
 }
\ No newline at end of file
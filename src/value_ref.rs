@@ -0,0 +1,325 @@
+use std::str;
+use crate::error::{ConversionError, Error, Result};
+use crate::stmt::Stmt;
+use crate::value::Value;
+use crate::value_try_from::mismatch;
+
+/// Column value borrowed from a statement, valid until the next step.
+/// Text keeps the bytes SQLite stored: embedded NULs are kept and UTF-8 is checked
+/// only when the text is read as `&str` or `String`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueRef<'a> {
+    Null,
+    I64(i64),
+    F64(f64),
+    Text(&'a [u8]),
+    Blob(&'a [u8]),
+}
+
+impl<'a> ValueRef<'a> {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ValueRef::Null => "Null",
+            ValueRef::I64(_) => "I64",
+            ValueRef::F64(_) => "F64",
+            ValueRef::Text(_) => "Text",
+            ValueRef::Blob(_) => "Blob",
+        }
+    }
+
+    /// Short form for messages, see `Value::preview`.
+    pub fn preview(&self) -> String {
+        match self {
+            ValueRef::Text(v) => Value::preview_text(&String::from_utf8_lossy(v)),
+            ValueRef::Blob(v) => format!("<blob {} bytes>", v.len()),
+            v => v.to_value().preview()
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, ValueRef::Null)
+    }
+
+    /// Text as `&str`, an error for other variants and invalid UTF-8.
+    pub fn as_str(&self) -> std::result::Result<&'a str, ConversionError> {
+        match *self {
+            ValueRef::Text(v) => str::from_utf8(v).map_err(|e| {
+                self.mismatch("&str").reason(&format!("invalid UTF-8 at byte {}", e.valid_up_to()))
+            }),
+            _ => Err(self.mismatch("&str"))
+        }
+    }
+
+    /// Bytes of a blob or a text.
+    pub fn as_bytes(&self) -> std::result::Result<&'a [u8], ConversionError> {
+        match *self {
+            ValueRef::Text(v) | ValueRef::Blob(v) => Ok(v),
+            _ => Err(self.mismatch("&[u8]"))
+        }
+    }
+
+    /// Owned copy. Invalid UTF-8 in a text is replaced with U+FFFD, as `select` does.
+    pub fn to_value(&self) -> Value {
+        match *self {
+            ValueRef::Null => Value::Null,
+            ValueRef::I64(v) => Value::I64(v),
+            ValueRef::F64(v) => Value::F64(v),
+            ValueRef::Text(v) => Value::Text(String::from_utf8_lossy(v).into_owned()),
+            ValueRef::Blob(v) => Value::Blob(v.to_vec()),
+        }
+    }
+
+    /// Convert into `&str`, `&[u8]` or an owned type.
+    pub fn get<T: FromValueRef<'a>>(self) -> std::result::Result<T, ConversionError> {
+        T::from_value_ref(self)
+    }
+
+    fn mismatch(&self, expected: &str) -> ConversionError {
+        ConversionError::new(expected, self.type_name(), &self.preview())
+    }
+}
+
+impl<'a> From<&'a Value> for ValueRef<'a> {
+    fn from(v: &'a Value) -> Self {
+        match v {
+            Value::Null => ValueRef::Null,
+            Value::I64(v) => ValueRef::I64(*v),
+            Value::F64(v) => ValueRef::F64(*v),
+            Value::Text(v) => ValueRef::Text(v.as_bytes()),
+            Value::Blob(v) => ValueRef::Blob(v),
+        }
+    }
+}
+
+/// Conversion from a borrowed column value, the borrowed counterpart of `TryFrom<Value>`.
+pub trait FromValueRef<'a>: Sized {
+    fn from_value_ref(v: ValueRef<'a>) -> std::result::Result<Self, ConversionError>;
+}
+
+impl<'a> FromValueRef<'a> for ValueRef<'a> {
+    fn from_value_ref(v: ValueRef<'a>) -> std::result::Result<Self, ConversionError> {
+        Ok(v)
+    }
+}
+
+impl<'a> FromValueRef<'a> for &'a str {
+    fn from_value_ref(v: ValueRef<'a>) -> std::result::Result<Self, ConversionError> {
+        v.as_str()
+    }
+}
+
+impl<'a> FromValueRef<'a> for &'a [u8] {
+    fn from_value_ref(v: ValueRef<'a>) -> std::result::Result<Self, ConversionError> {
+        v.as_bytes()
+    }
+}
+
+impl FromValueRef<'_> for String {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> {
+        v.as_str().map(String::from).map_err(|e| ConversionError { expected: "String".into(), ..e })
+    }
+}
+
+impl FromValueRef<'_> for Vec<u8> {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> {
+        match v {
+            ValueRef::Blob(v) => Ok(v.to_vec()),
+            v => Err(v.mismatch("Vec<u8>"))
+        }
+    }
+}
+
+impl FromValueRef<'_> for Value {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> {
+        Ok(v.to_value())
+    }
+}
+
+impl<'a, T: FromValueRef<'a>> FromValueRef<'a> for Option<T> {
+    fn from_value_ref(v: ValueRef<'a>) -> std::result::Result<Self, ConversionError> {
+        match v {
+            ValueRef::Null => Ok(None),
+            v => T::from_value_ref(v).map(Some)
+        }
+    }
+}
+
+/// Numbers, bool and char go through `TryFrom<Value>`, texts and blobs are copied into the `Value` first.
+fn owned<T: TryFrom<Value, Error = ConversionError>>(v: ValueRef<'_>) -> std::result::Result<T, ConversionError> {
+    T::try_from(v.to_value())
+}
+
+impl FromValueRef<'_> for i8 {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+impl FromValueRef<'_> for u8 {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+impl FromValueRef<'_> for i16 {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+impl FromValueRef<'_> for u16 {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+impl FromValueRef<'_> for i32 {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+impl FromValueRef<'_> for u32 {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+impl FromValueRef<'_> for i64 {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+impl FromValueRef<'_> for u64 {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+impl FromValueRef<'_> for usize {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+impl FromValueRef<'_> for i128 {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+impl FromValueRef<'_> for u128 {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+impl FromValueRef<'_> for f32 {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+impl FromValueRef<'_> for f64 {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+impl FromValueRef<'_> for bool {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+impl FromValueRef<'_> for char {
+    fn from_value_ref(v: ValueRef<'_>) -> std::result::Result<Self, ConversionError> { owned(v) }
+}
+
+/// Current row of a statement, passed to `SQLite::select_each_ref`.
+/// Values borrow from the statement and are valid only inside the callback.
+pub struct RowRef<'r> {
+    stmt: &'r Stmt,
+    columns: &'r [String],
+}
+
+impl<'r> RowRef<'r> {
+    pub(crate) fn new(stmt: &'r Stmt, columns: &'r [String]) -> Self {
+        RowRef { stmt, columns }
+    }
+
+    /// Column names in SELECT order.
+    pub fn columns(&self) -> &'r [String] {
+        self.columns
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Position of a column, names compare case-insensitively like in SQL.
+    pub fn index(&self, column: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.eq_ignore_ascii_case(column))
+    }
+
+    /// Borrowed value of a column, Null for an index past the last column.
+    pub fn value(&self, idx: usize) -> ValueRef<'r> {
+        match idx < self.columns.len() {
+            true => self.stmt.value_ref(idx as i32),
+            false => ValueRef::Null
+        }
+    }
+
+    /// Value of a column by position, errors name the column.
+    pub fn get<T: FromValueRef<'r>>(&self, idx: usize) -> Result<T> {
+        let Some(column) = self.columns.get(idx) else {
+            return Err(Error::Validation(format!("no such column index in row: {idx}")));
+        };
+        T::from_value_ref(self.value(idx)).map_err(|e| e.column(column).into())
+    }
+
+    /// Value of a column by name, errors name the column.
+    pub fn get_by_name<T: FromValueRef<'r>>(&self, column: &str) -> Result<T> {
+        match self.index(column) {
+            Some(idx) => self.get(idx),
+            None => Err(Error::Validation(format!("no such column in row: {column}")))
+        }
+    }
+
+    /// Owned copy of the row.
+    pub fn to_values(&self) -> Vec<Value> {
+        (0..self.len()).map(|idx| self.value(idx).to_value()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+    use crate::query::Query;
+
+    #[test]
+    fn borrowed_text_test() {
+        let mut sq = test_database("CREATE TABLE t (a TEXT, b BLOB, n INT)");
+        sq.exec(Query::new("INSERT INTO t VALUES ('x' || char(0) || 'y', x'0001ff', 300)")).unwrap();
+        sq.exec(Query::new("INSERT INTO t VALUES (CAST(x'41ff' AS TEXT), NULL, NULL)")).unwrap();
+
+        let mut rows = Vec::new();
+        sq.select_each_ref(Query::new("SELECT a, b, n FROM t ORDER BY rowid"), |row| {
+            rows.push((row.value(0).as_bytes()?.to_vec(), row.get::<Option<&[u8]>>(1)?.map(<[u8]>::len), row.get::<Option<i64>>(2)?));
+            Ok(())
+        }).unwrap();
+        assert_eq!(rows, vec![
+            (b"x\0y".to_vec(), Some(3), Some(300)),
+            (b"A\xff".to_vec(), None, None),
+        ]);
+
+        sq.select_each_ref(Query::new("SELECT a FROM t WHERE rowid = 1"), |row| {
+            let a: &str = row.get_by_name("A")?;
+            assert_eq!(a, "x\0y");
+            Ok(())
+        }).unwrap();
+
+        // select keeps the NUL and replaces invalid UTF-8
+        let result = sq.select(Query::new("SELECT a FROM t ORDER BY rowid")).unwrap();
+        assert_eq!(result[0]["a"], Value::from("x\0y"));
+        assert_eq!(result[1]["a"], Value::from("A\u{fffd}"));
+    }
+
+    #[test]
+    fn conversion_error_test() {
+        let mut sq = test_database("CREATE TABLE t (a TEXT, n INT)");
+        sq.exec(Query::new("INSERT INTO t VALUES (CAST(x'41ff' AS TEXT), 300)")).unwrap();
+
+        let e = sq.select_each_ref(Query::new("SELECT a FROM t"), |row| row.get::<&str>(0).map(drop)).unwrap_err();
+        let e = e.conversion().unwrap();
+        assert_eq!(e.reason.as_deref(), Some("invalid UTF-8 at byte 1"));
+        assert_eq!(e.column.as_deref(), Some("a"));
+
+        let e = sq.select_each_ref(Query::new("SELECT n FROM t"), |row| row.get::<u8>(0).map(drop)).unwrap_err();
+        assert_eq!(e.to_string(), "cannot convert I64 300 to u8: out of range (column n)");
+
+        let e = sq.select_each_ref(Query::new("SELECT n FROM t"), |row| row.get_by_name::<i64>("m").map(drop)).unwrap_err();
+        assert_eq!(e.to_string(), "no such column in row: m");
+
+        assert_eq!(ValueRef::from(&Value::from(7)).get::<i32>().unwrap(), 7);
+        assert!(ValueRef::Blob(b"a").get::<&str>().is_err());
+    }
+}
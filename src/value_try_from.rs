@@ -1,144 +1,405 @@
 use chrono::{
     DateTime,
+    Duration,
+    FixedOffset,
     Local,
     NaiveDate,
     NaiveDateTime,
-    TimeZone
+    NaiveTime,
+    TimeZone,
+    Utc
 };
+use crate::args::{AsBlob, AsText, WideInteger};
+use crate::datetime::{TimeFormat, TimeType, TimeValue};
+use crate::error::ConversionError;
 use crate::value::Value;
 
-const INVALID_VALUE_TYPE: &str = "invalid value type";
+pub(crate) fn mismatch(expected: &str, v: &Value) -> ConversionError {
+    ConversionError::new(expected, v.type_name(), &v.preview())
+}
+
+fn not_a_time(expected: &str, v: &Value) -> ConversionError {
+    match v {
+        Value::Null | Value::Blob(_) => mismatch(expected, v),
+        _ => mismatch(expected, v).reason("not a date/time value")
+    }
+}
+
+fn out_of_range(expected: &str, v: &Value) -> ConversionError {
+    mismatch(expected, v).reason("out of range")
+}
 
 impl TryFrom<Value> for i8 {
-    type Error =&'static str;
+    type Error = ConversionError;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v {
-            Value::I64(iv) => Ok(iv as i8),
-            _ => Err(INVALID_VALUE_TYPE)
+            // narrowed with a check, never wrapped
+            Value::I64(iv) => i8::try_from(iv).map_err(|_| out_of_range("i8", &v)),
+            _ => Err(mismatch("i8", &v))
         }
     }
 }
 
 impl TryFrom<Value> for u8 {
-    type Error =&'static str;
+    type Error = ConversionError;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v {
-            Value::I64(iv) => Ok(iv as u8),
-            _ => Err(INVALID_VALUE_TYPE)
+            Value::I64(iv) => u8::try_from(iv).map_err(|_| out_of_range("u8", &v)),
+            _ => Err(mismatch("u8", &v))
         }
     }
 }
 
 impl TryFrom<Value> for i16 {
-    type Error =&'static str;
+    type Error = ConversionError;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v {
-            Value::I64(iv) => Ok(iv as i16),
-            _ => Err(INVALID_VALUE_TYPE)
+            Value::I64(iv) => i16::try_from(iv).map_err(|_| out_of_range("i16", &v)),
+            _ => Err(mismatch("i16", &v))
         }
     }
 }
 
 impl TryFrom<Value> for u16 {
-    type Error =&'static str;
+    type Error = ConversionError;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v {
-            Value::I64(iv) => Ok(iv as u16),
-            _ => Err(INVALID_VALUE_TYPE)
+            Value::I64(iv) => u16::try_from(iv).map_err(|_| out_of_range("u16", &v)),
+            _ => Err(mismatch("u16", &v))
         }
     }
 }
 
 impl TryFrom<Value> for i32 {
-    type Error =&'static str;
+    type Error = ConversionError;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v {
-            Value::I64(iv) => Ok(iv as i32),
-            _ => Err(INVALID_VALUE_TYPE)
+            Value::I64(iv) => i32::try_from(iv).map_err(|_| out_of_range("i32", &v)),
+            _ => Err(mismatch("i32", &v))
         }
     }
 }
 
 impl TryFrom<Value> for u32 {
-    type Error =&'static str;
+    type Error = ConversionError;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v {
-            Value::I64(iv) => Ok(iv as u32),
-            _ => Err(INVALID_VALUE_TYPE)
+            Value::I64(iv) => u32::try_from(iv).map_err(|_| out_of_range("u32", &v)),
+            _ => Err(mismatch("u32", &v))
         }
     }
 }
 
 impl TryFrom<Value> for i64 {
-    type Error = &'static str;
+    type Error = ConversionError;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v {
             Value::I64(iv) => Ok(iv),
-            _ => Err(INVALID_VALUE_TYPE)
+            _ => Err(mismatch("i64", &v))
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::I64(0) => Ok(false),
+            Value::I64(1) => Ok(true),
+            Value::I64(_) => Err(mismatch("bool", &v).reason("not 0 or 1")),
+            _ => Err(mismatch("bool", &v))
+        }
+    }
+}
+
+impl TryFrom<Value> for char {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        if let Value::Text(tv) = &v {
+            let mut chars = tv.chars();
+            return match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err(mismatch("char", &v).reason("not a single character"))
+            };
+        }
+        Err(mismatch("char", &v))
+    }
+}
+
+/// Wide integers read integers only, like `i64`. Text and blobs go through `AsText` and `AsBlob`.
+fn wide_integer<T: WideInteger>(v: &Value) -> Result<T, ConversionError> {
+    match v {
+        Value::I64(iv) => T::try_from(*iv).map_err(|_| out_of_range(T::NAME, v)),
+        _ => Err(mismatch(T::NAME, v))
+    }
+}
+
+impl TryFrom<Value> for u64 {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        wide_integer(&v)
+    }
+}
+
+impl TryFrom<Value> for usize {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        wide_integer(&v)
+    }
+}
+
+impl TryFrom<Value> for i128 {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        wide_integer(&v)
+    }
+}
+
+impl TryFrom<Value> for u128 {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        wide_integer(&v)
+    }
+}
+
+/// Decimal text, integers too as numeric columns store small numbers that way.
+impl<T: WideInteger> TryFrom<Value> for AsText<T> {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match &v {
+            Value::Text(tv) => tv.trim().parse().map(AsText).map_err(|_| mismatch(T::NAME, &v).reason("not an integer in range")),
+            _ => wide_integer(&v).map(AsText)
+        }
+    }
+}
+
+/// The blob of `WideInteger::to_blob`.
+impl<T: WideInteger> TryFrom<Value> for AsBlob<T> {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match &v {
+            Value::Blob(bv) if bv.len() != T::BLOB_LEN => Err(mismatch(T::NAME, &v).reason(&format!("not {} bytes", T::BLOB_LEN))),
+            Value::Blob(bv) => T::from_blob(bv).map(AsBlob).ok_or_else(|| out_of_range(T::NAME, &v)),
+            _ => Err(mismatch(T::NAME, &v))
         }
     }
 }
 
 impl TryFrom<Value> for f32 {
-    type Error = &'static str;
+    type Error = ConversionError;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v {
+            // precision is lost, magnitude must fit
+            Value::F64(fv) if fv.is_finite() && fv.abs() > f32::MAX as f64 => Err(out_of_range("f32", &v)),
             Value::F64(fv) => Ok(fv as f32),
-            _ => Err(INVALID_VALUE_TYPE)
+            _ => Err(mismatch("f32", &v))
         }
     }
 }
 
 impl TryFrom<Value> for f64 {
-    type Error = &'static str;
+    type Error = ConversionError;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v {
             Value::F64(fv) => Ok(fv),
-            _ => Err(INVALID_VALUE_TYPE)
+            _ => Err(mismatch("f64", &v))
         }
     }
 }
 
 impl TryFrom<Value> for String {
-    type Error = &'static str;
+    type Error = ConversionError;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v {
             Value::Text(tv) => Ok(tv),
-            _ => Err(INVALID_VALUE_TYPE)
+            _ => Err(mismatch("String", &v))
         }
     }
 }
 
 impl TryFrom<Value> for Vec<u8> {
-    type Error = &'static str;
+    type Error = ConversionError;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v {
             Value::Blob(bv) => Ok(bv),
-            _ => Err(INVALID_VALUE_TYPE)
+            _ => Err(mismatch("Vec<u8>", &v))
         }
     }
 }
 
 impl TryFrom<Value> for NaiveDate {
-    type Error = &'static str;
+    type Error = ConversionError;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
-        match v {
-            Value::Text(tv) => Ok(NaiveDate::parse_from_str(tv.as_str(), "%Y-%m-%d").unwrap()),
-            _ => Err(INVALID_VALUE_TYPE)
+        match TimeValue::parse(&v) {
+            Some(t) => Ok(t.date()),
+            None => Err(not_a_time("NaiveDate", &v))
         }
     }
 }
 
 impl TryFrom<Value> for DateTime<Local> {
-    type Error = &'static str;
+    type Error = ConversionError;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
-        match v {
-            Value::Text(tv) => {
-              let ndt = NaiveDateTime::parse_from_str(tv.as_str(), "%Y-%m-%d %H:%M:%S").unwrap();
-              let dt: DateTime<Local> = Local.from_local_datetime(&ndt).unwrap(); 
-              Ok(dt)
-            },
-            _ => Err(INVALID_VALUE_TYPE)
-        }
-    }   
+        match TimeValue::parse(&v) {
+            // ambiguous times take the earlier offset, times skipped by DST fail
+            Some(TimeValue::Naive(t)) => Local.from_local_datetime(&t)
+                .earliest()
+                .ok_or_else(|| mismatch("DateTime<Local>", &v).reason("no such local time")),
+            Some(TimeValue::Fixed(t)) => Ok(t.with_timezone(&Local)),
+            None => Err(not_a_time("DateTime<Local>", &v))
+        }
+    }
+}
+
+impl TryFrom<Value> for NaiveDateTime {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        NaiveDateTime::decode(&v, TimeFormat::Iso).ok_or_else(|| not_a_time(NaiveDateTime::NAME, &v))
+    }
+}
+
+impl TryFrom<Value> for NaiveTime {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        NaiveTime::decode(&v, TimeFormat::Iso).ok_or_else(|| not_a_time(NaiveTime::NAME, &v))
+    }
+}
+
+impl TryFrom<Value> for DateTime<Utc> {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        DateTime::<Utc>::decode(&v, TimeFormat::Iso).ok_or_else(|| not_a_time(DateTime::<Utc>::NAME, &v))
+    }
+}
+
+impl TryFrom<Value> for DateTime<FixedOffset> {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        DateTime::<FixedOffset>::decode(&v, TimeFormat::Iso).ok_or_else(|| not_a_time(DateTime::<FixedOffset>::NAME, &v))
+    }
+}
+
+impl TryFrom<Value> for Duration {
+    type Error = ConversionError;
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        Duration::decode(&v, TimeFormat::Iso).ok_or_else(|| not_a_time(Duration::NAME, &v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+    use crate::args::{Args, ValueConvertible, WideInteger};
+    use crate::db::SQLite;
+    use crate::query::Query;
+    use chrono::Timelike;
+
+    #[test]
+    fn range_checked_test() {
+        assert_eq!(Value::I64(127).get::<i8>(), Some(127));
+        assert_eq!(Value::I64(300).get::<u8>(), None);
+        assert_eq!(Value::I64(-1).get::<u32>(), None);
+        assert_eq!(Value::I64(u32::MAX as i64).get::<u32>(), Some(u32::MAX));
+        assert_eq!(Value::F64(1e300).get::<f32>(), None);
+        assert_eq!(Value::F64(f64::INFINITY).get::<f32>(), Some(f32::INFINITY));
+
+        let e = u8::try_from(Value::I64(300)).unwrap_err();
+        assert_eq!((e.expected.as_str(), e.actual.as_str(), e.preview.as_str()), ("u8", "I64", "300"));
+        assert_eq!(e.to_string(), "cannot convert I64 300 to u8: out of range");
+    }
+
+    #[test]
+    fn mismatch_test() {
+        let e = i64::try_from(Value::Null).unwrap_err();
+        assert_eq!(e.to_string(), "cannot convert Null to i64");
+
+        let e = i64::try_from(Value::from("a long text that gets cut in the preview")).unwrap_err();
+        assert_eq!(e.actual, "Text");
+        assert_eq!(e.preview, "'a long text that get…'");
+
+        let e = NaiveDate::try_from(Value::from("2024-02-30")).unwrap_err();
+        assert_eq!(e.reason.as_deref(), Some("not a date/time value"));
+        assert!(DateTime::<Local>::try_from(Value::from("yesterday")).is_err());
+        assert_eq!(Value::from("2024-02-29").get::<NaiveDate>(), NaiveDate::from_ymd_opt(2024, 2, 29));
+    }
+
+    #[test]
+    fn date_time_formats_test() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1);
+        assert_eq!(Value::from("2024-05-01T10:00:00Z").get::<NaiveDate>(), date);
+        assert_eq!(Value::from("2024-05-01 10:00:00.123").get::<NaiveDate>(), date);
+        assert_eq!(Value::I64(1714557600).get::<NaiveDate>(), date);
+        assert_eq!(Value::F64(2460431.5).get::<NaiveDate>(), date);
+
+        let utc = DateTime::parse_from_rfc3339("2024-05-01T10:00:00Z").unwrap();
+        for v in [Value::from("2024-05-01T10:00:00Z"), Value::from("2024-05-01 12:00+02:00"), Value::I64(1714557600)] {
+            assert_eq!(v.get::<DateTime<Local>>().unwrap(), utc);
+        }
+        let now = Local::now();
+        assert_eq!(Value::from(now).get::<DateTime<Local>>(), Some(now));
+        // without an offset the text is a local time, as older versions wrote it
+        let now = now.with_nanosecond(0).unwrap();
+        assert_eq!(Value::from(now.format("%Y-%m-%d %H:%M:%S").to_string()).get::<DateTime<Local>>(), Some(now));
+        assert!(Value::Blob(vec![1]).get::<DateTime<Local>>().is_none());
+    }
+
+    #[test]
+    fn bool_and_char_test() {
+        assert_eq!(true.try_to_value().unwrap(), Value::I64(1));
+        assert_eq!(Value::I64(0).get::<bool>(), Some(false));
+        assert_eq!(bool::try_from(Value::I64(2)).unwrap_err().reason.as_deref(), Some("not 0 or 1"));
+        assert_eq!('ż'.try_to_value().unwrap(), Value::from("ż"));
+        assert_eq!(Value::from("ż").get::<char>(), Some('ż'));
+        assert!(Value::from("ab").get::<char>().is_none());
+        assert!(Value::from("").get::<char>().is_none());
+    }
+
+    #[test]
+    fn wide_integer_test() {
+        assert_eq!(u64::MAX.try_to_value().unwrap_err().to_string(), "cannot convert integer 18446744073709551615 to i64: out of range for SQLite integers");
+        assert_eq!(7_usize.try_to_value().unwrap(), Value::I64(7));
+        assert_eq!(Value::I64(-1).get::<u64>(), None);
+
+        assert!(i128::MAX.try_to_value().is_err());
+
+        for v in [0, 1, u64::MAX] {
+            assert_eq!(AsText(v).try_to_value().unwrap().get::<AsText<u64>>(), Some(AsText(v)));
+            assert_eq!(AsBlob(v).try_to_value().unwrap().get::<AsBlob<u64>>(), Some(AsBlob(v)));
+        }
+        for v in [i128::MIN, -1, 0, 1, i128::MAX] {
+            assert_eq!(AsBlob(v).try_to_value().unwrap().get::<AsBlob<i128>>(), Some(AsBlob(v)));
+            assert_eq!(AsText(v).try_to_value().unwrap().get::<AsText<i128>>(), Some(AsText(v)));
+        }
+        assert_eq!(AsBlob(u128::MAX).try_to_value().unwrap().get::<AsBlob<u128>>(), Some(AsBlob(u128::MAX)));
+        assert_eq!((-5_i128).try_to_value().unwrap(), Value::I64(-5));
+        assert_eq!(Value::I64(-5).get::<i128>(), Some(-5));
+        assert_eq!(Value::I64(5).get::<AsText<u128>>(), Some(AsText(5)));
+        assert!(Value::Blob(vec![1, 2]).get::<AsBlob<u128>>().is_none());
+
+        // the plain types are strict like i64
+        assert!(AsText(u64::MAX).try_to_value().unwrap().get::<u64>().is_none());
+        assert!(AsBlob(7_u64).try_to_value().unwrap().get::<u64>().is_none());
+        assert!(AsBlob(7_i128).try_to_value().unwrap().get::<AsText<i128>>().is_none());
+        assert!(AsText(7_i128).try_to_value().unwrap().get::<AsBlob<i128>>().is_none());
+
+        // blobs sort like the numbers
+        let mut blobs = [5_i128, -3, i128::MIN, 0].map(|v| v.to_blob());
+        blobs.sort();
+        assert_eq!(blobs.map(|b| Value::Blob(b).get::<AsBlob<i128>>().unwrap().0), [i128::MIN, -3, 0, 5]);
+    }
+
+    #[test]
+    fn argument_error_test() {
+        let mut sq = test_database("CREATE TABLE t (n INTEGER, big BLOB)");
+        let e = sq.exec(Query::new("INSERT INTO t VALUES (?, ?)").arg(1).arg(u64::MAX)).unwrap_err();
+        assert_eq!(e.conversion().unwrap().context.as_deref(), Some("argument 2"));
+        assert_eq!(sq.select(Query::new("SELECT * FROM t")).unwrap().len(), 0);
+
+        sq.exec(Query::new("INSERT INTO t VALUES (?, ?)").arg(i64::MAX as u64).arg(AsBlob(u64::MAX))).unwrap();
+        let row = sq.select(Query::new("SELECT n, big FROM t")).unwrap().remove(0);
+        assert_eq!(row["n"].clone().get::<u64>(), Some(i64::MAX as u64));
+        assert_eq!(row["big"].clone().get::<AsBlob<u64>>(), Some(AsBlob(u64::MAX)));
+        assert!(Args::new().arg(u64::MAX).error().is_some());
+    }
 }